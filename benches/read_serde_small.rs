@@ -0,0 +1,62 @@
+//! Compares `read_serde` with `read_serde_small`, which reads payloads of up to `INLINE_MESSAGE_SIZE` bytes without
+//! allocating. Run with `cargo bench --features smallvec`.
+//!
+//! Messages are read from memory, so the numbers show the cost of reading and deserializing alone, without a socket.
+
+use ipc_util::SocketExt;
+use std::hint::black_box;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+const MESSAGES: usize = 10_000;
+const ROUNDS: usize = 50;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Request {
+    id: u64,
+    method: String,
+    args: Vec<u32>,
+}
+
+fn main() {
+    let request = Request {
+        id: 7,
+        method: "status".to_string(),
+        args: vec![1, 2, 3],
+    };
+
+    let mut stream = Cursor::new(Vec::new());
+    for _ in 0..MESSAGES {
+        stream.write_serde(&request).unwrap();
+    }
+    let bytes = stream.into_inner();
+
+    let plain = time(&bytes, |stream| stream.read_serde::<Request>().unwrap());
+    let small = time(&bytes, |stream| {
+        stream.read_serde_small::<Request>().unwrap()
+    });
+
+    println!("read_serde:       {:>6.1} ns/message", per_message(plain));
+    println!("read_serde_small: {:>6.1} ns/message", per_message(small));
+}
+
+/// Reads every message in the buffer with `read`, and returns the fastest of several rounds.
+fn time(bytes: &[u8], read: impl Fn(&mut Cursor<Vec<u8>>) -> Request) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut stream = Cursor::new(bytes.to_vec());
+            let start = Instant::now();
+
+            for _ in 0..MESSAGES {
+                black_box(read(&mut stream));
+            }
+
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn per_message(elapsed: Duration) -> f64 {
+    elapsed.as_nanos() as f64 / MESSAGES as f64
+}
@@ -20,7 +20,7 @@ pub enum Message {
     Pong,
 }
 
-fn run_server() {
+fn start_server() -> std::thread::JoinHandle<()> {
     start_ipc_server(
         get_ipc_name(),
         |message: Message| match message {
@@ -34,8 +34,10 @@ fn run_server() {
         Some(|e| panic!("Incoming connection error: {e}")),
     )
     .expect("Failed to start ipc listener")
-    .join()
-    .expect("Failed to join server thread");
+}
+
+fn run_server() {
+    start_server().join().expect("Failed to join server thread");
 }
 
 fn run_client() {
@@ -72,13 +74,8 @@ mod tests {
 
     #[test]
     fn test_ipc_communication() {
-        // Start server in a separate thread
-        std::thread::spawn(move || {
-            run_server();
-        });
-
-        // Wait for server to start
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        // The socket is bound by the time the server thread is started, so clients can connect right away
+        start_server();
 
         // Send message from client
         let text = Message::Text {
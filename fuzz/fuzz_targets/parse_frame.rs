@@ -0,0 +1,39 @@
+#![no_main]
+
+use ipc_util::{Checksum, Framing, LengthPrefix, SocketExt};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_MESSAGE_SIZE: u32 = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&options, bytes)) = data.split_first() else {
+        return;
+    };
+
+    // The first byte picks the framing, so every combination gets explored.
+    let mut framing = Framing::new();
+
+    if options & 1 != 0 {
+        framing = framing.length_prefix(LengthPrefix::BigEndian);
+    }
+    if options & 2 != 0 {
+        framing = framing.checksum(Checksum::Crc32);
+    }
+    if options & 4 != 0 {
+        framing = framing.versioned(options >> 4);
+    }
+    if options & 8 != 0 {
+        framing = framing.skip_empty_frames();
+    }
+
+    let framing = framing.max_message_size(MAX_MESSAGE_SIZE);
+
+    if let Ok(Some((payload, used))) = framing.parse_frame(bytes) {
+        assert!(used <= bytes.len());
+        assert!(payload.len() <= MAX_MESSAGE_SIZE as usize);
+    }
+
+    // The stream path must never panic either, and only reads up to the limit.
+    let mut stream = std::io::Cursor::new(bytes.to_vec());
+    let _ = stream.read_serde_framed::<(u32, String, Vec<u8>)>(&framing);
+});
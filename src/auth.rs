@@ -0,0 +1,229 @@
+use crate::{Framing, IpcClientError, IpcServerError};
+use hmac::{Hmac, Mac};
+use interprocess::local_socket::LocalSocketStream;
+use sha2::Sha256;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const CHALLENGE_LEN: usize = 32;
+const RESPONSE_LEN: usize = 32;
+
+const AUTH_OK: u8 = 1;
+const AUTH_FAILED: u8 = 0;
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A shared secret used to authenticate clients with a challenge-response handshake.
+///
+/// When set on a server with `IpcServerBuilder::auth`, every connection starts with the server sending a random challenge,
+/// which the client must answer with an HMAC-SHA256 of the challenge keyed with the secret. Connections that fail the
+/// handshake are closed before the connection callback is invoked. Clients connect with `ipc_client_connect_with_auth`.
+///
+/// This is only meant as a gate against casual unauthorized local connections. Messages are not encrypted or signed.
+#[derive(Clone)]
+pub struct AuthConfig {
+    secret: Vec<u8>,
+    handshake_timeout: Duration,
+}
+
+impl AuthConfig {
+    /// Creates an auth config from a shared secret.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
+    }
+
+    /// Sets how long the server waits for a client to complete the handshake before closing its connection.
+    ///
+    /// The handshake runs before the connection is handed off, so this keeps a client that connects and then stays
+    /// silent from holding up the server. Defaults to 5 seconds. Only supported on Unix, since named pipes don't
+    /// support timeouts.
+    pub fn handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
+    fn mac(&self, challenge: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(challenge);
+        mac
+    }
+}
+
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Performs the server side of the handshake, returning `IpcServerError::AuthFailed` if the client fails it for any
+/// reason, including not completing it within the handshake timeout.
+pub(crate) fn authenticate_client(
+    stream: &mut LocalSocketStream,
+    auth: &AuthConfig,
+) -> Result<(), IpcServerError> {
+    #[cfg(unix)]
+    {
+        let deadline = std::time::Instant::now() + auth.handshake_timeout;
+        let result = challenge_client(
+            &mut crate::utils::DeadlineStream::new(stream, deadline),
+            auth,
+        );
+
+        // Lift the timeouts the deadline left behind, so they don't cut off the connection once it's handled.
+        crate::utils::set_read_timeout(stream, None).map_err(|_| IpcServerError::AuthFailed)?;
+        crate::utils::set_write_timeout(stream, None).map_err(|_| IpcServerError::AuthFailed)?;
+
+        result
+    }
+
+    #[cfg(windows)]
+    challenge_client(stream, auth)
+}
+
+fn challenge_client<S: Read + Write>(
+    stream: &mut S,
+    auth: &AuthConfig,
+) -> Result<(), IpcServerError> {
+    let framing = Framing::default();
+
+    let mut challenge = [0; CHALLENGE_LEN];
+    getrandom::getrandom(&mut challenge).map_err(|_| IpcServerError::AuthFailed)?;
+
+    framing
+        .write_payload(stream, &challenge)
+        .map_err(|_| IpcServerError::AuthFailed)?;
+
+    let response = match framing.read_len(stream) {
+        Ok(len) if len as usize == RESPONSE_LEN => framing
+            .read_payload(stream, len)
+            .map_err(|_| IpcServerError::AuthFailed)?,
+        _ => return Err(IpcServerError::AuthFailed),
+    };
+
+    let verified = auth.mac(&challenge).verify_slice(&response).is_ok();
+    let status = if verified { AUTH_OK } else { AUTH_FAILED };
+
+    framing
+        .write_payload(stream, &[status])
+        .map_err(|_| IpcServerError::AuthFailed)?;
+
+    if verified {
+        Ok(())
+    } else {
+        Err(IpcServerError::AuthFailed)
+    }
+}
+
+/// Performs the client side of the handshake.
+pub(crate) fn authenticate_to_server(
+    stream: &mut LocalSocketStream,
+    auth: &AuthConfig,
+) -> Result<(), IpcClientError> {
+    let framing = Framing::default();
+
+    let len = framing.read_len(stream)?;
+    if len as usize != CHALLENGE_LEN {
+        return Err(IpcClientError::AuthFailed);
+    }
+
+    let challenge = framing.read_payload(stream, len)?;
+    let response = auth.mac(&challenge).finalize().into_bytes();
+
+    framing.write_payload(stream, &response)?;
+
+    let len = framing.read_len(stream)?;
+    if len != 1 {
+        return Err(IpcClientError::AuthFailed);
+    }
+
+    match framing.read_payload(stream, len)?[..] {
+        [AUTH_OK] => Ok(()),
+        _ => Err(IpcClientError::AuthFailed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SocketExt;
+    use crate::{ipc_client_connect_with_auth, AuthConfig, IpcClientError, IpcServerBuilder};
+
+    #[test]
+    fn test_auth_handshake() {
+        let socket = "/tmp/ipc-util-test-auth.sock";
+
+        IpcServerBuilder::new(socket)
+            .auth(AuthConfig::new("secret"))
+            .serve(|request: u32| Some(request + 1))
+            .expect("Failed to start server");
+
+        let mut stream = ipc_client_connect_with_auth(socket, &AuthConfig::new("secret"))
+            .expect("Failed to authenticate");
+        stream.write_serde(&1u32).unwrap();
+        assert_eq!(stream.read_serde::<u32>().unwrap(), 2);
+
+        let result = ipc_client_connect_with_auth(socket, &AuthConfig::new("wrong"));
+        assert!(matches!(result, Err(IpcClientError::AuthFailed)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_server_drops_silent_and_unauthenticated_clients() {
+        use crate::{IpcServerError, ServerMetrics};
+        use interprocess::local_socket::LocalSocketStream;
+        use std::io::Read;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        struct AuthFailures(Arc<AtomicUsize>);
+
+        impl ServerMetrics for AuthFailures {
+            fn on_auth_failed(&self, error: &IpcServerError) {
+                assert!(matches!(error, IpcServerError::AuthFailed));
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let socket = "/tmp/ipc-util-test-auth-timeout.sock";
+        let auth = AuthConfig::new("secret").handshake_timeout(Duration::from_millis(100));
+        let failures = Arc::new(AtomicUsize::new(0));
+
+        IpcServerBuilder::new(socket)
+            .auth(auth.clone())
+            .metrics(AuthFailures(failures.clone()))
+            .serve(|request: u32| Some(request + 1))
+            .expect("Failed to start server");
+
+        // The handshake runs on the accepting thread, so a client that never answers the challenge would
+        // hold up every client after it if the handshake weren't bounded.
+        let mut silent = LocalSocketStream::connect(socket).unwrap();
+        let started = Instant::now();
+
+        let mut stream =
+            ipc_client_connect_with_auth(socket, &auth).expect("Failed to authenticate");
+        stream.write_serde(&1u32).unwrap();
+        assert_eq!(stream.read_serde::<u32>().unwrap(), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Once the handshake times out, the silent client's connection is closed after the challenge.
+        let mut received = Vec::new();
+        silent.read_to_end(&mut received).unwrap();
+
+        let result = ipc_client_connect_with_auth(socket, &AuthConfig::new("wrong"));
+        assert!(matches!(result, Err(IpcClientError::AuthFailed)));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while failures.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(failures.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,3350 @@
+use crate::utils::{
+    current_process_instance_count, default_warning, is_process_running, lock_file_path,
+    pid_file_path,
+};
+use crate::{
+    is_namespaced, validate_name, ConnectionInfo, ErrorAction, Executor, Framing, IpcServerError,
+    IpcStreamError, IpcStreamReadError, IpcStreamWriteError, Query, RpcRouter, ServerMetrics,
+    ThreadPerConnection,
+};
+use fs2::FileExt;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(unix)]
+use std::os::unix::{
+    io::{FromRawFd, IntoRawFd, OwnedFd},
+    net::UnixListener,
+};
+
+/// How the server decides whether a socket that is already in use belongs to a running instance,
+/// or is a stale leftover that can be deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InstanceCheck {
+    /// Counts the running processes with the same executable name as the current process.
+    /// If there is more than one, the socket is considered in use.
+    ///
+    /// Without the `sysinfo` feature, processes can't be listed, so this never finds another instance by itself. Like
+    /// every instance check, it's still backed by trying to connect to the socket before deleting it, which finds
+    /// another instance as long as it's accepting connections.
+    #[default]
+    ProcessCount,
+    /// Holds an exclusive lock on a `<socket>.lock` file for as long as the server is running.
+    /// If the lock is already held by another process, the socket is considered in use.
+    ///
+    /// For namespaced sockets, the lock file is created in the system temporary directory.
+    LockFile,
+    /// Writes the id of the server process to a `<socket>.pid` file, which is removed when the server stops.
+    /// If the file already names a running process, the socket is considered in use, and binding fails with
+    /// `IpcServerError::AlreadyInUseError`. Otherwise the file is stale, and it is overwritten along with deleting
+    /// the stale socket.
+    ///
+    /// The file is locked while it's checked and written, and for as long as the server runs, so two servers starting
+    /// at the same moment can't both take it. Unlike `ProcessCount`, this also detects instances running under a
+    /// different executable name, and the file shows which process is running the server. The PID file is placed
+    /// like the lock file.
+    PidFile,
+}
+
+/// What the server does with a socket file that is already in use, once no running instance was found holding it.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum StaleSocketPolicy {
+    /// Deletes the stale socket file and binds again, reporting a warning through `on_warning`.
+    #[default]
+    AutoDelete,
+    /// Never deletes anything, and fails with `IpcServerError::BindError` holding the `AddrInUse` error instead.
+    Error,
+    /// Calls the function with the socket path, which returns `true` to delete the file and bind again,
+    /// or `false` to fail like `Error`.
+    Callback(fn(&str) -> bool),
+}
+
+/// How the server came to hold its socket, returned by `bind_ipc_listener_with_outcome` and
+/// `IpcAcceptor::bind_outcome`, and passed to the function set with `IpcServerBuilder::on_bind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindOutcome {
+    /// The socket was free, and was bound straight away.
+    FreshBind,
+    /// The socket was in use by a stale socket file, likely left behind by a server that crashed, which was deleted
+    /// before binding again.
+    RecoveredStale,
+}
+
+/// How a server rebinds its socket after its listener fails, set with `IpcServerBuilder::restart_on_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// The most times to try binding the socket again after the listener fails, before the server stops.
+    pub max_attempts: u32,
+    /// How long to wait before the first attempt. The wait doubles after each attempt that fails.
+    pub initial_backoff: Duration,
+    /// The longest to wait between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    /// Five attempts, waiting from 100 milliseconds up to 5 seconds between them.
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Proof that no other instance is using the socket, held for as long as the server runs.
+///
+/// The fields are never read, since they only need to be dropped when the server stops.
+#[allow(dead_code)]
+pub(crate) enum InstanceGuard {
+    LockFile(File),
+    PidFile(PidFile),
+}
+
+/// A PID file that is removed when dropped.
+pub(crate) struct PidFile {
+    path: PathBuf,
+    /// Keeps the file locked until it's removed, so no other server takes it in the meantime.
+    _file: File,
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A function invoked once with the name of the bound socket. See `IpcServerBuilder::on_ready`.
+type OnReady = Box<dyn FnOnce(&str) + Send>;
+
+/// A function invoked each time the socket is bound, with how it went. See `IpcServerBuilder::on_bind`.
+type OnBind = Box<dyn Fn(&str, BindOutcome) + Send>;
+
+/// How many connections `serve_multiplexed` handles at once by default.
+#[cfg(unix)]
+const DEFAULT_MAX_MULTIPLEXED_CONNECTIONS: usize = 64;
+
+/// Configures and spawns an IPC server thread.
+///
+/// `start_ipc_listener` and `start_ipc_server` are thin wrappers around this builder using its default
+/// configuration. Use the builder directly when you need to change how the socket is bound.
+///
+/// # Platform defaults
+///
+/// On Unix, path-based sockets are created with the permissions implied by the process umask, which
+/// typically allows other users to connect. Use `socket_mode` to restrict them.
+///
+/// On Windows, sockets are named pipes created with the default security descriptor, which grants
+/// full control to LocalSystem, administrators and the creator owner, and read access to everyone else.
+/// Processes running as other users may therefore still open the pipe. Use `restrict_to_current_user`
+/// to create it with a security descriptor that only grants access to the user the server runs as.
+pub struct IpcServerBuilder {
+    socket: String,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+    on_warning: fn(&str),
+    on_panic: Option<fn(Box<dyn Any + Send>)>,
+    instance_check: InstanceCheck,
+    skip_instance_check: bool,
+    stale_socket_policy: StaleSocketPolicy,
+    on_bind: Option<OnBind>,
+    framing: Framing,
+    thread_name: Option<String>,
+    stack_size: Option<usize>,
+    metrics: Option<Arc<dyn ServerMetrics>>,
+    on_raw_frame: Option<fn(&[u8])>,
+    executor: Option<Arc<dyn Executor>>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    acknowledge_messages: bool,
+    shutdown: Option<Receiver<()>>,
+    on_ready: Option<OnReady>,
+    restart_policy: Option<RestartPolicy>,
+    on_restart: Option<fn(u32, &io::Error)>,
+    max_connection_duration: Option<Duration>,
+    #[cfg(unix)]
+    max_multiplexed_connections: usize,
+    #[cfg(feature = "auth")]
+    auth: Option<crate::AuthConfig>,
+    #[cfg(unix)]
+    socket_mode: Option<u32>,
+    #[cfg(target_os = "linux")]
+    abstract_namespace: bool,
+    #[cfg(unix)]
+    namespace_fallback: Option<String>,
+    #[cfg(unix)]
+    inherited_listener: Option<OwnedFd>,
+    #[cfg(unix)]
+    idle_timeout: Option<Duration>,
+    #[cfg(windows)]
+    restrict_to_current_user: bool,
+    #[cfg(windows)]
+    message_mode: bool,
+}
+
+impl IpcServerBuilder {
+    /// Creates a builder for a server listening on the given socket name.
+    pub fn new(socket: impl Into<String>) -> Self {
+        Self {
+            socket: socket.into(),
+            on_connection_error: None,
+            on_warning: default_warning,
+            on_panic: None,
+            instance_check: InstanceCheck::default(),
+            skip_instance_check: false,
+            stale_socket_policy: StaleSocketPolicy::default(),
+            on_bind: None,
+            framing: Framing::default(),
+            thread_name: None,
+            stack_size: None,
+            metrics: None,
+            on_raw_frame: None,
+            executor: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            acknowledge_messages: false,
+            shutdown: None,
+            on_ready: None,
+            restart_policy: None,
+            on_restart: None,
+            max_connection_duration: None,
+            #[cfg(unix)]
+            max_multiplexed_connections: DEFAULT_MAX_MULTIPLEXED_CONNECTIONS,
+            #[cfg(feature = "auth")]
+            auth: None,
+            #[cfg(unix)]
+            socket_mode: None,
+            #[cfg(target_os = "linux")]
+            abstract_namespace: false,
+            #[cfg(unix)]
+            namespace_fallback: None,
+            #[cfg(unix)]
+            inherited_listener: None,
+            #[cfg(unix)]
+            idle_timeout: None,
+            #[cfg(windows)]
+            restrict_to_current_user: false,
+            #[cfg(windows)]
+            message_mode: false,
+        }
+    }
+
+    /// Sets a function that will be invoked if there is an error accepting a connection.
+    ///
+    /// The returned `ErrorAction` decides whether the server keeps accepting connections or stops.
+    /// When no function is set, the server always continues.
+    pub fn on_connection_error(
+        mut self,
+        on_connection_error: fn(io::Error) -> ErrorAction,
+    ) -> Self {
+        self.on_connection_error = Some(on_connection_error);
+        self
+    }
+
+    /// Sets a function that will be invoked with warnings that don't stop the server, such as a stale socket file being deleted.
+    ///
+    /// Defaults to printing the warning to stderr, or emitting a `tracing` warning with the `tracing` feature enabled.
+    /// Pass a no-op function to silence warnings.
+    pub fn on_warning(mut self, on_warning: fn(&str)) -> Self {
+        self.on_warning = on_warning;
+        self
+    }
+
+    /// Sets a function that will be invoked with the payload when handling a connection panics.
+    ///
+    /// Panics are always caught for each connection, so a buggy handler drops the connection it was handling rather than
+    /// taking down the server, which keeps accepting connections. The panic message is still printed by the panic hook.
+    /// With `listen_with_state`, the state is kept as the panicking callback left it.
+    pub fn on_panic(mut self, on_panic: fn(Box<dyn Any + Send>)) -> Self {
+        self.on_panic = Some(on_panic);
+        self
+    }
+
+    /// Sets how the server detects whether another instance is already using the socket.
+    ///
+    /// Defaults to `InstanceCheck::ProcessCount`.
+    pub fn instance_check(mut self, instance_check: InstanceCheck) -> Self {
+        self.instance_check = instance_check;
+        self
+    }
+
+    /// Skips the instance check entirely, so no processes are scanned and no lock or PID file is used.
+    ///
+    /// If the socket is already in use, the `stale_socket_policy` decides what happens straight away, which by default
+    /// deletes the file and binds again, even if a server is still accepting connections on it. Use this when the single-instance guard isn't needed, such as in tests,
+    /// to avoid the cost of scanning processes on bind.
+    pub fn skip_instance_check(mut self, skip_instance_check: bool) -> Self {
+        self.skip_instance_check = skip_instance_check;
+        self
+    }
+
+    /// Sets what the server does with a stale socket file left behind by a server that didn't shut down cleanly.
+    ///
+    /// The instance check runs first, so this only decides about files no running instance is using.
+    /// Defaults to `StaleSocketPolicy::AutoDelete`.
+    pub fn stale_socket_policy(mut self, stale_socket_policy: StaleSocketPolicy) -> Self {
+        self.stale_socket_policy = stale_socket_policy;
+        self
+    }
+
+    /// Sets a function that will be invoked each time the socket is bound, with the name that was bound and whether
+    /// a stale socket file had to be deleted first.
+    ///
+    /// Binding succeeds either way, so this is how a daemon can tell it's starting up after a predecessor that didn't
+    /// shut down cleanly, for example to log it or raise an alert. It's invoked for every way of binding, including
+    /// `bind_socket` and `incoming_async`, but not for an inherited listener, or when `can_bind` checks the socket.
+    /// To get the outcome of a single bind instead, use `IpcAcceptor::bind_outcome` or
+    /// `bind_ipc_listener_with_outcome`.
+    pub fn on_bind(mut self, on_bind: impl Fn(&str, BindOutcome) + Send + 'static) -> Self {
+        self.on_bind = Some(Box::new(on_bind));
+        self
+    }
+
+    /// Rebinds the socket and resumes accepting connections if the listener fails, instead of leaving a server that
+    /// can never be reached again.
+    ///
+    /// This kicks in when accepting fails in a way that means the listener itself is broken, rather than a single
+    /// connection, and on Unix also when the socket file is deleted or replaced while the server is running, which
+    /// is checked a few times a second while no clients are connecting. The server waits according to the policy,
+    /// then binds the socket again, keeping its lock or PID file. Once `max_attempts` attempts in a row have failed,
+    /// the server stops. A socket file that another process has bound in the meantime is never deleted.
+    ///
+    /// Without this, those errors go to `on_connection_error` like any other.
+    pub fn restart_on_error(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = Some(policy);
+        self
+    }
+
+    /// Sets a function that will be invoked before each attempt to rebind the socket, with the number of the
+    /// attempt, starting from 1, and the error that made it necessary. See `restart_on_error`.
+    pub fn on_restart(mut self, on_restart: fn(u32, &io::Error)) -> Self {
+        self.on_restart = Some(on_restart);
+        self
+    }
+
+    /// Sets the framing used by `serve` to read requests and write responses.
+    ///
+    /// Clients must use the same framing. Defaults to `Framing::default()`.
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Requires clients to authenticate with a shared secret before their connections are handled. See `AuthConfig`.
+    ///
+    /// Connections that fail the handshake, or don't complete it within `AuthConfig::handshake_timeout`, are closed.
+    /// `on_connection_error` is invoked with a `PermissionDenied` error, and the metrics hooks' `on_auth_failed` with
+    /// `IpcServerError::AuthFailed`.
+    #[cfg(feature = "auth")]
+    pub fn auth(mut self, auth: crate::AuthConfig) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the file permissions (e.g. `0o600`) applied to the socket file after binding.
+    ///
+    /// This has no effect on namespaced sockets, since they have no file to apply permissions to.
+    #[cfg(unix)]
+    pub fn socket_mode(mut self, mode: u32) -> Self {
+        self.socket_mode = Some(mode);
+        self
+    }
+
+    /// Binds the socket in the Linux abstract namespace, even if its name doesn't start with `@`.
+    ///
+    /// Abstract sockets have no file on disk and are released by the kernel as soon as the process holding them exits,
+    /// which avoids stale socket files entirely. Since there is no file, the stale socket deletion logic and `socket_mode`
+    /// don't apply, and a socket that is already in use always results in `IpcServerError::AlreadyInUseError`.
+    /// Clients must connect to the name with a leading `@`.
+    #[cfg(target_os = "linux")]
+    pub fn abstract_namespace(mut self, abstract_namespace: bool) -> Self {
+        self.abstract_namespace = abstract_namespace;
+        self
+    }
+
+    /// Binds to the given path-based socket instead if binding the namespaced socket isn't supported.
+    ///
+    /// Some sandboxed environments, such as locked-down containers, refuse to bind sockets in the abstract namespace.
+    /// With a fallback set, a namespaced bind that fails because it's unsupported or not permitted is retried with
+    /// the fallback path, and a warning is passed to `on_warning`. Use `on_ready` to find out which name was bound,
+    /// so clients can be pointed at it. Other bind errors, including the socket being in use, aren't retried.
+    #[cfg(unix)]
+    pub fn namespace_fallback(mut self, path: impl Into<String>) -> Self {
+        self.namespace_fallback = Some(path.into());
+        self
+    }
+
+    /// Accepts connections on an already-bound listening socket instead of binding a new one.
+    ///
+    /// This allows a supervisor or a previous instance of the server to hand off its listener across `exec`,
+    /// so the socket stays bound and pending clients aren't dropped during a restart. The socket must be a listening
+    /// Unix stream socket. Since nothing is bound, the stale socket handling, instance check and `socket_mode` don't apply.
+    ///
+    /// There is no Windows equivalent, since named pipe servers create a new pipe instance for each connection
+    /// rather than having a single listening handle that could be handed off.
+    #[cfg(unix)]
+    pub fn inherit_listener(mut self, fd: OwnedFd) -> Self {
+        self.inherited_listener = Some(fd);
+        self
+    }
+
+    /// Closes connections that don't send a complete message within the given duration.
+    ///
+    /// For typed servers, the whole request must arrive before the timeout elapses, so a client can't hold the server
+    /// by trickling in one byte at a time. For `listen`, the timeout is set as the read timeout of each accepted stream,
+    /// so reads that wait longer than the timeout fail with `WouldBlock`, and the callback decides what to do.
+    /// Handlers that keep reading from a typed connection get the same per-read timeout, which effectively resets
+    /// after each message.
+    ///
+    /// Named pipes don't support read timeouts, so this is only available on Unix.
+    #[cfg(unix)]
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Closes connections of typed servers once they have been open for the given duration, however active they are.
+    ///
+    /// Unlike `idle_timeout`, this bounds the whole lifetime of a connection, so a client can't hold it open
+    /// indefinitely with a steady trickle of messages. On Unix, the connection is shut down once the duration has
+    /// passed, with a warning passed to `on_warning`, which also cuts off a handler or `serve_iter` iterator that is
+    /// blocked, and for `serve_multiplexed` every request still in flight. Named pipes can't be shut down from another
+    /// thread, so on Windows `serve_iter` only checks the elapsed time before writing each response, dropping the rest
+    /// of the iterator once it's exceeded.
+    ///
+    /// This doesn't apply to `listen` and its variants, which hand the connection over to the callback to keep for as
+    /// long as it likes.
+    pub fn max_connection_duration(mut self, duration: Duration) -> Self {
+        self.max_connection_duration = Some(duration);
+        self
+    }
+
+    /// Creates the named pipe with a security descriptor that only lets the user the server runs as open it.
+    ///
+    /// The pipe's DACL grants full control to the current user's SID and nothing to anyone else, so processes running
+    /// as other users fail to connect with `PermissionDenied`. As an extra check, the user of the peer process is
+    /// also looked up right after each connection is accepted, and connections from other users are closed without
+    /// invoking the connection callback. This is the Windows counterpart to restricting a Unix socket with
+    /// `socket_mode(0o600)`.
+    #[cfg(windows)]
+    pub fn restrict_to_current_user(mut self, restrict: bool) -> Self {
+        self.restrict_to_current_user = restrict;
+        self
+    }
+
+    /// Binds a message-mode named pipe instead of the byte-mode pipe `interprocess` creates, for interop with
+    /// Windows clients and services that expect message boundaries to be preserved.
+    ///
+    /// Each write to a message-mode pipe, from either end, is delivered as its own message. Connections are still
+    /// read as a byte stream, so the length-prefixed framing works the same either way, and clients connecting with
+    /// `send_ipc_query` or `ipc_client_connect` need no changes, since the pipe's mode is chosen by the server.
+    #[cfg(windows)]
+    pub fn message_mode(mut self, message_mode: bool) -> Self {
+        self.message_mode = message_mode;
+        self
+    }
+
+    /// Sets the size of the receive buffer of each accepted connection, in bytes.
+    ///
+    /// On Unix, this sets `SO_RCVBUF` on each connection before it's handled, which the kernel may round or double.
+    /// On Windows, pipe buffer sizes are only hints, fixed when each pipe instance is created, so this is passed when
+    /// binding with `message_mode` or `restrict_to_current_user`, and otherwise `interprocess` creates its pipes with its own defaults.
+    /// Local sockets never batch small writes like TCP's Nagle algorithm does, so there is nothing like `TCP_NODELAY`
+    /// to disable.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the send buffer of each accepted connection, in bytes, with `SO_SNDBUF` on Unix.
+    /// See `recv_buffer_size`.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the name of the server thread. Defaults to `ipc server '<socket>'`.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Sets the stack size of the server thread, in bytes. Defaults to the standard library's default.
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Sets the metrics hooks invoked as connections are handled. See `ServerMetrics`.
+    pub fn metrics(mut self, metrics: impl ServerMetrics + 'static) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Sets a function that will be invoked with the raw payload of each request that typed servers read,
+    /// right before it is deserialized.
+    ///
+    /// This is meant for diagnosing wire issues, such as dumping the bytes of a frame that fails to deserialize.
+    /// Methods that pass the stream straight to the callback, like `listen`, don't read requests, so it isn't invoked.
+    pub fn on_raw_frame(mut self, on_raw_frame: fn(&[u8])) -> Self {
+        self.on_raw_frame = Some(on_raw_frame);
+        self
+    }
+
+    /// Sets the executor that `listen_concurrent` and `serve_concurrent` run each connection on. See `Executor`.
+    ///
+    /// Defaults to `ThreadPerConnection`. The other methods handle connections on the server thread and ignore this.
+    pub fn executor(mut self, executor: impl Executor + 'static) -> Self {
+        self.executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// Sets how many connections `serve_multiplexed` handles at once, each of which has a thread of its own reading its
+    /// requests.
+    ///
+    /// Connections beyond the limit are closed as soon as they're accepted, and `on_connection_error` is invoked with
+    /// an `ErrorKind::ConnectionRefused` error. Defaults to 64.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    #[cfg(unix)]
+    pub fn max_multiplexed_connections(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_multiplexed_connections must be at least 1");
+        self.max_multiplexed_connections = max;
+        self
+    }
+
+    /// Makes typed servers write an empty acknowledgement frame after the handler returns without a response.
+    ///
+    /// This pairs with `send_ipc_message_acked`, which waits for the acknowledgement to know the message was handled.
+    /// Clients using plain `send_ipc_message` still work, since they close the connection without reading it.
+    pub fn acknowledge_messages(mut self, acknowledge: bool) -> Self {
+        self.acknowledge_messages = acknowledge;
+        self
+    }
+
+    /// Stops the server once a message is received on the given channel.
+    ///
+    /// A background thread waits on the channel and then connects to the server itself to unblock the accept loop,
+    /// which finishes without passing that connection to the callback. Any other connection accepted after the signal
+    /// is closed without being handled as well. Dropping the sender without sending doesn't stop the server.
+    ///
+    /// For an inherited listener, the server can only connect to itself if the socket is bound to a name. Otherwise,
+    /// the server stops when the next client connects.
+    pub fn shutdown_on(mut self, receiver: Receiver<()>) -> Self {
+        self.shutdown = Some(receiver);
+        self
+    }
+
+    /// Sets a function that will be invoked once the socket is bound, with the name clients can connect to.
+    ///
+    /// It runs before the first connection is accepted, so clients connecting after it is invoked won't be refused.
+    /// This is mainly useful with `listen_blocking` and `serve_blocking`, which don't return until the server stops,
+    /// for example to signal readiness to a supervisor or to a test waiting on a channel. The other methods only
+    /// return after the socket is bound, so clients can connect as soon as they return.
+    pub fn on_ready(mut self, on_ready: impl FnOnce(&str) + Send + 'static) -> Self {
+        self.on_ready = Some(Box::new(on_ready));
+        self
+    }
+
+    /// Binds the socket and spawns a thread that invokes `on_connection` for each incoming connection.
+    ///
+    /// The socket is bound before this returns, so clients can connect right away.
+    /// See `start_ipc_listener` for details on how stale sockets are handled.
+    pub fn listen<F: Fn(LocalSocketStream) + Send + 'static>(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        self.listen_until(move |stream| {
+            on_connection(stream);
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Like `listen`, but `on_connection` also receives mutable access to a state value owned by the server.
+    ///
+    /// The state is moved to the server thread and shared by every connection, which avoids wrapping it in
+    /// `Arc<Mutex<_>>` to capture it in the callback. Since connections are handled one at a time, the callback
+    /// can mutate it freely. It is dropped when the server stops.
+    pub fn listen_with_state<S, F>(
+        self,
+        state: S,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError>
+    where
+        S: Send + 'static,
+        F: Fn(&mut S, LocalSocketStream) + Send + 'static,
+    {
+        // The mutex is never contended, since the accept loop is the only thing that locks it.
+        let state = std::sync::Mutex::new(state);
+
+        self.listen(move |stream| {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            on_connection(&mut state, stream);
+        })
+    }
+
+    /// Like `listen`, but `on_connection` also receives a `ConnectionInfo` with the connection's id, when it was
+    /// accepted, and what could be found out about the peer.
+    ///
+    /// Ids count the connections passed to `on_connection`, so connections dropped before reaching it, such as those
+    /// that fail authentication, don't use one up.
+    pub fn listen_with_info<F: Fn(LocalSocketStream, &ConnectionInfo) + Send + 'static>(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let next_id = Cell::new(0);
+
+        self.listen(move |stream| {
+            let info = ConnectionInfo::new(&stream, next_id.replace(next_id.get() + 1));
+            on_connection(stream, &info);
+        })
+    }
+
+    /// Like `listen`, but hands each connection to the executor, so several connections can be handled at once.
+    ///
+    /// The accept loop runs on the server thread and dispatches a job per connection with `Executor::execute`.
+    /// Panics in the callback are caught within the job and passed to `on_panic`, like with `listen`.
+    pub fn listen_concurrent<F: Fn(LocalSocketStream) + Send + Sync + 'static>(
+        mut self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let executor = self
+            .executor
+            .take()
+            .unwrap_or_else(|| Arc::new(ThreadPerConnection));
+
+        // The jobs report their own connections, since the accept loop would report them closed as soon as they're dispatched.
+        let metrics = self.metrics.take();
+        let on_panic = self.on_panic;
+        let on_connection = Arc::new(on_connection);
+
+        self.listen(move |stream| {
+            let metrics = metrics.clone();
+            let on_connection = on_connection.clone();
+
+            executor.execute(Box::new(move || {
+                if let Some(metrics) = &metrics {
+                    metrics.on_connection_opened();
+                }
+
+                if let Err(payload) =
+                    panic::catch_unwind(AssertUnwindSafe(|| on_connection(stream)))
+                {
+                    if let Some(on_panic) = on_panic {
+                        on_panic(payload);
+                    }
+                }
+
+                if let Some(metrics) = &metrics {
+                    metrics.on_connection_closed();
+                }
+            }));
+        })
+    }
+
+    /// Like `serve`, but hands each connection to the executor, so several requests can be handled at once.
+    ///
+    /// See `listen_concurrent`.
+    pub fn serve_concurrent<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse> + Send + Sync + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let config = self.typed_config();
+
+        self.listen_concurrent(move |stream| {
+            let _ = handle_typed_connection(stream, &config, &|request, _| {
+                ControlFlow::Continue(on_connection(request))
+            });
+        })
+    }
+
+    /// Like `serve_concurrent`, but each connection can carry many requests at once, whose responses are written back
+    /// as they finish rather than in the order the requests arrived.
+    ///
+    /// Each request payload starts with a `u64` request id in little-endian, chosen by the client, followed by the
+    /// serialized request. Each response payload starts with the id of the request it answers, followed by the
+    /// serialized response. The requests of a connection are read on a thread of its own and handed to the executor
+    /// one by one, so a slow request doesn't hold up the ones behind it, and responses are written through a shared
+    /// writer behind a mutex, so their frames never interleave. Returning `None` writes nothing back, for requests that
+    /// don't expect a response. A magic header, if the framing has one, is only expected at the start of the connection.
+    ///
+    /// Errors reading or handling a request are reported to the metrics hooks' `on_error`. A request without an id,
+    /// or that fails to be read, closes the connection. A request that fails to be deserialized is answered with a
+    /// payload holding nothing but its id, so the client can fail that request rather than wait forever for a
+    /// response, while the others on the connection carry on. At most `max_multiplexed_connections` connections are
+    /// handled at once.
+    ///
+    /// Named pipes opened for synchronous I/O can't be read and written at the same time, so this is only available on Unix.
+    #[cfg(unix)]
+    pub fn serve_multiplexed<TRequest, TResponse, F>(
+        mut self,
+        on_request: F,
+    ) -> Result<JoinHandle<()>, IpcServerError>
+    where
+        TRequest: DeserializeOwned + 'static,
+        TResponse: Serialize + 'static,
+        F: Fn(TRequest) -> Option<TResponse> + Send + Sync + 'static,
+    {
+        let executor = self
+            .executor
+            .take()
+            .unwrap_or_else(|| Arc::new(ThreadPerConnection));
+
+        // The connection threads report their own connections, since the accept loop would report them closed right away.
+        let config = Arc::new(self.typed_config());
+        let metrics = self.metrics.take();
+        let on_panic = self.on_panic;
+        let on_connection_error = self.on_connection_error;
+        let on_request = Arc::new(on_request);
+        let max_connections = self.max_multiplexed_connections;
+        let open_connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        self.listen_until(move |stream| {
+            let Some(slot) = ConnectionSlot::acquire(&open_connections, max_connections) else {
+                let error = io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("Refusing connection, since {max_connections} multiplexed connections are open"),
+                );
+
+                return match on_connection_error.map(|f| f(error)) {
+                    Some(ErrorAction::Stop) => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                };
+            };
+
+            let executor = executor.clone();
+            let config = config.clone();
+            let metrics = metrics.clone();
+            let on_request = on_request.clone();
+
+            // If the thread can't be spawned, the connection and its slot are dropped along with the closure.
+            let _ = std::thread::Builder::new()
+                .name("ipc multiplexed connection".to_string())
+                .spawn(move || {
+                    let _slot = slot;
+
+                    if let Some(metrics) = &metrics {
+                        metrics.on_connection_opened();
+                    }
+
+                    let result = read_multiplexed_requests(
+                        stream,
+                        &config,
+                        executor.as_ref(),
+                        &on_request,
+                        on_panic,
+                    );
+
+                    if let (Err(e), Some(metrics)) = (result, &metrics) {
+                        metrics.on_error(&e);
+                    }
+
+                    if let Some(metrics) = &metrics {
+                        metrics.on_connection_closed();
+                    }
+                });
+
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Like `listen`, but the accept loop stops once `on_connection` returns `ControlFlow::Break`.
+    fn listen_until<F: Fn(LocalSocketStream) -> ControlFlow<()> + Send + 'static>(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let name = self
+            .thread_name
+            .clone()
+            .unwrap_or_else(|| format!("ipc server '{}'", self.socket));
+
+        let mut thread = std::thread::Builder::new().name(name);
+
+        if let Some(stack_size) = self.stack_size {
+            thread = thread.stack_size(stack_size);
+        }
+
+        let server = self.bind()?;
+
+        thread
+            .spawn(move || server.run(on_connection))
+            .map_err(IpcServerError::ThreadSpawnError)
+    }
+
+    /// Binds the socket and runs the accept loop on the current thread, invoking `on_connection` for each incoming connection.
+    ///
+    /// This blocks for as long as the server runs, which suits single-purpose daemons that have nothing else to do.
+    /// The thread name and stack size options are ignored.
+    pub fn listen_blocking<F: Fn(LocalSocketStream)>(
+        self,
+        on_connection: F,
+    ) -> Result<(), IpcServerError> {
+        self.bind()?.run(|stream| {
+            on_connection(stream);
+            ControlFlow::Continue(())
+        });
+
+        Ok(())
+    }
+
+    /// Binds the socket and spawns a thread that reads a deserializable request from each incoming connection,
+    /// then optionally responds with a serializable object.
+    ///
+    /// See `start_ipc_server` for details.
+    pub fn serve<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        self.serve_with_stream(move |request, _: &mut LocalSocketStream| on_connection(request))
+    }
+
+    /// Like `serve`, but the handler answers each query with the response type paired with it by its `Query`
+    /// implementation. Since every query expects a response, the handler always returns one.
+    pub fn serve_query<Q, F>(self, on_query: F) -> Result<JoinHandle<()>, IpcServerError>
+    where
+        Q: Query + DeserializeOwned,
+        Q::Response: Serialize,
+        F: Fn(Q) -> Q::Response + Send + 'static,
+    {
+        self.serve(move |query: Q| Some(on_query(query)))
+    }
+
+    /// Like `serve`, but takes an `FnMut` handler, which can keep mutable state such as a counter or a cache without
+    /// wrapping it in `Arc<Mutex<_>>`.
+    ///
+    /// The handler runs on the server thread, one request at a time, so it can never be called while it's already
+    /// running. For the same reason, it can't be used with `serve_concurrent` or an `Executor`, which need a handler
+    /// that several connections can call at once. Like `listen_with_state`, the handler is dropped when the server stops.
+    pub fn serve_mut<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: FnMut(TRequest) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        // The accept loop is the only thing that calls the handler, so it's never borrowed twice.
+        let on_connection = RefCell::new(on_connection);
+
+        self.serve(move |request| (on_connection.borrow_mut())(request))
+    }
+
+    /// Like `serve`, but the handler also receives a `ConnectionInfo` describing the connection the request came from.
+    ///
+    /// The info is gathered as soon as the connection is accepted, before the request is read. See `listen_with_info`.
+    pub fn serve_with_info<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest, &ConnectionInfo) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let config = self.typed_config();
+        let next_id = Cell::new(0);
+
+        self.listen_until(move |stream| {
+            let info = ConnectionInfo::new(&stream, next_id.replace(next_id.get() + 1));
+
+            handle_typed_connection(stream, &config, &|request, _| {
+                ControlFlow::Continue(on_connection(request, &info))
+            })
+        })
+    }
+
+    /// Like `serve`, but the handler also receives a mutable reference to the connection's stream.
+    ///
+    /// This allows the handler to write several messages, or to take over the connection with its own protocol,
+    /// after the initial request has been read. If the handler returns a response, it is written after the handler returns.
+    pub fn serve_with_stream<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest, &mut LocalSocketStream) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        self.serve_with_control(move |request, stream| {
+            ControlFlow::Continue(on_connection(request, stream))
+        })
+    }
+
+    /// Like `serve_with_stream`, but the handler can also stop the server.
+    ///
+    /// Returning `ControlFlow::Continue` behaves like returning the inner `Option` from `serve_with_stream`.
+    /// Returning `ControlFlow::Break` writes the response if there is one, then finishes the accept loop,
+    /// so the thread exits cleanly and the returned `JoinHandle` can be joined. Connections that fail to be
+    /// read or deserialized never stop the server.
+    pub fn serve_with_control<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(
+                TRequest,
+                &mut LocalSocketStream,
+            ) -> ControlFlow<Option<TResponse>, Option<TResponse>>
+            + Send
+            + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let config = self.typed_config();
+
+        self.listen_until(move |stream| handle_typed_connection(stream, &config, &on_connection))
+    }
+
+    /// Like `serve`, but the handler returns an iterator of responses, which are written to the client one at a time
+    /// before the connection is closed. Clients read them with `send_ipc_query_stream`.
+    ///
+    /// The iterator is consumed lazily, so each response, such as a page of results, is only produced once the
+    /// previous one has been written. If a response fails to be written, such as when the client disconnects early,
+    /// the rest of the iterator is dropped and the error is reported to the metrics hooks.
+    pub fn serve_iter<TRequest, TResponse, I, F>(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError>
+    where
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        I: IntoIterator<Item = TResponse>,
+        F: Fn(TRequest) -> I + Send + 'static,
+    {
+        let config = self.typed_config();
+
+        self.listen_until(move |mut stream| {
+            if let Err(e) = respond_with_iter(&mut stream, &config, &on_connection) {
+                if let Some(metrics) = &config.metrics {
+                    metrics.on_error(&e);
+                }
+            }
+
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Like `serve`, but gives up on the handler if it doesn't return within the timeout.
+    ///
+    /// Each request is handled on a new thread while the server waits for the response. If the timeout elapses first,
+    /// the connection is dropped without a response, and `on_connection_error` is invoked with an `ErrorKind::TimedOut`
+    /// error, whose `ErrorAction` decides whether the server keeps running. A handler that panics is reported the same way,
+    /// with an `ErrorKind::Other` error.
+    ///
+    /// The handler isn't cancelled when it times out, since threads can't be stopped from the outside. It is abandoned
+    /// and keeps running in the background, so a handler that hangs forever still leaks its thread.
+    pub fn serve_with_handler_timeout<
+        TRequest: DeserializeOwned + 'static,
+        TResponse: Serialize + 'static,
+        F: Fn(TRequest) -> Option<TResponse> + Send + Sync + 'static,
+    >(
+        self,
+        timeout: Duration,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        let config = self.typed_config();
+        let on_connection_error = self.on_connection_error;
+        let on_connection = Arc::new(on_connection);
+
+        self.listen_until(move |mut stream| {
+            match respond_within(&mut stream, &config, &on_connection, timeout) {
+                Ok(None) => ControlFlow::Continue(()),
+                Ok(Some(error)) => match on_connection_error.map(|f| f(error)) {
+                    Some(ErrorAction::Stop) => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                },
+                Err(e) => {
+                    if let Some(metrics) = &config.metrics {
+                        metrics.on_error(&e);
+                    }
+
+                    ControlFlow::Continue(())
+                }
+            }
+        })
+    }
+
+    /// Like `serve`, but dispatches each request to one of several typed handlers by its type tag. See `RpcRouter`.
+    ///
+    /// Requests with a tag that has no handler, and requests that fail to be read or deserialized, are reported
+    /// to the metrics hooks' `on_error`, and the connection is closed without a response.
+    pub fn serve_router(self, router: RpcRouter) -> Result<JoinHandle<()>, IpcServerError> {
+        let config = self.typed_config();
+
+        self.listen(move |mut stream| {
+            if let Err(e) = route_request(&mut stream, &config, &router) {
+                if let Some(metrics) = &config.metrics {
+                    metrics.on_error(&e);
+                }
+            }
+        })
+    }
+
+    /// Like `serve`, but stops after handling `n` connections, then removes the socket file.
+    ///
+    /// This suits handing a single request off to a short-lived helper, such as receiving a callback and exiting.
+    /// Every accepted connection counts towards `n`, including ones that fail to be read or deserialized, so the
+    /// server never handles more than `n` connections. The returned `JoinHandle` can be joined to wait for them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn serve_n<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        n: usize,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        assert!(n > 0, "the server must handle at least one connection");
+
+        let config = self.typed_config();
+        let handled = std::cell::Cell::new(0);
+
+        #[cfg(unix)]
+        let socket_file = self.socket_file();
+
+        self.listen_until(move |stream| {
+            let flow = handle_typed_connection(stream, &config, &|request, _| {
+                ControlFlow::Continue(on_connection(request))
+            });
+
+            handled.set(handled.get() + 1);
+
+            if flow.is_continue() && handled.get() < n {
+                return ControlFlow::Continue(());
+            }
+
+            #[cfg(unix)]
+            if let Some(socket_file) = &socket_file {
+                let _ = std::fs::remove_file(socket_file);
+            }
+
+            ControlFlow::Break(())
+        })
+    }
+
+    /// Like `serve`, but stops after handling a single connection. See `serve_n`.
+    pub fn serve_once<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<JoinHandle<()>, IpcServerError> {
+        self.serve_n(1, on_connection)
+    }
+
+    /// Like `serve`, but runs the accept loop on the current thread. See `listen_blocking`.
+    pub fn serve_blocking<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse>,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<(), IpcServerError> {
+        let config = self.typed_config();
+
+        self.bind()?.run(move |stream| {
+            handle_typed_connection(stream, &config, &|request, _: &mut LocalSocketStream| {
+                ControlFlow::Continue(on_connection(request))
+            })
+        });
+
+        Ok(())
+    }
+
+    /// Like `serve_blocking`, but returns once Ctrl-C is pressed, which is SIGINT on Unix.
+    ///
+    /// The connection being handled when the signal arrives is allowed to finish, then the socket file is removed.
+    /// This installs a process-wide handler with the `ctrlc` crate, which can only be done once per process,
+    /// so it fails with `IpcServerError::SignalHandlerError` if a handler was already installed.
+    #[cfg(feature = "ctrlc")]
+    pub fn serve_until_ctrl_c<
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse>,
+    >(
+        self,
+        on_connection: F,
+    ) -> Result<(), IpcServerError> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        ctrlc::set_handler(move || {
+            let _ = sender.send(());
+        })
+        .map_err(IpcServerError::SignalHandlerError)?;
+
+        #[cfg(unix)]
+        let socket_file = self.socket_file();
+
+        self.shutdown_on(receiver).serve_blocking(on_connection)?;
+
+        #[cfg(unix)]
+        if let Some(socket_file) = socket_file {
+            let _ = std::fs::remove_file(socket_file);
+        }
+
+        Ok(())
+    }
+
+    /// Binds the socket and returns an async stream of the incoming connections. See `incoming_async`.
+    #[cfg(feature = "tokio")]
+    pub fn incoming_async(self) -> Result<crate::IncomingAsync, IpcServerError> {
+        let bound = self.bind_socket()?;
+        crate::IncomingAsync::spawn(bound.listener, bound.instance_guard, bound.name)
+    }
+
+    /// Gets the path of the socket file to remove once the server stops, if the server creates one.
+    #[cfg(unix)]
+    pub(crate) fn socket_file(&self) -> Option<String> {
+        match self.inherited_listener {
+            Some(_) => None,
+            None => Some(self.socket_name()).filter(|socket| !is_namespaced(socket)),
+        }
+    }
+
+    /// Binds the socket and returns an `IpcAcceptor` to poll for connections, instead of accepting them on a thread.
+    ///
+    /// This is the building block for integrating the server into an event loop that isn't based on threads.
+    pub fn bind_acceptor(self) -> Result<IpcAcceptor, IpcServerError> {
+        let name = self.socket_name();
+        let mut server = self.bind()?;
+
+        server
+            .listener
+            .set_nonblocking(true)
+            .map_err(IpcServerError::bind(&name))?;
+
+        if let Some(restart) = &mut server.restart {
+            restart.nonblocking = true;
+        }
+
+        Ok(IpcAcceptor {
+            server,
+            stopped: false,
+        })
+    }
+
+    fn typed_config(&self) -> TypedConfig {
+        TypedConfig {
+            framing: self.framing,
+            metrics: self.metrics.clone(),
+            on_raw_frame: self.on_raw_frame,
+            acknowledge_messages: self.acknowledge_messages,
+            max_connection_duration: self.max_connection_duration,
+            on_warning: self.on_warning,
+            #[cfg(unix)]
+            idle_timeout: self.idle_timeout,
+        }
+    }
+
+    /// Gets the name to bind to, which has an `@` prefix if binding in the abstract namespace was requested.
+    pub(crate) fn socket_name(&self) -> String {
+        #[cfg(target_os = "linux")]
+        if self.abstract_namespace && !self.socket.starts_with('@') {
+            return format!("@{}", self.socket);
+        }
+
+        self.socket.clone()
+    }
+
+    /// Binds the socket, or takes the inherited listener if there is one, along with the rest of the server configuration.
+    pub(crate) fn bind(mut self) -> Result<BoundServer, IpcServerError> {
+        #[cfg(unix)]
+        let bound = match self.inherited_listener.take() {
+            Some(fd) => {
+                let listener = Listener::Unix(UnixListener::from(fd));
+                let name = listener
+                    .connect_name()
+                    .unwrap_or_else(|| self.socket_name());
+                (listener, None, name, None)
+            }
+            // A socket file is bound with a `UnixListener`, so it can be watched for being deleted.
+            None if self.restart_policy.is_some() => {
+                let bound = self.bind_socket_with(bind_watchable)?;
+                (
+                    bound.listener,
+                    bound.instance_guard,
+                    bound.name,
+                    Some(bound.outcome),
+                )
+            }
+            None => {
+                let bound = self.bind_socket()?;
+                let listener = Listener::Local(bound.listener);
+                (
+                    listener,
+                    bound.instance_guard,
+                    bound.name,
+                    Some(bound.outcome),
+                )
+            }
+        };
+
+        #[cfg(windows)]
+        let bound = if self.message_mode || self.restrict_to_current_user {
+            let options = self.pipe_options();
+            let bound =
+                self.bind_socket_with(|socket| crate::utils::PipeListener::bind(socket, options))?;
+            let listener = Listener::Pipe(bound.listener);
+            (
+                listener,
+                bound.instance_guard,
+                bound.name,
+                Some(bound.outcome),
+            )
+        } else {
+            let bound = self.bind_socket()?;
+            let listener = Listener::Local(bound.listener);
+            (
+                listener,
+                bound.instance_guard,
+                bound.name,
+                Some(bound.outcome),
+            )
+        };
+
+        let (listener, instance_guard, name, bind_outcome) = bound;
+
+        let shutdown_requested = match self.shutdown.take() {
+            Some(receiver) => Some(watch_for_shutdown(receiver, name.clone())?),
+            None => None,
+        };
+
+        if let Some(on_ready) = self.on_ready.take() {
+            on_ready(&name);
+        }
+
+        let restart = self.restart_policy.map(|policy| Restart {
+            policy,
+            on_restart: self.on_restart,
+            nonblocking: false,
+            #[cfg(unix)]
+            socket_mode: self.socket_mode,
+            #[cfg(unix)]
+            socket_file: crate::utils::file_id(&name).ok(),
+            #[cfg(windows)]
+            pipe: (self.message_mode || self.restrict_to_current_user).then(|| self.pipe_options()),
+        });
+
+        Ok(BoundServer {
+            listener,
+            name,
+            bind_outcome,
+            restart,
+            _instance_guard: instance_guard,
+            shutdown_requested,
+            on_connection_error: self.on_connection_error,
+            on_panic: self.on_panic,
+            metrics: self.metrics,
+            #[cfg(feature = "auth")]
+            auth: self.auth,
+            #[cfg(unix)]
+            idle_timeout: self.idle_timeout,
+            #[cfg(unix)]
+            recv_buffer_size: self.recv_buffer_size,
+            #[cfg(unix)]
+            send_buffer_size: self.send_buffer_size,
+            #[cfg(windows)]
+            restrict_to_current_user: self.restrict_to_current_user,
+        })
+    }
+
+    /// Gets how to create the instances of a named pipe, when `interprocess` can't create them.
+    #[cfg(windows)]
+    fn pipe_options(&self) -> crate::utils::PipeOptions {
+        crate::utils::PipeOptions {
+            message_mode: self.message_mode,
+            restrict_to_current_user: self.restrict_to_current_user,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+        }
+    }
+
+    /// Binds the socket, recovering from stale sockets, and returns the listener along with its lock or PID file
+    /// if there is one, the name that was bound, and whether a stale socket had to be deleted.
+    pub(crate) fn bind_socket(&self) -> Result<BoundSocket<LocalSocketListener>, IpcServerError> {
+        self.bind_socket_with(|socket| LocalSocketListener::bind(socket))
+    }
+
+    /// Like `bind_socket`, but creates the listener with `bind`, for listeners other than `LocalSocketListener`.
+    fn bind_socket_with<L>(
+        &self,
+        bind: impl Fn(&str) -> io::Result<L>,
+    ) -> Result<BoundSocket<L>, IpcServerError> {
+        let socket = self.socket_name();
+
+        #[cfg(unix)]
+        if let Some(fallback) = &self.namespace_fallback {
+            return match self.bind_name(&socket, &bind) {
+                Err(IpcServerError::BindError { source: e, .. })
+                    if is_namespaced(&socket) && is_unsupported_bind_error(&e) =>
+                {
+                    (self.on_warning)(&format!(
+                        "Binding namespaced socket '{socket}' failed ({e}), falling back to '{fallback}'."
+                    ));
+
+                    self.bind_name(fallback, &bind)
+                }
+                result => result,
+            };
+        }
+
+        self.bind_name(&socket, &bind)
+    }
+
+    /// Decides what to do about a socket that is already in use. Fails if a running instance holds it, or if the stale
+    /// socket policy keeps the file, and otherwise returns `Ok` to delete the stale file and bind again.
+    fn check_in_use(&self, socket: &str, error: io::Error) -> Result<(), IpcServerError> {
+        // Namespaced sockets have no file, and are released as soon as the process holding them exits,
+        // so one that is in use always belongs to a running process.
+        if is_namespaced(socket) {
+            return Err(IpcServerError::AlreadyInUseError);
+        }
+
+        // Whatever the instance check found, a socket that accepts connections belongs to a running server, such as
+        // one the process count can't see without `sysinfo`, or one that won a race for the PID file.
+        if !self.skip_instance_check && LocalSocketStream::connect(socket).is_ok() {
+            return Err(IpcServerError::AlreadyInUseError);
+        }
+
+        // With a lock or PID file, acquiring it already proves no other instance is running.
+        if self.instance_check == InstanceCheck::ProcessCount
+            && !self.skip_instance_check
+            && current_process_instance_count() > 1
+        {
+            return Err(IpcServerError::AlreadyInUseError);
+        }
+
+        let delete = match self.stale_socket_policy {
+            StaleSocketPolicy::AutoDelete => true,
+            StaleSocketPolicy::Error => false,
+            StaleSocketPolicy::Callback(decide) => decide(socket),
+        };
+
+        if !delete {
+            return Err(IpcServerError::bind(socket)(error));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the server could bind its socket, without starting it or leaving anything behind.
+    ///
+    /// This runs the same checks as binding, and fails with the same errors, such as
+    /// `IpcServerError::AlreadyInUseError` when another instance holds the socket,
+    /// so a program can report that clearly before spawning any threads. The socket is bound and immediately closed.
+    /// A stale socket file counts as bindable if the `stale_socket_policy` would delete it, but it isn't deleted, and a
+    /// lock or PID file is only checked, never created. `StaleSocketPolicy::Callback` is still called to decide. The
+    /// `namespace_fallback` and `message_mode` options aren't taken into account.
+    ///
+    /// Another process can still take the socket between this check and starting the server.
+    pub fn can_bind(&self) -> Result<(), IpcServerError> {
+        let socket = self.socket_name();
+        validate_name(&socket)?;
+
+        if !self.skip_instance_check {
+            match self.instance_check {
+                InstanceCheck::ProcessCount => {}
+                InstanceCheck::LockFile => check_lock_file(&socket)?,
+                InstanceCheck::PidFile => check_pid_file(&pid_file_path(&socket))?,
+            }
+        }
+
+        match LocalSocketListener::bind(socket.as_str()) {
+            Ok(listener) => {
+                drop(listener);
+
+                #[cfg(unix)]
+                if !is_namespaced(&socket) {
+                    let _ = std::fs::remove_file(&socket);
+                }
+
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => self.check_in_use(&socket, e),
+            Err(e) => Err(IpcServerError::bind(&socket)(e)),
+        }
+    }
+
+    /// Binds the given socket name, recovering from stale sockets, and returns the listener along with its lock or PID file if there is one.
+    fn bind_name<L>(
+        &self,
+        socket: &str,
+        bind: &impl Fn(&str) -> io::Result<L>,
+    ) -> Result<BoundSocket<L>, IpcServerError> {
+        validate_name(socket)?;
+
+        let instance_guard = match self.instance_check {
+            _ if self.skip_instance_check => None,
+            InstanceCheck::ProcessCount => None,
+            InstanceCheck::LockFile => Some(InstanceGuard::LockFile(acquire_lock_file(socket)?)),
+            InstanceCheck::PidFile => Some(InstanceGuard::PidFile(acquire_pid_file(socket)?)),
+        };
+
+        let (listener, outcome) = match bind(socket) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                self.check_in_use(socket, e)?;
+
+                // The address was in use but there's no instances of this process running,
+                // so it's likely a leftover socket file that we can delete.
+                (self.on_warning)("Socket file already in use, deleting it and trying again.");
+
+                std::fs::remove_file(socket).map_err(IpcServerError::FileError)?;
+                let listener = bind(socket).map_err(IpcServerError::bind(socket))?;
+                (listener, BindOutcome::RecoveredStale)
+            }
+            Err(e) => return Err(IpcServerError::bind(socket)(e)),
+            Ok(listener) => (listener, BindOutcome::FreshBind),
+        };
+
+        #[cfg(unix)]
+        if let Some(mode) = self.socket_mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            if !is_namespaced(socket) {
+                std::fs::set_permissions(socket, std::fs::Permissions::from_mode(mode))
+                    .map_err(IpcServerError::PermissionsError)?;
+            }
+        }
+
+        if let Some(on_bind) = &self.on_bind {
+            on_bind(socket, outcome);
+        }
+
+        Ok(BoundSocket {
+            listener,
+            instance_guard,
+            name: socket.to_string(),
+            outcome,
+        })
+    }
+}
+
+/// A socket bound by `IpcServerBuilder::bind_socket`, along with what binding it involved.
+pub(crate) struct BoundSocket<L> {
+    pub(crate) listener: L,
+    /// The lock or PID file, if the instance check uses one, which must be held for as long as the listener is.
+    pub(crate) instance_guard: Option<InstanceGuard>,
+    /// The name that was bound, which differs from the configured one if binding fell back to `namespace_fallback`.
+    pub(crate) name: String,
+    pub(crate) outcome: BindOutcome,
+}
+
+/// Binds a path-based socket with a `UnixListener`, whose socket file `BoundServer` can watch, and a namespaced one
+/// as usual, since it has no file.
+#[cfg(unix)]
+fn bind_watchable(socket: &str) -> io::Result<Listener> {
+    if is_namespaced(socket) {
+        return LocalSocketListener::bind(socket).map(Listener::Local);
+    }
+
+    UnixListener::bind(socket).map(Listener::Unix)
+}
+
+/// Checks whether a bind failed because the environment doesn't allow the kind of socket, rather than because of the name.
+#[cfg(unix)]
+fn is_unsupported_bind_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+    ) || matches!(
+        error.raw_os_error(),
+        Some(libc::EAFNOSUPPORT | libc::EOPNOTSUPP | libc::EPROTONOSUPPORT)
+    )
+}
+
+/// The parts of the builder's configuration that typed servers use to handle each connection.
+struct TypedConfig {
+    framing: Framing,
+    metrics: Option<Arc<dyn ServerMetrics>>,
+    on_raw_frame: Option<fn(&[u8])>,
+    acknowledge_messages: bool,
+    max_connection_duration: Option<Duration>,
+    on_warning: fn(&str),
+    #[cfg(unix)]
+    idle_timeout: Option<Duration>,
+}
+
+/// Shuts down a connection once it has been open for the maximum connection duration, unless dropped before then.
+#[cfg(unix)]
+struct ConnectionTimer {
+    /// Dropped along with the timer, which wakes up its thread so it lets go of the connection.
+    _cancel: std::sync::mpsc::Sender<()>,
+}
+
+#[cfg(unix)]
+impl ConnectionTimer {
+    /// Starts a timer for the connection if the config has a maximum connection duration.
+    fn start(
+        stream: &LocalSocketStream,
+        config: &TypedConfig,
+    ) -> Result<Option<Self>, IpcStreamReadError> {
+        let Some(max) = config.max_connection_duration else {
+            return Ok(None);
+        };
+
+        let handle = crate::utils::try_clone(stream)?;
+        let (cancel, cancelled) = std::sync::mpsc::channel::<()>();
+        let on_warning = config.on_warning;
+
+        std::thread::Builder::new()
+            .name("ipc connection timer".to_string())
+            .spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(max) {
+                    on_warning(&format!(
+                        "Closing connection, which exceeds the maximum connection duration of {max:?}."
+                    ));
+                    let _ = crate::utils::shutdown(&handle, std::net::Shutdown::Both);
+                }
+            })?;
+
+        Ok(Some(Self { _cancel: cancel }))
+    }
+}
+
+/// Handles a connection for a typed server, reporting any error to the metrics hooks before dropping the connection.
+///
+/// Returns whether the handler asked for the server to stop.
+fn handle_typed_connection<TRequest, TResponse, F>(
+    mut stream: LocalSocketStream,
+    config: &TypedConfig,
+    on_connection: &F,
+) -> ControlFlow<()>
+where
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest, &mut LocalSocketStream) -> ControlFlow<Option<TResponse>, Option<TResponse>>,
+{
+    match respond_to_request(&mut stream, config, on_connection) {
+        Ok(flow) => flow,
+        Err(e) => {
+            if let Some(metrics) = &config.metrics {
+                metrics.on_error(&e);
+            }
+
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/// Reads a request from the stream, passes it to a typed handler, and writes the response if there is one.
+///
+/// When acknowledging messages, an empty frame is written instead if the handler doesn't return a response.
+fn respond_to_request<TRequest, TResponse, F>(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+    on_connection: &F,
+) -> Result<ControlFlow<()>, IpcStreamError>
+where
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest, &mut LocalSocketStream) -> ControlFlow<Option<TResponse>, Option<TResponse>>,
+{
+    #[cfg(unix)]
+    let _timer = ConnectionTimer::start(stream, config)?;
+
+    let bytes = read_request(stream, config)?;
+    let request: TRequest = config.framing.deserialize(&bytes)?;
+
+    let (response, flow) = match on_connection(request, stream) {
+        ControlFlow::Continue(response) => (response, ControlFlow::Continue(())),
+        ControlFlow::Break(response) => (response, ControlFlow::Break(())),
+    };
+
+    let response = response
+        .map(|response| crate::codec::serialize(&response))
+        .transpose()
+        .map_err(IpcStreamWriteError::from)?;
+
+    write_response(stream, config, response)?;
+
+    Ok(flow)
+}
+
+/// Reads a request from the stream, passes it to a handler returning an iterator, and writes each of its responses.
+fn respond_with_iter<TRequest, TResponse, I, F>(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+    on_connection: &F,
+) -> Result<(), IpcStreamError>
+where
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    I: IntoIterator<Item = TResponse>,
+    F: Fn(TRequest) -> I,
+{
+    let started = Instant::now();
+
+    #[cfg(unix)]
+    let _timer = ConnectionTimer::start(stream, config)?;
+
+    let bytes = read_request(stream, config)?;
+    let request: TRequest = config.framing.deserialize(&bytes)?;
+
+    for response in on_connection(request) {
+        if let Some(max) = config.max_connection_duration {
+            if started.elapsed() >= max {
+                (config.on_warning)(&format!(
+                    "Closing connection after {:?}, which exceeds the maximum connection duration of {max:?}.",
+                    started.elapsed()
+                ));
+                break;
+            }
+        }
+
+        let response = crate::codec::serialize(&response).map_err(IpcStreamWriteError::from)?;
+        write_response(stream, config, Some(response))?;
+    }
+
+    Ok(())
+}
+
+/// Like `respond_to_request`, but runs the handler on its own thread and gives up on it once the timeout elapses.
+///
+/// The request is deserialized and the response serialized on the handler's thread, so neither type has to be `Send`.
+/// If the handler times out or panics, nothing is written and the error to report is returned.
+fn respond_within<TRequest, TResponse, F>(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+    on_connection: &Arc<F>,
+    timeout: Duration,
+) -> Result<Option<io::Error>, IpcStreamError>
+where
+    TRequest: DeserializeOwned + 'static,
+    TResponse: Serialize + 'static,
+    F: Fn(TRequest) -> Option<TResponse> + Send + Sync + 'static,
+{
+    #[cfg(unix)]
+    let _timer = ConnectionTimer::start(stream, config)?;
+
+    let bytes = read_request(stream, config)?;
+
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    let on_connection = on_connection.clone();
+    let framing = config.framing;
+
+    std::thread::spawn(move || {
+        let result = framing
+            .deserialize(&bytes)
+            .map_err(IpcStreamError::from)
+            .and_then(|request: TRequest| {
+                on_connection(request)
+                    .map(|response| crate::codec::serialize(&response))
+                    .transpose()
+                    .map_err(|e| IpcStreamError::from(IpcStreamWriteError::from(e)))
+            });
+
+        // The server may have given up on the handler already, in which case nobody is listening.
+        let _ = sender.send(result);
+    });
+
+    let error = match receiver.recv_timeout(timeout) {
+        Ok(response) => {
+            write_response(stream, config, response?)?;
+            return Ok(None);
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            io::Error::new(io::ErrorKind::TimedOut, "The handler timed out")
+        }
+        Err(RecvTimeoutError::Disconnected) => io::Error::other("The handler panicked"),
+    };
+
+    Ok(Some(error))
+}
+
+/// Reads a tagged request from the stream, dispatches it with the router, and writes the response if there is one.
+fn route_request(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+    router: &RpcRouter,
+) -> Result<(), IpcStreamError> {
+    #[cfg(unix)]
+    let _timer = ConnectionTimer::start(stream, config)?;
+
+    let bytes = read_request(stream, config)?;
+    let response = router.dispatch(&config.framing, &bytes)?;
+    write_response(stream, config, response)?;
+
+    Ok(())
+}
+
+/// Reads the requests of a multiplexed connection until the client closes it, handing each of them to the executor.
+#[cfg(unix)]
+fn read_multiplexed_requests<TRequest, TResponse, F>(
+    mut stream: LocalSocketStream,
+    config: &Arc<TypedConfig>,
+    executor: &dyn Executor,
+    on_request: &Arc<F>,
+    on_panic: Option<fn(Box<dyn Any + Send>)>,
+) -> Result<(), IpcStreamError>
+where
+    TRequest: DeserializeOwned + 'static,
+    TResponse: Serialize + 'static,
+    F: Fn(TRequest) -> Option<TResponse> + Send + Sync + 'static,
+{
+    let _timer = ConnectionTimer::start(&stream, config)?;
+    let writer = crate::utils::try_clone(&stream).map_err(IpcStreamReadError::from)?;
+    let writer = Arc::new(std::sync::Mutex::new(writer));
+    let framing = &config.framing;
+
+    framing.read_magic(&mut stream)?;
+
+    while let Some(len) = framing.try_read_len(&mut stream)? {
+        let bytes = framing.read_payload(&mut stream, len)?;
+        report_request(config, &bytes);
+
+        let [a, b, c, d, e, f, g, h, ..] = bytes[..] else {
+            return Err(IpcStreamReadError::MissingRequestId.into());
+        };
+        let id = u64::from_le_bytes([a, b, c, d, e, f, g, h]);
+
+        let config = config.clone();
+        let writer = writer.clone();
+        let on_request = on_request.clone();
+
+        executor.execute(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                respond_multiplexed(&config, &writer, on_request.as_ref(), id, &bytes[8..])
+            }));
+
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    if let Some(metrics) = &config.metrics {
+                        metrics.on_error(&e);
+                    }
+                }
+                Err(payload) => {
+                    if let Some(on_panic) = on_panic {
+                        on_panic(payload);
+                    }
+                }
+            }
+        }));
+    }
+
+    Ok(())
+}
+
+/// Counts a multiplexed connection towards `max_multiplexed_connections` until dropped.
+#[cfg(unix)]
+struct ConnectionSlot(Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(unix)]
+impl ConnectionSlot {
+    /// Takes a slot, or returns `None` if `max` connections are open already.
+    fn acquire(open: &Arc<std::sync::atomic::AtomicUsize>, max: usize) -> Option<Self> {
+        open.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < max).then_some(n + 1)
+        })
+        .ok()?;
+
+        Some(Self(open.clone()))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Deserializes a request of a multiplexed connection, passes it to the handler, and writes the response tagged with
+/// the request's id, if there is one.
+#[cfg(unix)]
+fn respond_multiplexed<TRequest, TResponse, F>(
+    config: &TypedConfig,
+    writer: &std::sync::Mutex<LocalSocketStream>,
+    on_request: &F,
+    id: u64,
+    body: &[u8],
+) -> Result<(), IpcStreamError>
+where
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest) -> Option<TResponse>,
+{
+    let lock = || writer.lock().unwrap_or_else(|e| e.into_inner());
+
+    let request: TRequest = match config.framing.deserialize(body) {
+        Ok(request) => request,
+        Err(e) => {
+            // Only this request failed, so the client is told which one, and the connection carries on.
+            config
+                .framing
+                .write_payload(&mut *lock(), &id.to_le_bytes())?;
+            return Err(e.into());
+        }
+    };
+
+    let Some(response) = on_request(request) else {
+        return Ok(());
+    };
+
+    let mut payload = id.to_le_bytes().to_vec();
+    crate::codec::serialize_into(&mut payload, &response).map_err(IpcStreamWriteError::from)?;
+    write_response(&mut lock(), config, Some(payload))?;
+
+    Ok(())
+}
+
+/// Writes a serialized response, or an empty acknowledgement frame if there is no response and acknowledgements are enabled.
+fn write_response(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+    response: Option<Vec<u8>>,
+) -> Result<(), IpcStreamWriteError> {
+    let bytes = match response {
+        Some(bytes) => bytes,
+        None if config.acknowledge_messages => Vec::new(),
+        None => return Ok(()),
+    };
+
+    config.framing.write_payload(stream, &bytes)?;
+
+    if let Some(metrics) = &config.metrics {
+        metrics.on_message_written(bytes.len());
+    }
+
+    Ok(())
+}
+
+/// Reads the payload of a request and reports its size to the metrics hooks.
+///
+/// If the request carries a deadline that has already passed, the client is told so instead, and this fails with
+/// `IpcStreamReadError::DeadlineExceeded` without reporting the request, so the handler never sees it.
+fn read_request(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+) -> Result<Vec<u8>, IpcStreamReadError> {
+    let (bytes, deadline) = read_request_payload(stream, config)?;
+
+    if deadline.is_some_and(|deadline| SystemTime::now() >= deadline) {
+        // The client may have stopped waiting already, in which case there's nobody to tell.
+        let _ = config.framing.write_deadline_exceeded(stream);
+        return Err(IpcStreamReadError::DeadlineExceeded);
+    }
+
+    report_request(config, &bytes);
+
+    Ok(bytes)
+}
+
+/// Reports the payload of a request that was read to the metrics and raw frame hooks.
+fn report_request(config: &TypedConfig, bytes: &[u8]) {
+    if let Some(metrics) = &config.metrics {
+        metrics.on_message_read(bytes.len());
+    }
+
+    if let Some(on_raw_frame) = config.on_raw_frame {
+        on_raw_frame(bytes);
+    }
+}
+
+/// Reads the payload of a request along with its deadline, if it has one, giving up once the idle timeout elapses if
+/// there is one.
+fn read_request_payload(
+    stream: &mut LocalSocketStream,
+    config: &TypedConfig,
+) -> Result<(Vec<u8>, Option<SystemTime>), IpcStreamReadError> {
+    let framing = &config.framing;
+
+    #[cfg(unix)]
+    if let Some(deadline) = request_deadline(config) {
+        let mut reader = crate::utils::DeadlineStream::new(stream, deadline);
+        framing.read_magic(&mut reader)?;
+        let (size, request_deadline) = framing.read_len_with_deadline(&mut reader)?;
+        let bytes = framing.read_payload(&mut reader, size)?;
+
+        // Restore the per-read timeout for handlers that keep reading from the stream.
+        crate::utils::set_read_timeout(stream, config.idle_timeout)?;
+
+        return Ok((bytes, request_deadline));
+    }
+
+    framing.read_magic(stream)?;
+    let (size, deadline) = framing.read_len_with_deadline(stream)?;
+    let bytes = framing.read_payload(stream, size)?;
+
+    Ok((bytes, deadline))
+}
+
+/// Gets the time by which a request must have been read, from the idle timeout and the maximum connection duration.
+#[cfg(unix)]
+fn request_deadline(config: &TypedConfig) -> Option<Instant> {
+    let now = Instant::now();
+
+    [config.idle_timeout, config.max_connection_duration]
+        .into_iter()
+        .flatten()
+        .map(|timeout| now + timeout)
+        .min()
+}
+
+/// Spawns a thread that waits for the shutdown signal, then connects to the server to unblock its accept loop.
+///
+/// Returns the flag the accept loop checks to tell the shutdown connection apart from a client.
+fn watch_for_shutdown(
+    receiver: Receiver<()>,
+    name: String,
+) -> Result<Arc<AtomicBool>, IpcServerError> {
+    let requested = Arc::new(AtomicBool::new(false));
+    let flag = requested.clone();
+
+    std::thread::Builder::new()
+        .name(format!("ipc shutdown '{name}'"))
+        .spawn(move || {
+            if receiver.recv().is_ok() {
+                flag.store(true, Ordering::SeqCst);
+                crate::utils::poke(&name);
+            }
+        })
+        .map_err(IpcServerError::ThreadSpawnError)?;
+
+    Ok(requested)
+}
+
+/// The listener a server accepts connections from.
+enum Listener {
+    Local(LocalSocketListener),
+    /// A Unix listener inherited from another process with `IpcServerBuilder::inherit_listener`, or bound to a socket
+    /// file for `IpcServerBuilder::restart_on_error`.
+    #[cfg(unix)]
+    Unix(UnixListener),
+    /// A named pipe bound with `IpcServerBuilder::message_mode` or `IpcServerBuilder::restrict_to_current_user`,
+    /// whose instances `interprocess` can't create.
+    #[cfg(windows)]
+    Pipe(crate::utils::PipeListener),
+}
+
+impl Listener {
+    /// Gets the name a client can connect to the listener with, if it differs from the configured socket name.
+    #[cfg(unix)]
+    fn connect_name(&self) -> Option<String> {
+        match self {
+            Listener::Local(_) => None,
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let addr = listener.local_addr().ok()?;
+
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                {
+                    #[cfg(target_os = "android")]
+                    use std::os::android::net::SocketAddrExt;
+                    #[cfg(target_os = "linux")]
+                    use std::os::linux::net::SocketAddrExt;
+
+                    if let Some(name) = addr.as_abstract_name() {
+                        return Some(format!("@{}", String::from_utf8_lossy(name)));
+                    }
+                }
+
+                Some(addr.as_pathname()?.to_string_lossy().to_string())
+            }
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Local(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(windows)]
+            Listener::Pipe(listener) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    fn accept(&self) -> io::Result<LocalSocketStream> {
+        match self {
+            Listener::Local(listener) => listener.accept(),
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+
+                // SAFETY: `into_raw_fd` gives up ownership of a valid, connected stream socket,
+                // which is exactly what a Unix local socket stream wraps.
+                Ok(unsafe { LocalSocketStream::from_raw_fd(stream.into_raw_fd()) })
+            }
+            #[cfg(windows)]
+            Listener::Pipe(listener) => listener.accept(),
+        }
+    }
+}
+
+/// A bound listener along with the configuration needed to run its accept loop.
+pub(crate) struct BoundServer {
+    listener: Listener,
+    /// The name the listener is bound to, used to recognize the connections this process makes to wake it up.
+    name: String,
+    /// Whether binding had to delete a stale socket, or `None` for an inherited listener.
+    bind_outcome: Option<BindOutcome>,
+    /// Set with `IpcServerBuilder::restart_on_error`, to rebind the socket if the listener fails.
+    restart: Option<Restart>,
+    /// Held for as long as the server runs when using `InstanceCheck::LockFile` or `InstanceCheck::PidFile`.
+    _instance_guard: Option<InstanceGuard>,
+    /// Set once the shutdown signal is received, so the accept loop stops instead of handling the next connection.
+    shutdown_requested: Option<Arc<AtomicBool>>,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+    on_panic: Option<fn(Box<dyn Any + Send>)>,
+    metrics: Option<Arc<dyn ServerMetrics>>,
+    #[cfg(feature = "auth")]
+    auth: Option<crate::AuthConfig>,
+    #[cfg(unix)]
+    idle_timeout: Option<Duration>,
+    #[cfg(unix)]
+    recv_buffer_size: Option<usize>,
+    #[cfg(unix)]
+    send_buffer_size: Option<usize>,
+    #[cfg(windows)]
+    restrict_to_current_user: bool,
+}
+
+/// What a server needs to bind its socket again after its listener fails. See `IpcServerBuilder::restart_on_error`.
+struct Restart {
+    policy: RestartPolicy,
+    on_restart: Option<fn(u32, &io::Error)>,
+    /// Set for an `IpcAcceptor`, whose listener must stay nonblocking once it's replaced.
+    nonblocking: bool,
+    #[cfg(unix)]
+    socket_mode: Option<u32>,
+    /// The identity of the socket file the listener is bound to, if it has one, to notice it being deleted or replaced.
+    #[cfg(unix)]
+    socket_file: Option<crate::utils::FileId>,
+    /// How the named pipe was created, if `interprocess` didn't create it, so it's bound the same way again.
+    #[cfg(windows)]
+    pipe: Option<crate::utils::PipeOptions>,
+}
+
+impl Restart {
+    /// Binds the socket again, in place of a listener that failed.
+    fn bind(&mut self, name: &str) -> io::Result<Listener> {
+        #[cfg(unix)]
+        let listener = {
+            // The file is only deleted if it's still the one the failed listener was bound to,
+            // so a socket another process has bound in the meantime is left alone.
+            if self.socket_file.is_some() && crate::utils::file_id(name).ok() == self.socket_file {
+                std::fs::remove_file(name)?;
+            }
+
+            let listener = bind_watchable(name)?;
+
+            if let (Some(mode), Listener::Unix(_)) = (self.socket_mode, &listener) {
+                use std::os::unix::fs::PermissionsExt;
+
+                std::fs::set_permissions(name, std::fs::Permissions::from_mode(mode))?;
+            }
+
+            self.socket_file = crate::utils::file_id(name).ok();
+            listener
+        };
+
+        #[cfg(windows)]
+        let listener = match self.pipe {
+            Some(options) => crate::utils::PipeListener::bind(name, options).map(Listener::Pipe)?,
+            None => LocalSocketListener::bind(name).map(Listener::Local)?,
+        };
+
+        if self.nonblocking {
+            listener.set_nonblocking(true)?;
+        }
+
+        Ok(listener)
+    }
+}
+
+/// Checks whether accepting failed because the listener itself is broken, so no later accept can succeed either.
+fn is_fatal_accept_error(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    let fatal = [libc::EBADF, libc::EINVAL, libc::ENOTSOCK, libc::EOPNOTSUPP];
+
+    #[cfg(windows)]
+    let fatal = [
+        winapi::shared::winerror::ERROR_INVALID_HANDLE as i32,
+        winapi::shared::winerror::ERROR_BAD_PIPE as i32,
+    ];
+
+    // A deleted or replaced socket file is reported as `NotFound` by `wait_for_connection`.
+    error.kind() == io::ErrorKind::NotFound
+        || error
+            .raw_os_error()
+            .is_some_and(|code| fatal.contains(&code))
+}
+
+impl BoundServer {
+    /// Accepts incoming connections and invokes `on_connection` for each of them, until it returns `ControlFlow::Break`.
+    fn run<F: Fn(LocalSocketStream) -> ControlFlow<()>>(mut self, on_connection: F) {
+        while let ControlFlow::Continue(stream) = self.accept_next() {
+            let Some(stream) = stream else {
+                continue;
+            };
+
+            if let Some(metrics) = &self.metrics {
+                metrics.on_connection_opened();
+            }
+
+            // A panicking callback only loses the connection it was handling, rather than the whole server.
+            let flow = match panic::catch_unwind(AssertUnwindSafe(|| on_connection(stream))) {
+                Ok(flow) => flow,
+                Err(payload) => {
+                    if let Some(on_panic) = self.on_panic {
+                        on_panic(payload);
+                    }
+
+                    ControlFlow::Continue(())
+                }
+            };
+
+            if let Some(metrics) = &self.metrics {
+                metrics.on_connection_closed();
+            }
+
+            if flow.is_break() {
+                break;
+            }
+        }
+    }
+
+    /// Accepts the next connection and prepares it to be handled, applying the idle timeout, buffer sizes and access checks.
+    ///
+    /// Continues with `None` if no connection was ready on a nonblocking listener, or if the connection was
+    /// rejected or failed and `on_connection_error` chose to carry on. Breaks once the server should stop.
+    fn accept_next(&mut self) -> ControlFlow<(), Option<LocalSocketStream>> {
+        let stream = match self.accept() {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return ControlFlow::Continue(None),
+            Err(_) if self.is_shutdown_requested() => return ControlFlow::Break(()),
+            Err(e) if self.restart.is_some() && is_fatal_accept_error(&e) => {
+                return self.restart(e);
+            }
+            Err(e) => return self.handle_error(e),
+        };
+
+        // Take the poke before checking for a shutdown, so it isn't mistaken for another poke of the same socket later.
+        let poked = crate::utils::take_poke(&stream, &self.name);
+
+        if self.is_shutdown_requested() {
+            return ControlFlow::Break(());
+        }
+
+        if poked {
+            return ControlFlow::Continue(None);
+        }
+
+        match self.admit(stream) {
+            Ok(stream) => ControlFlow::Continue(stream),
+            Err(e) => self.handle_error(e),
+        }
+    }
+
+    /// Accepts a connection, first waiting for one while checking that the socket file is still there,
+    /// if the server is set to restart when it isn't.
+    fn accept(&self) -> io::Result<LocalSocketStream> {
+        #[cfg(unix)]
+        if let (
+            Listener::Unix(listener),
+            Some(Restart {
+                socket_file: Some(id),
+                ..
+            }),
+        ) = (&self.listener, &self.restart)
+        {
+            crate::utils::wait_for_connection(listener, &self.name, *id)?;
+        }
+
+        self.listener.accept()
+    }
+
+    /// Binds the socket again after the listener failed with the error, waiting between attempts as the restart
+    /// policy says. Breaks if every attempt failed, or a shutdown was requested while waiting.
+    fn restart(&mut self, mut error: io::Error) -> ControlFlow<(), Option<LocalSocketStream>> {
+        let Some(restart) = &mut self.restart else {
+            return ControlFlow::Break(());
+        };
+
+        let policy = restart.policy;
+        let mut backoff = policy.initial_backoff;
+
+        for attempt in 1..=policy.max_attempts {
+            if let Some(on_restart) = restart.on_restart {
+                on_restart(attempt, &error);
+            }
+
+            std::thread::sleep(backoff);
+
+            if self
+                .shutdown_requested
+                .as_ref()
+                .is_some_and(|requested| requested.load(Ordering::SeqCst))
+            {
+                return ControlFlow::Break(());
+            }
+
+            match restart.bind(&self.name) {
+                Ok(listener) => {
+                    self.listener = listener;
+                    return ControlFlow::Continue(None);
+                }
+                Err(e) => error = e,
+            }
+
+            backoff = (backoff * 2).min(policy.max_backoff);
+        }
+
+        // Every attempt failed, so let `on_connection_error` see why before stopping, since carrying on is pointless.
+        let _ = self.handle_error::<()>(error);
+        ControlFlow::Break(())
+    }
+
+    /// Returns `None` if the connection was rejected without an error.
+    fn admit(
+        &self,
+        #[allow(unused_mut)] mut stream: LocalSocketStream,
+    ) -> io::Result<Option<LocalSocketStream>> {
+        #[cfg(unix)]
+        if let Some(size) = self.recv_buffer_size {
+            crate::utils::set_buffer_size(&stream, libc::SO_RCVBUF, size)?;
+        }
+
+        #[cfg(unix)]
+        if let Some(size) = self.send_buffer_size {
+            crate::utils::set_buffer_size(&stream, libc::SO_SNDBUF, size)?;
+        }
+
+        #[cfg(windows)]
+        if self.restrict_to_current_user && !crate::utils::is_peer_current_user(&stream) {
+            return Ok(None);
+        }
+
+        // The handshake is bounded by its own timeout, so the idle timeout is only set once it's done.
+        #[cfg(feature = "auth")]
+        if let Some(auth) = &self.auth {
+            if let Err(e) = crate::auth::authenticate_client(&mut stream, auth) {
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_auth_failed(&e);
+                }
+
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    e.to_string(),
+                ));
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(timeout) = self.idle_timeout {
+            crate::utils::set_read_timeout(&stream, Some(timeout))?;
+        }
+
+        Ok(Some(stream))
+    }
+
+    fn is_shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+            .as_ref()
+            .is_some_and(|requested| requested.load(Ordering::SeqCst))
+    }
+
+    /// Passes an error to `on_connection_error`, if set, and returns whether the accept loop should carry on.
+    fn handle_error<T>(&self, error: io::Error) -> ControlFlow<(), Option<T>> {
+        let action = match self.on_connection_error {
+            Some(on_connection_error) => on_connection_error(error),
+            None => ErrorAction::Continue,
+        };
+
+        match action {
+            ErrorAction::Continue => ControlFlow::Continue(None),
+            ErrorAction::Stop => ControlFlow::Break(()),
+        }
+    }
+}
+
+/// A bound server whose connections are accepted one at a time by polling, for use with a custom event loop.
+///
+/// The listener is put in nonblocking mode, so `poll_accept` returns straight away when no client is waiting.
+/// Accepted connections go through the same checks as in the threaded servers, such as the idle timeout and
+/// authentication, and are returned in blocking mode. Note that the authentication handshake blocks while it runs,
+/// and that `ServerMetrics` connection events are not reported, since the caller decides when a connection is closed.
+///
+/// Created with `IpcServerBuilder::bind_acceptor`.
+pub struct IpcAcceptor {
+    server: BoundServer,
+    stopped: bool,
+}
+
+impl IpcAcceptor {
+    /// Accepts a connection if one is ready, returning `None` otherwise.
+    ///
+    /// Connections that fail to be accepted or set up are passed to `on_connection_error`, and `None` is returned
+    /// for them too. Once it returns `ErrorAction::Stop`, or a shutdown is requested with
+    /// `IpcServerBuilder::shutdown_on`, no more connections are accepted and `is_stopped` returns `true`.
+    pub fn poll_accept(&mut self) -> Option<LocalSocketStream> {
+        if self.stopped {
+            return None;
+        }
+
+        match self.server.accept_next() {
+            ControlFlow::Continue(Some(stream)) => match stream.set_nonblocking(false) {
+                Ok(()) => Some(stream),
+                Err(e) => {
+                    self.stopped = self.server.handle_error::<()>(e).is_break();
+                    None
+                }
+            },
+            ControlFlow::Continue(None) => None,
+            ControlFlow::Break(()) => {
+                self.stopped = true;
+                None
+            }
+        }
+    }
+
+    /// Checks whether the acceptor has stopped accepting connections.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Returns whether a stale socket file had to be deleted to bind the socket, or `None` if the listener was
+    /// inherited with `IpcServerBuilder::inherit_listener` rather than bound.
+    pub fn bind_outcome(&self) -> Option<BindOutcome> {
+        self.server.bind_outcome
+    }
+}
+
+/// Opens the lock file for the given socket and takes an exclusive lock on it.
+fn acquire_lock_file(socket: &str) -> Result<File, IpcServerError> {
+    let path = lock_file_path(socket);
+    let lock_error = |source| IpcServerError::LockFileError {
+        path: path.clone(),
+        source,
+    };
+
+    let lock_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(lock_error)?;
+
+    match lock_file.try_lock_exclusive() {
+        Ok(()) => Ok(lock_file),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+            Err(IpcServerError::AlreadyInUseError)
+        }
+        Err(e) => Err(lock_error(e)),
+    }
+}
+
+/// Fails with `IpcServerError::AlreadyInUseError` if another process holds the lock file for the given socket, without
+/// creating the file if it doesn't exist.
+fn check_lock_file(socket: &str) -> Result<(), IpcServerError> {
+    let path = lock_file_path(socket);
+    let lock_error = |source| IpcServerError::LockFileError {
+        path: path.clone(),
+        source,
+    };
+
+    let lock_file = match OpenOptions::new().write(true).open(&path) {
+        Ok(lock_file) => lock_file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(lock_error(e)),
+    };
+
+    // The lock is released as soon as the file is dropped.
+    match lock_file.try_lock_exclusive() {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+            Err(IpcServerError::AlreadyInUseError)
+        }
+        Err(e) => Err(lock_error(e)),
+    }
+}
+
+/// Locks the PID file for the given socket and, if it doesn't name a running process, writes the current process id
+/// to it.
+///
+/// A file that is created or replaced is only written while it's locked, so of two servers starting at once, the
+/// second finds the first one's lock and fails, rather than both taking the file.
+fn acquire_pid_file(socket: &str) -> Result<PidFile, IpcServerError> {
+    use std::io::{Seek, Write};
+
+    let path = pid_file_path(socket);
+    let pid_error = |source| IpcServerError::PidFileError {
+        path: path.clone(),
+        source,
+    };
+
+    loop {
+        let mut file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                lock_pid_file(&file, &path)?;
+                file
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let Some(file) = open_pid_file(&path)? else {
+                    continue;
+                };
+
+                check_pid(&file, &path)?;
+                file
+            }
+            Err(e) => return Err(pid_error(e)),
+        };
+
+        // The server that held the file may have removed it before letting go of the lock,
+        // in which case another server could be creating a new one.
+        if !is_same_file(&file, &path) {
+            continue;
+        }
+
+        file.set_len(0).map_err(pid_error)?;
+        file.rewind().map_err(pid_error)?;
+        writeln!(file, "{}", std::process::id()).map_err(pid_error)?;
+
+        return Ok(PidFile { path, _file: file });
+    }
+}
+
+/// Fails with `IpcServerError::AlreadyInUseError` if the PID file exists and is locked or names a running process,
+/// without creating the file if it doesn't exist.
+fn check_pid_file(path: &Path) -> Result<(), IpcServerError> {
+    match open_pid_file(path)? {
+        Some(file) => check_pid(&file, path),
+        None => Ok(()),
+    }
+}
+
+/// Opens and locks an existing PID file, returning `None` if it doesn't exist.
+fn open_pid_file(path: &Path) -> Result<Option<File>, IpcServerError> {
+    let file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(IpcServerError::PidFileError {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    lock_pid_file(&file, path)?;
+
+    Ok(Some(file))
+}
+
+/// Takes an exclusive lock on a PID file, failing with `IpcServerError::AlreadyInUseError` if another server holds it.
+fn lock_pid_file(file: &File, path: &Path) -> Result<(), IpcServerError> {
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+            Err(IpcServerError::AlreadyInUseError)
+        }
+        Err(source) => Err(IpcServerError::PidFileError {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Fails with `IpcServerError::AlreadyInUseError` if the locked PID file names a running process.
+fn check_pid(mut file: &File, path: &Path) -> Result<(), IpcServerError> {
+    use std::io::Read;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|source| IpcServerError::PidFileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+    match contents.trim().parse() {
+        Ok(pid) if is_process_running(pid) => Err(IpcServerError::AlreadyInUseError),
+        _ => Ok(()),
+    }
+}
+
+/// Checks whether the path still refers to the open file, rather than having been removed or replaced.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Windows doesn't let an open file's name be reused until every handle to it is closed, so the path can't have
+/// moved on to another file while it's open.
+#[cfg(windows)]
+fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_with_state() {
+        use crate::SocketExt;
+
+        let socket = "/tmp/ipc-util-test-listen-state.sock";
+
+        IpcServerBuilder::new(socket)
+            .listen_with_state(0u32, |count, mut stream| {
+                *count += 1;
+                let _ = stream.write_serde(count);
+            })
+            .expect("Failed to start server");
+
+        for expected in 1..=3u32 {
+            let mut stream = LocalSocketStream::connect(socket).unwrap();
+            assert_eq!(stream.read_serde::<u32>().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_acceptor_polls_without_blocking() {
+        use crate::SocketExt;
+
+        let socket = "/tmp/ipc-util-test-acceptor.sock";
+
+        let mut acceptor = IpcServerBuilder::new(socket)
+            .bind_acceptor()
+            .expect("Failed to bind acceptor");
+
+        assert!(acceptor.poll_accept().is_none());
+
+        let mut client = LocalSocketStream::connect(socket).unwrap();
+
+        let mut stream = loop {
+            match acceptor.poll_accept() {
+                Some(stream) => break stream,
+                None => std::thread::sleep(Duration::from_millis(5)),
+            }
+        };
+
+        client.write_serde(&7u32).unwrap();
+        assert_eq!(stream.read_serde::<u32>().unwrap(), 7);
+        assert!(!acceptor.is_stopped());
+    }
+
+    #[test]
+    fn test_on_ready_signals_blocking_server() {
+        let socket = "/tmp/ipc-util-test-on-ready.sock";
+        let (ready_sender, ready) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            IpcServerBuilder::new(socket)
+                .on_ready(move |name| ready_sender.send(name.to_string()).unwrap())
+                .serve_blocking(|request: u32| Some(request + 1))
+                .expect("Failed to run server");
+        });
+
+        let name = ready.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(name, socket);
+
+        let response: u32 = crate::send_ipc_query(&name, &1u32).unwrap();
+        assert_eq!(response, 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_namespace_fallback_only_on_unsupported_errors() {
+        assert!(is_unsupported_bind_error(&io::Error::from_raw_os_error(
+            libc::EAFNOSUPPORT
+        )));
+        assert!(is_unsupported_bind_error(
+            &io::ErrorKind::PermissionDenied.into()
+        ));
+        assert!(!is_unsupported_bind_error(&io::ErrorKind::AddrInUse.into()));
+
+        // A path-based name binds normally, without using the fallback.
+        let socket = "/tmp/ipc-util-test-namespace-fallback.sock";
+        let (ready_sender, ready) = std::sync::mpsc::channel();
+
+        IpcServerBuilder::new(socket)
+            .namespace_fallback("/tmp/ipc-util-test-namespace-fallback-unused.sock")
+            .on_ready(move |name| ready_sender.send(name.to_string()).unwrap())
+            .listen(|_| {})
+            .expect("Failed to start server");
+
+        assert_eq!(ready.recv().unwrap(), socket);
+    }
+
+    #[test]
+    fn test_server_survives_panicking_handler() {
+        use std::sync::atomic::AtomicUsize;
+
+        static PANICS: AtomicUsize = AtomicUsize::new(0);
+
+        let socket = "/tmp/ipc-util-test-on-panic.sock";
+
+        IpcServerBuilder::new(socket)
+            .on_panic(|payload| {
+                assert_eq!(payload.downcast_ref::<&str>(), Some(&"bad request"));
+                PANICS.fetch_add(1, Ordering::SeqCst);
+            })
+            .serve(|request: u32| {
+                if request == 0 {
+                    panic!("bad request");
+                }
+
+                Some(request * 2)
+            })
+            .expect("Failed to start server");
+
+        assert!(crate::send_ipc_query::<u32, u32>(socket, &0).is_err());
+
+        let response: u32 = crate::send_ipc_query(socket, &4u32).unwrap();
+        assert_eq!(response, 8);
+        assert_eq!(PANICS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_serve_concurrent_on_executor() {
+        use crate::Job;
+        use std::sync::atomic::AtomicUsize;
+
+        static JOBS: AtomicUsize = AtomicUsize::new(0);
+
+        let socket = "/tmp/ipc-util-test-executor.sock";
+        let (started, wait_started) = std::sync::mpsc::channel();
+        let (release, wait_release) = std::sync::mpsc::channel::<()>();
+        let wait_release = std::sync::Mutex::new(wait_release);
+
+        IpcServerBuilder::new(socket)
+            .executor(|job: Job| {
+                JOBS.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(job);
+            })
+            .serve_concurrent(move |request: u32| {
+                // The first request blocks until the second one has been handled.
+                if request == 0 {
+                    started.send(()).unwrap();
+                    wait_release.lock().unwrap().recv().unwrap();
+                }
+
+                Some(request)
+            })
+            .expect("Failed to start server");
+
+        let slow = std::thread::spawn(move || crate::send_ipc_query::<u32, u32>(socket, &0));
+        wait_started.recv().unwrap();
+
+        let fast: u32 = crate::send_ipc_query(socket, &1u32).unwrap();
+        assert_eq!(fast, 1);
+
+        release.send(()).unwrap();
+        assert_eq!(slow.join().unwrap().unwrap(), 0);
+        assert_eq!(JOBS.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_handler_timeout_drops_connection() {
+        use std::sync::atomic::AtomicBool;
+
+        static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+        let socket = "/tmp/ipc-util-test-handler-timeout.sock";
+
+        IpcServerBuilder::new(socket)
+            .on_connection_error(|e| {
+                TIMED_OUT.store(e.kind() == io::ErrorKind::TimedOut, Ordering::SeqCst);
+                ErrorAction::Continue
+            })
+            .serve_with_handler_timeout(Duration::from_millis(50), |request: u64| {
+                std::thread::sleep(Duration::from_millis(request));
+                Some(request)
+            })
+            .expect("Failed to start server");
+
+        let result = crate::send_ipc_query::<_, u64>(socket, &1000u64);
+        assert!(result.is_err());
+        assert!(TIMED_OUT.load(Ordering::SeqCst));
+
+        let response: u64 = crate::send_ipc_query(socket, &0u64).unwrap();
+        assert_eq!(response, 0);
+    }
+
+    #[test]
+    fn test_on_raw_frame_sees_payload_before_deserializing() {
+        use crate::SocketExt;
+        use std::sync::Mutex;
+
+        static FRAMES: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+        let socket = "/tmp/ipc-util-test-raw-frame.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .on_raw_frame(|bytes| FRAMES.lock().unwrap().push(bytes.to_vec()))
+            .serve(|request: u32| Some(request))
+            .expect("Failed to start server");
+
+        assert_eq!(crate::send_ipc_query::<u32, u32>(socket, &7).unwrap(), 7);
+
+        // A payload that fails to deserialize is still passed to the hook.
+        let mut stream = LocalSocketStream::connect(socket).unwrap();
+        stream.write_frame(&[0xc1]).unwrap();
+        assert!(stream.read_frame().is_err());
+
+        let frames = FRAMES.lock().unwrap();
+        assert_eq!(
+            *frames,
+            [crate::codec::serialize(&7u32).unwrap(), vec![0xc1]]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_on_channel() {
+        use std::sync::atomic::AtomicUsize;
+
+        static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+        let socket = "/tmp/ipc-util-test-shutdown.sock";
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let server = IpcServerBuilder::new(socket)
+            .shutdown_on(receiver)
+            .serve(|_: String| {
+                CONNECTIONS.fetch_add(1, Ordering::SeqCst);
+                Some(())
+            })
+            .expect("Failed to start server");
+
+        // Wait for the response, so the connection is handled before the server is told to stop.
+        crate::send_ipc_query::<_, ()>(socket, &"hello").unwrap();
+
+        sender.send(()).unwrap();
+        server.join().expect("Server thread panicked");
+
+        assert_eq!(CONNECTIONS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_on_warning_reports_stale_socket() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static WARNED: AtomicBool = AtomicBool::new(false);
+
+        let socket = "/tmp/ipc-util-test-on-warning.sock";
+        let _ = std::fs::remove_file(socket);
+        drop(UnixListener::bind(socket).expect("Failed to create stale socket"));
+
+        let _server = IpcServerBuilder::new(socket)
+            .on_warning(|_| WARNED.store(true, Ordering::SeqCst))
+            .listen(|_| {})
+            .expect("Failed to start server");
+
+        assert!(WARNED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_restart_on_deleted_socket_file() {
+        use std::sync::atomic::AtomicU32;
+
+        static RESTARTS: AtomicU32 = AtomicU32::new(0);
+
+        let socket = "/tmp/ipc-util-test-restart.sock";
+
+        IpcServerBuilder::new(socket)
+            .restart_on_error(RestartPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_millis(10),
+            })
+            .on_restart(|attempt, error| {
+                assert_eq!(attempt, 1);
+                assert_eq!(error.kind(), io::ErrorKind::NotFound);
+                RESTARTS.fetch_add(1, Ordering::SeqCst);
+            })
+            .serve(|request: u32| Some(request + 1))
+            .expect("Failed to start server");
+
+        assert_eq!(crate::send_ipc_query::<u32, u32>(socket, &1).unwrap(), 2);
+
+        std::fs::remove_file(socket).unwrap();
+
+        while RESTARTS.load(Ordering::SeqCst) == 0 || !Path::new(socket).exists() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(crate::send_ipc_query::<u32, u32>(socket, &2).unwrap(), 3);
+        assert_eq!(RESTARTS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    // Only where the peer's process id can be looked up, see `LocalSocketExt::is_self_connection`.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "haiku",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        windows
+    ))]
+    fn test_pokes_never_reach_handler() {
+        use crate::{LocalSocketExt, SocketExt};
+
+        let socket = "/tmp/ipc-util-test-poke.sock";
+
+        IpcServerBuilder::new(socket)
+            .listen_with_state(0u32, |count, mut stream| {
+                *count += 1;
+                let _ = stream.write_serde(count);
+            })
+            .expect("Failed to start server");
+
+        crate::utils::poke(socket);
+        crate::utils::poke(socket);
+
+        // The pokes are accepted first, and dropped without counting as connections.
+        let mut stream = LocalSocketStream::connect(socket).unwrap();
+        assert!(stream.is_self_connection());
+        assert_eq!(stream.read_serde::<u32>().unwrap(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_stale_socket_policy() {
+        let socket = "/tmp/ipc-util-test-stale-policy.sock";
+        let stale = || {
+            let _ = std::fs::remove_file(socket);
+            drop(UnixListener::bind(socket).expect("Failed to create stale socket"));
+        };
+        let bind = |policy| {
+            IpcServerBuilder::new(socket)
+                .on_warning(|_| {})
+                .stale_socket_policy(policy)
+                .bind_socket()
+                .map(|_| ())
+        };
+
+        stale();
+        assert!(matches!(
+            bind(StaleSocketPolicy::Error),
+            Err(IpcServerError::BindError { source: e, .. }) if e.kind() == io::ErrorKind::AddrInUse
+        ));
+        assert!(std::path::Path::new(socket).exists());
+
+        assert!(bind(StaleSocketPolicy::Callback(|_| false)).is_err());
+        assert!(bind(StaleSocketPolicy::Callback(
+            |path| path.ends_with("policy.sock")
+        ))
+        .is_ok());
+
+        stale();
+        assert!(bind(StaleSocketPolicy::AutoDelete).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_on_bind_reports_stale_socket_recovery() {
+        let socket = "/tmp/ipc-util-test-bind-outcome.sock";
+        let (sender, outcomes) = std::sync::mpsc::channel();
+        let bind = || {
+            let sender = sender.clone();
+            IpcServerBuilder::new(socket)
+                .on_warning(|_| {})
+                .skip_instance_check(true)
+                .on_bind(move |_, outcome| sender.send(outcome).unwrap())
+                .bind_socket()
+                .expect("Failed to bind")
+                .outcome
+        };
+
+        let _ = std::fs::remove_file(socket);
+        assert_eq!(bind(), BindOutcome::FreshBind);
+
+        // Dropping the listener leaves the socket file behind, like a server that crashed.
+        assert!(std::path::Path::new(socket).exists());
+        assert_eq!(bind(), BindOutcome::RecoveredStale);
+
+        assert_eq!(
+            outcomes.try_iter().collect::<Vec<_>>(),
+            [BindOutcome::FreshBind, BindOutcome::RecoveredStale]
+        );
+    }
+
+    #[test]
+    fn test_serve_iter_streams_responses() {
+        let socket = "/tmp/ipc-util-test-serve-iter.sock";
+        let (sender, produced) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+
+        let _server = IpcServerBuilder::new(socket)
+            .serve_iter(move |pages: u32| {
+                let sender = sender.lock().unwrap().clone();
+
+                (0..pages).map(move |page| {
+                    sender.send(page).unwrap();
+                    format!("page {page}")
+                })
+            })
+            .expect("Failed to start server");
+
+        let pages = crate::send_ipc_query_stream::<u32, String>(socket, &3)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(pages, ["page 0", "page 1", "page 2"]);
+
+        // An empty iterator closes the connection without a response.
+        let mut empty = crate::send_ipc_query_stream::<u32, String>(socket, &0).unwrap();
+        assert!(empty.next().is_none());
+
+        assert_eq!(produced.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_multiplexed_responds_out_of_order() {
+        use crate::SocketExt;
+
+        let socket = "/tmp/ipc-util-test-serve-multiplexed.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .serve_multiplexed(|delay: u64| {
+                std::thread::sleep(Duration::from_millis(delay));
+                Some(delay * 2)
+            })
+            .expect("Failed to start server");
+
+        let mut stream = crate::ipc_client_connect(socket).unwrap();
+
+        for (id, delay) in [(1u64, 200u64), (2, 0), (3, 100)] {
+            let mut payload = id.to_le_bytes().to_vec();
+            crate::codec::serialize_into(&mut payload, &delay).unwrap();
+            stream.write_frame(&payload).unwrap();
+        }
+
+        let responses = (0..3)
+            .map(|_| {
+                let payload = stream.read_frame().unwrap();
+                let id = u64::from_le_bytes(payload[..8].try_into().unwrap());
+                (
+                    id,
+                    crate::codec::deserialize::<u64>(&payload[8..], u64::MAX).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // The slowest request was sent first, but its response comes last.
+        assert_eq!(responses, [(2, 0), (3, 200), (1, 400)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_multiplexed_fails_only_malformed_request() {
+        use crate::SocketExt;
+
+        let socket = "/tmp/ipc-util-test-serve-multiplexed-malformed.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .serve_multiplexed(|delay: u64| {
+                std::thread::sleep(Duration::from_millis(delay));
+                Some(delay * 2)
+            })
+            .expect("Failed to start server");
+
+        let mut stream = crate::ipc_client_connect(socket).unwrap();
+
+        let mut payload = 1u64.to_le_bytes().to_vec();
+        crate::codec::serialize_into(&mut payload, &100u64).unwrap();
+        stream.write_frame(&payload).unwrap();
+
+        let mut malformed = 2u64.to_le_bytes().to_vec();
+        malformed.extend([0xff, 0xff]);
+        stream.write_frame(&malformed).unwrap();
+
+        // The malformed request is answered with just its id, and the slow one still gets its response.
+        assert_eq!(stream.read_frame().unwrap(), 2u64.to_le_bytes());
+
+        let payload = stream.read_frame().unwrap();
+        assert_eq!(payload[..8], 1u64.to_le_bytes());
+        assert_eq!(
+            crate::codec::deserialize::<u64>(&payload[8..], u64::MAX).unwrap(),
+            200
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_multiplexed_refuses_connections_over_limit() {
+        use crate::SocketExt;
+
+        static REFUSED: AtomicBool = AtomicBool::new(false);
+        let socket = "/tmp/ipc-util-test-serve-multiplexed-limit.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .max_multiplexed_connections(1)
+            .on_connection_error(|e| {
+                REFUSED.store(
+                    e.kind() == io::ErrorKind::ConnectionRefused,
+                    Ordering::SeqCst,
+                );
+                ErrorAction::Continue
+            })
+            .serve_multiplexed(|request: u64| Some(request))
+            .expect("Failed to start server");
+
+        let _first = crate::ipc_client_connect(socket).unwrap();
+
+        // The first connection holds the only slot, so the second is closed straight away.
+        let mut second = crate::ipc_client_connect(socket).unwrap();
+        assert!(second.read_frame().is_err());
+        assert!(REFUSED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_max_connection_duration_closes_busy_connection() {
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        let socket = "/tmp/ipc-util-test-max-connection-duration.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .max_connection_duration(Duration::from_millis(100))
+            .on_warning(|_| WARNED.store(true, Ordering::SeqCst))
+            .serve_iter(|interval: u64| {
+                (0..).inspect(move |_| std::thread::sleep(Duration::from_millis(interval)))
+            })
+            .expect("Failed to start server");
+
+        // The responses never stop on their own, so the stream only ends once the server closes the connection.
+        let started = Instant::now();
+        let responses = crate::send_ipc_query_stream::<u64, u32>(socket, &10)
+            .unwrap()
+            .map_while(Result::ok)
+            .count();
+
+        assert!(responses > 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(WARNED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_max_connection_duration_cuts_off_blocked_iterator() {
+        let socket = "/tmp/ipc-util-test-max-connection-duration-blocked.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .max_connection_duration(Duration::from_millis(100))
+            .on_warning(|_| {})
+            .serve_iter(|()| {
+                std::iter::from_fn(|| -> Option<u32> {
+                    std::thread::sleep(Duration::from_secs(60));
+                    None
+                })
+            })
+            .expect("Failed to start server");
+
+        // The iterator never yields, so only shutting down the connection can end the stream.
+        let started = Instant::now();
+        let responses = crate::send_ipc_query_stream::<(), u32>(socket, &())
+            .unwrap()
+            .map_while(Result::ok)
+            .count();
+
+        assert_eq!(responses, 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_expired_request_deadline_skips_handler() {
+        use crate::{IpcClientError, SocketExt};
+        use std::sync::atomic::AtomicUsize;
+
+        static HANDLED: AtomicUsize = AtomicUsize::new(0);
+        let socket = "/tmp/ipc-util-test-request-deadline.sock";
+        let framing = Framing::new().deadlines();
+
+        let _server = IpcServerBuilder::new(socket)
+            .framing(framing)
+            .serve(|request: u32| {
+                HANDLED.fetch_add(1, Ordering::SeqCst);
+                Some(request * 2)
+            })
+            .expect("Failed to start server");
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let response: u32 =
+            crate::send_ipc_query_with_deadline(socket, &framing, &4, later).unwrap();
+        assert_eq!(response, 8);
+
+        let earlier = SystemTime::now() - Duration::from_secs(1);
+        assert!(matches!(
+            crate::send_ipc_query_with_deadline::<u32, u32>(socket, &framing, &5, earlier),
+            Err(IpcClientError::ReadError(
+                IpcStreamReadError::DeadlineExceeded
+            ))
+        ));
+
+        // Requests without a deadline never expire.
+        let mut stream = crate::ipc_client_connect(socket).unwrap();
+        stream.write_serde_framed(&6u32, &framing).unwrap();
+        assert_eq!(stream.read_serde_framed::<u32>(&framing).unwrap(), 12);
+
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_can_bind() {
+        let socket = "/tmp/ipc-util-test-can-bind.sock";
+        let _ = std::fs::remove_file(socket);
+        let _ = std::fs::remove_file(lock_file_path(socket));
+
+        IpcServerBuilder::new(socket).can_bind().unwrap();
+        assert!(!std::path::Path::new(socket).exists());
+
+        IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::LockFile)
+            .can_bind()
+            .unwrap();
+        assert!(!lock_file_path(socket).exists());
+
+        // A stale socket file can be bound by deleting it, but the check leaves it alone.
+        drop(UnixListener::bind(socket).expect("Failed to create stale socket"));
+        IpcServerBuilder::new(socket).can_bind().unwrap();
+        assert!(std::path::Path::new(socket).exists());
+
+        assert!(matches!(
+            IpcServerBuilder::new(socket)
+                .stale_socket_policy(StaleSocketPolicy::Error)
+                .can_bind(),
+            Err(IpcServerError::BindError { .. })
+        ));
+
+        let _server = IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::LockFile)
+            .on_warning(|_| {})
+            .listen(|_| {})
+            .expect("Failed to start server");
+
+        assert!(matches!(
+            IpcServerBuilder::new(socket)
+                .instance_check(InstanceCheck::LockFile)
+                .can_bind(),
+            Err(IpcServerError::AlreadyInUseError)
+        ));
+        assert!(matches!(
+            crate::can_bind(""),
+            Err(IpcServerError::InvalidName { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_buffer_sizes() {
+        use crate::LocalSocketExt;
+
+        let socket = "/tmp/ipc-util-test-buffer-sizes.sock";
+        let size = 256 * 1024;
+
+        let _server = IpcServerBuilder::new(socket)
+            .recv_buffer_size(size)
+            .send_buffer_size(size)
+            .serve_with_stream(|_: (), stream: &mut LocalSocketStream| {
+                Some((
+                    stream.recv_buffer_size().ok(),
+                    stream.send_buffer_size().ok(),
+                ))
+            })
+            .expect("Failed to start server");
+
+        let (recv, send): (Option<usize>, Option<usize>) =
+            crate::send_ipc_query(socket, &()).unwrap();
+
+        // The kernel may round the sizes up, or double them on Linux.
+        assert!(recv.unwrap() >= size);
+        assert!(send.unwrap() >= size);
+
+        let stream = crate::ipc_client_connect(socket).unwrap();
+        stream.set_send_buffer_size(size).unwrap();
+        assert!(stream.send_buffer_size().unwrap() >= size);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_live_socket_is_never_deleted() {
+        let socket = "/tmp/ipc-util-test-live-socket.sock";
+        let _ = std::fs::remove_file(socket);
+
+        // Whether or not the process count finds it, a server accepting connections keeps its socket.
+        let _live = UnixListener::bind(socket).expect("Failed to bind live socket");
+
+        assert!(matches!(
+            IpcServerBuilder::new(socket).bind_socket().map(|_| ()),
+            Err(IpcServerError::AlreadyInUseError)
+        ));
+        assert!(matches!(
+            IpcServerBuilder::new(socket).can_bind(),
+            Err(IpcServerError::AlreadyInUseError)
+        ));
+        assert!(std::path::Path::new(socket).exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_skip_instance_check() {
+        let socket = "/tmp/ipc-util-test-skip-instance.sock";
+        let pid_file = pid_file_path(socket);
+
+        // A PID file naming a running process would normally reject the bind.
+        std::fs::write(&pid_file, format!("{}\n", std::process::id())).unwrap();
+        let _ = std::fs::remove_file(socket);
+        drop(UnixListener::bind(socket).expect("Failed to create stale socket"));
+
+        let builder = || {
+            IpcServerBuilder::new(socket)
+                .on_warning(|_| {})
+                .instance_check(InstanceCheck::PidFile)
+        };
+
+        assert!(matches!(
+            builder().bind_socket().map(|_| ()),
+            Err(IpcServerError::AlreadyInUseError)
+        ));
+
+        let bound = builder()
+            .skip_instance_check(true)
+            .bind_socket()
+            .expect("Failed to bind");
+        assert!(bound.instance_guard.is_none());
+
+        std::fs::remove_file(pid_file).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_idle_timeout_closes_silent_connection() {
+        use std::io::{Read, Write};
+
+        let socket = "/tmp/ipc-util-test-idle-timeout.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .idle_timeout(Duration::from_millis(100))
+            .serve(|request: String| Some(request))
+            .expect("Failed to start server");
+
+        let mut stream = LocalSocketStream::connect(socket).expect("Failed to connect");
+        crate::utils::set_read_timeout(&stream, Some(Duration::from_secs(5))).unwrap();
+
+        // Send part of a length prefix and then go silent.
+        stream.write_all(&[1, 0]).unwrap();
+
+        let mut buf = Vec::new();
+        let read = stream
+            .read_to_end(&mut buf)
+            .expect("Connection was not closed");
+        assert_eq!(read, 0);
+
+        let response: String = crate::send_ipc_query(socket, &"hello").unwrap();
+        assert_eq!(response, "hello");
+    }
+
+    #[test]
+    fn test_pid_file_rejects_second_instance() {
+        let socket = "/tmp/ipc-util-test-pid-file.sock";
+        let pid_file = pid_file_path(socket);
+
+        // A PID file naming a process that isn't running is stale and gets replaced.
+        std::fs::write(&pid_file, format!("{}\n", u32::MAX)).unwrap();
+
+        let server = IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::PidFile)
+            .bind()
+            .expect("Failed to start first server");
+
+        let contents = std::fs::read_to_string(&pid_file).unwrap();
+        assert_eq!(contents.trim(), std::process::id().to_string());
+
+        let result = IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::PidFile)
+            .bind();
+        assert!(matches!(result, Err(IpcServerError::AlreadyInUseError)));
+
+        drop(server);
+        assert!(!pid_file.exists());
+    }
+
+    #[test]
+    fn test_lock_file_rejects_second_instance() {
+        let socket = "/tmp/ipc-util-test-lock-file.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::LockFile)
+            .listen(|_| {})
+            .expect("Failed to start first server");
+
+        let result = IpcServerBuilder::new(socket)
+            .instance_check(InstanceCheck::LockFile)
+            .listen(|_| {});
+
+        assert!(matches!(result, Err(IpcServerError::AlreadyInUseError)));
+
+        // Failing to create the lock file is reported as such, rather than as a problem with the socket.
+        let result = IpcServerBuilder::new("/tmp/ipc-util-test-missing-dir/lock-file.sock")
+            .instance_check(InstanceCheck::LockFile)
+            .listen(|_| {});
+
+        assert!(matches!(
+            result,
+            Err(IpcServerError::LockFileError { path, .. }) if path.ends_with("lock-file.sock.lock")
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_abstract_namespace_in_use() {
+        let socket = "ipc-util-test-abstract.sock";
+
+        let _server = IpcServerBuilder::new(socket)
+            .abstract_namespace(true)
+            .listen(|_| {})
+            .expect("Failed to start first server");
+
+        let result = IpcServerBuilder::new(socket)
+            .abstract_namespace(true)
+            .listen(|_| {});
+
+        assert!(matches!(result, Err(IpcServerError::AlreadyInUseError)));
+        assert!(!std::path::Path::new(socket).exists());
+    }
+}
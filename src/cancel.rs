@@ -0,0 +1,157 @@
+use crate::{ipc_client_connect, IpcClientError, SocketExt};
+use interprocess::local_socket::LocalSocketStream;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A handle for abandoning queries from another thread, such as when the user cancels an operation in a UI.
+///
+/// Clones share the same state, so one clone can be passed to the thread sending the query and another kept to cancel
+/// it. Once cancelled, a token stays cancelled, and queries sent with it fail straight away, so create a new token for
+/// each operation that can be cancelled on its own.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<Mutex<CancelState>>,
+}
+
+/// The connections of the queries in flight, which are shut down once the token is cancelled.
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: bool,
+    next_id: u64,
+    streams: HashMap<u64, LocalSocketStream>,
+}
+
+impl CancellationToken {
+    /// Creates a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, shutting down the connections of the queries sent with it, so they fail with
+    /// `IpcClientError::Cancelled` instead of waiting for their response.
+    pub fn cancel(&self) {
+        let mut state = self.lock();
+        state.cancelled = true;
+
+        for (_, stream) in state.streams.drain() {
+            let _ = crate::utils::shutdown(&stream, Shutdown::Both);
+        }
+    }
+
+    /// Checks whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    /// Keeps a handle to a stream until the returned guard is dropped, so cancelling can shut it down.
+    ///
+    /// Fails with `IpcClientError::Cancelled` if the token was already cancelled.
+    fn register(&self, stream: LocalSocketStream) -> Result<Registration<'_>, IpcClientError> {
+        let mut state = self.lock();
+
+        if state.cancelled {
+            return Err(IpcClientError::Cancelled);
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.streams.insert(id, stream);
+
+        Ok(Registration { token: self, id })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CancelState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Removes a stream from its token once the query is done with it.
+struct Registration<'a> {
+    token: &'a CancellationToken,
+    id: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.token.lock().streams.remove(&self.id);
+    }
+}
+
+/// Like `send_ipc_query`, but the query can be abandoned from another thread by cancelling the token.
+///
+/// Cancelling shuts down the connection, which wakes up the blocked write or read, and the query fails with
+/// `IpcClientError::Cancelled`. The server sees the connection close, and whatever it responds is discarded.
+/// If the token is already cancelled, this fails without connecting.
+pub fn send_ipc_query_cancellable<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+    cancel: &CancellationToken,
+) -> Result<TResponse, IpcClientError> {
+    if cancel.is_cancelled() {
+        return Err(IpcClientError::Cancelled);
+    }
+
+    let socket_name = socket_name.as_ref();
+
+    let mut stream = ipc_client_connect(socket_name)?;
+    let handle = crate::utils::try_clone(&stream).map_err(IpcClientError::connect(socket_name))?;
+    let _registration = cancel.register(handle)?;
+
+    let result = stream
+        .write_serde(request)
+        .map_err(IpcClientError::from)
+        .and_then(|()| Ok(stream.read_serde()?));
+
+    // A query that fails once the token is cancelled failed because its connection was shut down.
+    match result {
+        Err(_) if cancel.is_cancelled() => Err(IpcClientError::Cancelled),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpcServerBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_abandons_blocked_query() {
+        let socket = "/tmp/ipc-util-test-cancellable.sock";
+        let (sender, received) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+
+        // The server never responds, keeping each connection open until the next one arrives.
+        IpcServerBuilder::new(socket)
+            .listen_with_state(None, move |held, mut stream| {
+                if let Ok(request) = stream.read_serde::<u32>() {
+                    let _ = sender.lock().unwrap().send(request);
+                }
+
+                *held = Some(stream);
+            })
+            .expect("Failed to start server");
+
+        let cancel = CancellationToken::new();
+        let query = std::thread::spawn({
+            let cancel = cancel.clone();
+            move || send_ipc_query_cancellable::<u32, u32>(socket, &7, &cancel)
+        });
+
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap(), 7);
+        cancel.cancel();
+
+        assert!(matches!(
+            query.join().unwrap(),
+            Err(IpcClientError::Cancelled)
+        ));
+        assert!(matches!(
+            send_ipc_query_cancellable::<u32, u32>(socket, &8, &cancel),
+            Err(IpcClientError::Cancelled)
+        ));
+        assert!(cancel.lock().streams.is_empty());
+    }
+}
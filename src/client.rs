@@ -0,0 +1,165 @@
+use crate::{
+    ipc_client_connect, send_ipc_message, send_ipc_message_acked, send_ipc_query,
+    send_ipc_query_stream, send_ipc_query_typed, Framing, IpcClientError, IpcResponseStream, Query,
+    SocketExt,
+};
+use interprocess::local_socket::LocalSocketStream;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A handle for making requests to a socket, which can be cloned cheaply and shared between threads.
+///
+/// The client holds no connection. Each request opens its own connection and closes it once done, exactly like
+/// the `send_ipc_*` functions, so clones can make requests concurrently without coordinating with each other.
+/// Use `IpcClientPool` instead to reuse connections across requests.
+#[derive(Debug, Clone)]
+pub struct IpcClient {
+    socket: Arc<str>,
+}
+
+impl IpcClient {
+    /// Creates a client for the given socket. Nothing is connected until a request is made.
+    pub fn new(socket: impl Into<String>) -> Self {
+        Self {
+            socket: Arc::from(socket.into()),
+        }
+    }
+
+    /// Gets the name of the socket the client connects to.
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+
+    /// Connects to the socket and returns the stream. See `ipc_client_connect`.
+    pub fn connect(&self) -> Result<LocalSocketStream, IpcClientError> {
+        ipc_client_connect(&*self.socket)
+    }
+
+    /// Sends a message without waiting for a response. See `send_ipc_message`.
+    pub fn send<TRequest: Serialize>(&self, request: &TRequest) -> Result<(), IpcClientError> {
+        send_ipc_message(&*self.socket, request)
+    }
+
+    /// Sends a message and waits for the server to acknowledge it. See `send_ipc_message_acked`.
+    pub fn send_acked<TRequest: Serialize>(
+        &self,
+        request: &TRequest,
+    ) -> Result<(), IpcClientError> {
+        send_ipc_message_acked(&*self.socket, request)
+    }
+
+    /// Sends a request and reads the response. See `send_ipc_query`.
+    pub fn query<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        send_ipc_query(&*self.socket, request)
+    }
+
+    /// Sends a query and reads its response, whose type is paired with the query's. See `send_ipc_query_typed`.
+    pub fn query_typed<Q: Query>(&self, query: &Q) -> Result<Q::Response, IpcClientError> {
+        send_ipc_query_typed(&*self.socket, query)
+    }
+
+    /// Sends a request and returns an iterator over the responses. See `send_ipc_query_stream`.
+    pub fn query_stream<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &self,
+        request: &TRequest,
+    ) -> Result<IpcResponseStream<TResponse>, IpcClientError> {
+        send_ipc_query_stream(&*self.socket, request)
+    }
+}
+
+/// A client that reads each response into a buffer it keeps, so responses can borrow from it instead of allocating.
+///
+/// `query_ref` deserializes the response in place, so types with `&str` or `&[u8]` fields point into the buffer
+/// rather than copying out of it. The response borrows the client, so it can't outlive the next query. The buffer
+/// keeps its capacity between queries, so once it has grown to fit the largest response, reading doesn't allocate.
+///
+/// Like `IpcClient`, each query opens its own connection.
+#[derive(Debug)]
+pub struct BorrowingClient {
+    socket: String,
+    buffer: Vec<u8>,
+}
+
+impl BorrowingClient {
+    /// Creates a client for the given socket, with an empty response buffer.
+    pub fn new(socket: impl Into<String>) -> Self {
+        Self {
+            socket: socket.into(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Gets the name of the socket the client connects to.
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+
+    /// Sends a request and reads the response, which borrows from the client's buffer until the next query.
+    pub fn query_ref<'a, TRequest: Serialize, TResponse: Deserialize<'a>>(
+        &'a mut self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        let mut stream = ipc_client_connect(&self.socket)?;
+        stream.write_serde(request)?;
+
+        let framing = Framing::default();
+        let len = framing.read_len(&mut stream)?;
+        framing.read_payload_into(&mut stream, len, &mut self.buffer)?;
+
+        let response = framing.deserialize(&self.buffer)?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::start_ipc_server;
+
+    #[test]
+    fn test_client_clones_query_concurrently() {
+        let socket = "/tmp/ipc-util-test-client.sock";
+
+        start_ipc_server(socket, |request: u32| Some(request * 3), None)
+            .expect("Failed to start server");
+
+        let client = IpcClient::new(socket);
+
+        let threads = (0..4u32)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || client.query::<u32, u32>(&i).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        for (i, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap(), i as u32 * 3);
+        }
+
+        assert_eq!(client.socket(), socket);
+    }
+
+    #[test]
+    fn test_borrowing_client_query_ref() {
+        let socket = "/tmp/ipc-util-test-borrowing-client.sock";
+
+        start_ipc_server(
+            socket,
+            |request: String| Some((request.len(), request.to_uppercase())),
+            None,
+        )
+        .expect("Failed to start server");
+
+        let mut client = BorrowingClient::new(socket);
+
+        let (len, upper): (usize, &str) = client.query_ref(&"hello".to_string()).unwrap();
+        assert_eq!((len, upper), (5, "HELLO"));
+
+        let (len, upper): (usize, &str) = client.query_ref(&"hi".to_string()).unwrap();
+        assert_eq!((len, upper), (2, "HI"));
+    }
+}
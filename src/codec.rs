@@ -0,0 +1,172 @@
+//! The serialization format of typed messages, chosen at compile time by the `codec-*` features.
+//!
+//! `codec-bincode` is enabled by default. Enabling `codec-json` or `codec-msgpack` takes its place, so either can be
+//! picked without turning off the default features, but at most one of them can be enabled. Turning off the default
+//! features as well leaves bincode out of the dependency tree. Errors are reported as `CodecError`, whose variants are
+//! those of the enabled codecs.
+
+use crate::CodecError;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+#[cfg(not(any(
+    feature = "codec-bincode",
+    feature = "codec-json",
+    feature = "codec-msgpack"
+)))]
+compile_error!(
+    "ipc_util needs a codec to serialize messages: enable one of the `codec-bincode`, `codec-json` or `codec-msgpack` features"
+);
+
+#[cfg(all(feature = "codec-json", feature = "codec-msgpack"))]
+compile_error!(
+    "the `codec-json` and `codec-msgpack` features of ipc_util can't be enabled together, since both sides of a \
+     connection must use the same codec"
+);
+
+/// The name of the codec typed messages are serialized with, such as `"bincode"`.
+pub const CODEC: &str = if cfg!(feature = "codec-json") {
+    "json"
+} else if cfg!(feature = "codec-msgpack") {
+    "msgpack"
+} else {
+    "bincode"
+};
+
+/// Serializes a message into a new buffer.
+pub(crate) fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CodecError> {
+    let mut bytes = Vec::new();
+    serialize_into(&mut bytes, value)?;
+    Ok(bytes)
+}
+
+/// Serializes a message straight into the writer.
+#[cfg(feature = "codec-json")]
+pub(crate) fn serialize_into<W: Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+) -> Result<(), CodecError> {
+    Ok(serde_json::to_writer(writer, value)?)
+}
+
+/// Serializes a message straight into the writer.
+#[cfg(all(feature = "codec-msgpack", not(feature = "codec-json")))]
+pub(crate) fn serialize_into<W: Write, T: Serialize + ?Sized>(
+    mut writer: W,
+    value: &T,
+) -> Result<(), CodecError> {
+    // Structs are written as maps with their field names, for peers written in other languages.
+    Ok(rmp_serde::encode::write_named(&mut writer, value)?)
+}
+
+/// Serializes a message straight into the writer.
+#[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+pub(crate) fn serialize_into<W: Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+) -> Result<(), CodecError> {
+    Ok(bincode::serialize_into(writer, value)?)
+}
+
+/// Gets the size in bytes that a message will have once serialized.
+#[cfg(any(feature = "codec-json", feature = "codec-msgpack"))]
+pub(crate) fn serialized_size<T: Serialize + ?Sized>(value: &T) -> Result<u64, CodecError> {
+    let mut counter = ByteCounter(0);
+    serialize_into(&mut counter, value)?;
+    Ok(counter.0)
+}
+
+/// Gets the size in bytes that a message will have once serialized.
+#[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+pub(crate) fn serialized_size<T: Serialize + ?Sized>(value: &T) -> Result<u64, CodecError> {
+    Ok(bincode::serialized_size(value)?)
+}
+
+/// Deserializes a message from a payload.
+///
+/// The limit bounds how many bytes bincode may allocate for the message, since its length prefixes can claim more
+/// than the payload holds. The other codecs can't, as they read everything from the payload itself, so they ignore it.
+#[cfg(feature = "codec-json")]
+pub(crate) fn deserialize<'a, T: Deserialize<'a>>(
+    payload: &'a [u8],
+    _limit: u64,
+) -> Result<T, CodecError> {
+    Ok(serde_json::from_slice(payload)?)
+}
+
+/// Deserializes a message from a payload.
+///
+/// The limit bounds how many bytes bincode may allocate for the message, since its length prefixes can claim more
+/// than the payload holds. The other codecs can't, as they read everything from the payload itself, so they ignore it.
+#[cfg(all(feature = "codec-msgpack", not(feature = "codec-json")))]
+pub(crate) fn deserialize<'a, T: Deserialize<'a>>(
+    payload: &'a [u8],
+    _limit: u64,
+) -> Result<T, CodecError> {
+    Ok(rmp_serde::from_slice(payload)?)
+}
+
+/// Deserializes a message from a payload.
+///
+/// The limit bounds how many bytes bincode may allocate for the message, since its length prefixes can claim more
+/// than the payload holds. The other codecs can't, as they read everything from the payload itself, so they ignore it.
+#[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+pub(crate) fn deserialize<'a, T: Deserialize<'a>>(
+    payload: &'a [u8],
+    limit: u64,
+) -> Result<T, CodecError> {
+    use bincode::Options;
+
+    let options = bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit);
+
+    // `Options::deserialize` drops the limit for slices, since their length already bounds what's read,
+    // so the deserializer is created directly to keep it.
+    let mut deserializer = bincode::Deserializer::from_slice(payload, options);
+    Ok(T::deserialize(&mut deserializer)?)
+}
+
+/// A writer that only counts the bytes written to it.
+#[cfg(any(feature = "codec-json", feature = "codec-msgpack"))]
+struct ByteCounter(u64);
+
+#[cfg(any(feature = "codec-json", feature = "codec-msgpack"))]
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message {
+        name: String,
+        values: BTreeMap<String, Vec<u32>>,
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        let message = Message {
+            name: "config".to_string(),
+            values: BTreeMap::from([("ports".to_string(), vec![80, 443])]),
+        };
+
+        let bytes = serialize(&message).unwrap();
+        assert_eq!(serialized_size(&message).unwrap(), bytes.len() as u64);
+        assert_eq!(deserialize::<Message>(&bytes, u64::MAX).unwrap(), message);
+
+        #[cfg(feature = "codec-json")]
+        assert!(bytes.starts_with(br#"{"name":"config""#));
+    }
+}
@@ -0,0 +1,81 @@
+use interprocess::local_socket::LocalSocketStream;
+use std::time::Instant;
+
+/// Details about an accepted connection, passed to the handlers of `listen_with_info` and `serve_with_info`.
+///
+/// Everything is looked up once the connection is accepted, before the handler runs, so the peer details describe the
+/// process that connected even if it has exited since.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// The number of connections the server had handed to its handler before this one, counting up from zero.
+    pub id: u64,
+    /// When the connection was accepted.
+    pub accepted_at: Instant,
+    /// The process id of the peer, if the platform can tell.
+    pub peer_pid: Option<u32>,
+    /// The effective user id of the peer, if the platform can tell.
+    #[cfg(unix)]
+    pub peer_uid: Option<u32>,
+    /// The effective group id of the peer, if the platform can tell.
+    #[cfg(unix)]
+    pub peer_gid: Option<u32>,
+}
+
+impl ConnectionInfo {
+    /// Gathers the details of a connection that was just accepted.
+    pub(crate) fn new(stream: &LocalSocketStream, id: u64) -> Self {
+        #[cfg(unix)]
+        let credentials = crate::utils::peer_credentials(stream).ok();
+
+        Self {
+            id,
+            accepted_at: Instant::now(),
+            peer_pid: crate::utils::peer_pid(stream).ok(),
+            #[cfg(unix)]
+            peer_uid: credentials.map(|(uid, _)| uid),
+            #[cfg(unix)]
+            peer_gid: credentials.map(|(_, gid)| gid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpcServerBuilder;
+
+    #[test]
+    fn test_handlers_receive_connection_info() {
+        let socket = "/tmp/ipc-util-test-connection-info.sock";
+        let (sender, infos) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+
+        IpcServerBuilder::new(socket)
+            .serve_with_info(move |request: u32, info: &ConnectionInfo| {
+                sender.lock().unwrap().send(info.clone()).unwrap();
+                Some(request + info.id as u32)
+            })
+            .expect("Failed to start server");
+
+        let before = Instant::now();
+
+        for id in 0..3 {
+            assert_eq!(
+                crate::send_ipc_query::<u32, u32>(socket, &10).unwrap(),
+                10 + id
+            );
+        }
+
+        let infos = infos.try_iter().collect::<Vec<_>>();
+        assert!(infos.iter().map(|info| info.id).eq(0..3));
+        assert!(infos.iter().all(|info| info.accepted_at >= before));
+
+        #[cfg(target_os = "linux")]
+        for info in &infos {
+            assert_eq!(info.peer_pid, Some(std::process::id()));
+            assert_eq!(info.peer_uid, Some(unsafe { libc::geteuid() }));
+            assert_eq!(info.peer_gid, Some(unsafe { libc::getegid() }));
+        }
+    }
+}
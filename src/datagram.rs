@@ -0,0 +1,146 @@
+//! Datagram-based messaging, for small messages on Unix.
+//!
+//! Datagram sockets preserve message boundaries natively, so messages are sent without a length prefix and each one
+//! takes a single syscall to send or receive. The tradeoff is that every message must fit in a single datagram:
+//! the listener drops datagrams larger than its configured maximum size, and the OS rejects datagrams larger than the
+//! socket's send buffer (around 200KiB by default on Linux). Datagrams are also one-way, so there are no responses.
+//!
+//! Path-based names bind a socket file, like stream sockets. On Linux, names starting with `@` bind to the abstract namespace.
+
+use crate::utils::current_process_instance_count;
+use crate::{is_namespaced, ErrorAction, IpcClientError, IpcServerError, IpcStreamWriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::thread::JoinHandle;
+
+/// Serializes an object and sends it as a single datagram to the socket.
+pub fn send_ipc_datagram<TRequest: Serialize>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+) -> Result<(), IpcClientError> {
+    let bytes = crate::codec::serialize(request).map_err(IpcStreamWriteError::from)?;
+
+    let socket_name = socket_name.as_ref();
+
+    let socket = UnixDatagram::unbound().map_err(IpcClientError::connect(socket_name))?;
+    let addr = socket_addr(socket_name).map_err(IpcClientError::connect(socket_name))?;
+
+    socket
+        .send_to_addr(&bytes, &addr)
+        .map_err(IpcStreamWriteError::from)?;
+
+    Ok(())
+}
+
+/// Binds a datagram socket and spawns a thread that deserializes each received datagram and invokes `on_message` with it.
+///
+/// Datagrams larger than `max_size` bytes, and datagrams that fail to deserialize, are dropped.
+/// If receiving fails, `on_receive_error` decides whether to keep listening.
+/// Stale socket files are handled the same way as in `start_ipc_listener`.
+pub fn start_ipc_datagram_listener<TRequest: DeserializeOwned, F: Fn(TRequest) + Send + 'static>(
+    socket: impl Into<String>,
+    max_size: usize,
+    on_message: F,
+    on_receive_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    let socket = socket.into();
+    let datagram = bind_datagram(&socket)?;
+
+    std::thread::Builder::new()
+        .name(format!("ipc datagram server '{socket}'"))
+        .spawn(move || {
+            // One extra byte to detect datagrams that were truncated because they exceed `max_size`.
+            let mut buffer = vec![0; max_size + 1];
+
+            loop {
+                let size = match datagram.recv(&mut buffer) {
+                    Ok(size) => size,
+                    Err(e) => match on_receive_error.map(|on_receive_error| on_receive_error(e)) {
+                        Some(ErrorAction::Stop) => break,
+                        Some(ErrorAction::Continue) | None => continue,
+                    },
+                };
+
+                if size > max_size {
+                    continue;
+                }
+
+                // A datagram's length prefixes can't claim more than it holds for a message to deserialize anyway.
+                if let Ok(message) = crate::codec::deserialize(&buffer[..size], max_size as u64) {
+                    on_message(message);
+                }
+            }
+        })
+        .map_err(IpcServerError::ThreadSpawnError)
+}
+
+fn bind_datagram(socket: &str) -> Result<UnixDatagram, IpcServerError> {
+    let addr = socket_addr(socket).map_err(IpcServerError::bind(socket))?;
+
+    match UnixDatagram::bind_addr(&addr) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse && !is_namespaced(socket) => {
+            if current_process_instance_count() > 1 {
+                return Err(IpcServerError::AlreadyInUseError);
+            }
+
+            crate::utils::default_warning(
+                "Socket file already in use, deleting it and trying again.",
+            );
+
+            std::fs::remove_file(socket).map_err(IpcServerError::FileError)?;
+            UnixDatagram::bind_addr(&addr).map_err(IpcServerError::bind(socket))
+        }
+        Err(e) => Err(IpcServerError::bind(socket)(e)),
+        Ok(datagram) => Ok(datagram),
+    }
+}
+
+fn socket_addr(socket: &str) -> io::Result<SocketAddr> {
+    match socket.strip_prefix('@') {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Some(name) => {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+
+            SocketAddr::from_abstract_name(name)
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "namespaced datagram sockets are only supported on Linux",
+        )),
+        None => SocketAddr::from_pathname(socket),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_datagram_round_trip() {
+        let socket = "/tmp/ipc-util-test-datagram.sock";
+        let (sender, receiver) = mpsc::channel();
+
+        start_ipc_datagram_listener(
+            socket,
+            64,
+            move |message: String| sender.send(message).unwrap(),
+            None,
+        )
+        .expect("Failed to start listener");
+
+        send_ipc_datagram(socket, &"x".repeat(100)).expect("Failed to send datagram");
+        send_ipc_datagram(socket, &"hello".to_string()).expect("Failed to send datagram");
+
+        // The oversized datagram is dropped, so the first message received is the small one.
+        let received = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received, "hello");
+    }
+}
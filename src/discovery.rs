@@ -0,0 +1,146 @@
+use std::path::Path;
+
+/// Finds the names of sockets that start with the given prefix, so clients can discover servers registered by convention.
+///
+/// The returned names can be passed directly to the client functions. Sockets are only found if they currently exist,
+/// but a stale socket file left behind by a crashed server will still be returned, so connecting may still fail.
+///
+/// # Platform-specific behavior
+///
+/// - For path-based prefixes on Unix (e.g. `/tmp/my-app-plugin-`), the prefix's directory is scanned for socket files
+///   whose file name starts with the rest of the prefix.
+/// - For namespaced prefixes on Linux (e.g. `@my-app-plugin-`), listening abstract sockets are read from `/proc/net/unix`.
+///   Other Unix platforms have no abstract namespace, so nothing is returned.
+/// - On Windows, the named pipes in `\\.\pipe\` whose name starts with the prefix are returned.
+pub fn discover_sockets(prefix: &str) -> Vec<String> {
+    let mut names = platform::discover_sockets(prefix);
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+
+    pub fn discover_sockets(prefix: &str) -> Vec<String> {
+        match prefix.strip_prefix('@') {
+            Some(_) => discover_namespaced(prefix),
+            None => discover_paths(prefix),
+        }
+    }
+
+    fn discover_paths(prefix: &str) -> Vec<String> {
+        let path = Path::new(prefix);
+
+        // A prefix ending with a separator matches everything in that directory.
+        let (dir, file_prefix) = if prefix.ends_with('/') {
+            (path, "")
+        } else {
+            let file_prefix = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            let dir = path.parent().unwrap_or(Path::new("."));
+            (dir, file_prefix)
+        };
+
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|ty| ty.is_socket()))
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(file_prefix))
+            .map(|entry| {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                prefix[..prefix.len() - file_prefix.len()].to_string() + &file_name
+            })
+            .collect()
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn discover_namespaced(prefix: &str) -> Vec<String> {
+        // The flag set on listening sockets in /proc/net/unix (__SO_ACCEPTCON).
+        const LISTENING: &str = "00010000";
+
+        let Ok(table) = std::fs::read_to_string("/proc/net/unix") else {
+            return Vec::new();
+        };
+
+        table
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let columns = line.split_whitespace().collect::<Vec<_>>();
+                match columns[..] {
+                    [_, _, _, flags, _, _, _, path, ..] if flags == LISTENING => Some(path),
+                    _ => None,
+                }
+            })
+            // Abstract names may be padded with NUL bytes, which are shown as '@'.
+            .map(|path| format!("@{}", path[1..].trim_end_matches('@')))
+            .filter(|path| path.starts_with(prefix))
+            .collect()
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn discover_namespaced(_prefix: &str) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+
+    pub fn discover_sockets(prefix: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(Path::new(r"\\.\pipe\")) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(prefix))
+            .collect()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::IpcServerBuilder;
+
+    #[test]
+    fn test_discover_path_sockets() {
+        let _server = IpcServerBuilder::new("/tmp/ipc-util-test-discover-a.sock")
+            .listen(|_| {})
+            .expect("Failed to start server");
+
+        let names = discover_sockets("/tmp/ipc-util-test-discover-");
+
+        assert!(names.contains(&"/tmp/ipc-util-test-discover-a.sock".to_string()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_discover_namespaced_sockets() {
+        let _server = IpcServerBuilder::new("@ipc-util-test-discover-b.sock")
+            .listen(|_| {})
+            .expect("Failed to start server");
+
+        let names = discover_sockets("@ipc-util-test-discover-");
+
+        assert_eq!(names, vec!["@ipc-util-test-discover-b.sock".to_string()]);
+    }
+}
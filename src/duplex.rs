@@ -0,0 +1,216 @@
+use interprocess::local_socket::LocalSocketStream;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// The default capacity of each of the read and write buffers, matching the standard library's `BufReader` and `BufWriter`.
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// A buffered connection that implements both `Read` and `Write`, for use with libraries that work with generic streams.
+///
+/// Since it implements `SocketExt` as well, it also cuts down on syscalls when reading messages. Reading from a bare
+/// stream takes one read for the header of each message and another for its payload, while through an `IpcDuplex`
+/// both, and any messages already sent after it, are usually taken from the stream in a single read.
+///
+/// Reads are buffered like a `BufReader`, and writes are buffered like a `BufWriter`. Buffered writes are flushed
+/// before every read, since the peer usually needs the whole request before it responds, and when the duplex is
+/// dropped, so written data isn't lost. Errors from flushing on drop are ignored, so call `flush` to handle them.
+///
+/// Created with `ipc_client_connect_buffered` or `IpcDuplex::new`. Wraps a `LocalSocketStream` by default,
+/// but works with any stream, such as the `TcpStream`s of the TCP backend.
+pub struct IpcDuplex<S: Read + Write = LocalSocketStream> {
+    reader: BufReader<S>,
+    write_buffer: Vec<u8>,
+    write_capacity: usize,
+}
+
+impl<S: Read + Write> IpcDuplex<S> {
+    /// Wraps a connected stream in read and write buffers with the default capacity.
+    pub fn new(stream: S) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, DEFAULT_CAPACITY, stream)
+    }
+
+    /// Wraps a connected stream in read and write buffers with the given capacities, in bytes.
+    pub fn with_capacity(read_capacity: usize, write_capacity: usize, stream: S) -> Self {
+        Self {
+            reader: BufReader::with_capacity(read_capacity, stream),
+            write_buffer: Vec::with_capacity(write_capacity),
+            write_capacity,
+        }
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        self.reader.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    ///
+    /// Reading from or writing to it directly bypasses the buffers, which may reorder or lose data.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.reader.get_mut()
+    }
+
+    /// Writes out the buffered data, retrying on interrupts.
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        let mut written = 0;
+
+        while written < self.write_buffer.len() {
+            match self.reader.get_mut().write(&self.write_buffer[written..]) {
+                Ok(0) => {
+                    self.write_buffer.drain(..written);
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.write_buffer.drain(..written);
+                    return Err(e);
+                }
+            }
+        }
+
+        self.write_buffer.clear();
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for IpcDuplex<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.flush()?;
+        self.reader.read(buf)
+    }
+}
+
+impl<S: Read + Write> BufRead for IpcDuplex<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.flush()?;
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount);
+    }
+}
+
+impl<S: Read + Write> Write for IpcDuplex<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.write_buffer.len() + buf.len() > self.write_capacity {
+            self.flush_buffer()?;
+        }
+
+        // Writes too large to buffer go straight to the stream, like `BufWriter`.
+        if buf.len() >= self.write_capacity {
+            return self.reader.get_mut().write(buf);
+        }
+
+        self.write_buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.reader.get_mut().flush()
+    }
+}
+
+impl<S: Read + Write> Drop for IpcDuplex<S> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame_message, ipc_client_connect_buffered, start_ipc_listener, SocketExt};
+    use std::io::Cursor;
+
+    /// A stream that counts how many times it's read from.
+    struct CountingStream {
+        inner: Cursor<Vec<u8>>,
+        reads: usize,
+    }
+
+    impl Read for CountingStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn counting_stream(messages: &[String]) -> CountingStream {
+        let bytes = messages
+            .iter()
+            .flat_map(|message| frame_message(message).unwrap())
+            .collect();
+
+        CountingStream {
+            inner: Cursor::new(bytes),
+            reads: 0,
+        }
+    }
+
+    #[test]
+    fn test_duplex_shares_reads_between_messages() {
+        let messages = ["one", "two", "three"].map(String::from);
+
+        let mut stream = counting_stream(&messages);
+        for message in &messages {
+            assert_eq!(&stream.read_serde::<String>().unwrap(), message);
+        }
+        assert_eq!(stream.reads, 2 * messages.len());
+
+        let mut duplex = IpcDuplex::new(counting_stream(&messages));
+        for message in &messages {
+            assert_eq!(&duplex.read_serde::<String>().unwrap(), message);
+        }
+        assert_eq!(duplex.get_ref().reads, 1);
+    }
+
+    #[test]
+    fn test_duplex_round_trip_and_flush_on_drop() {
+        let socket = "/tmp/ipc-util-test-duplex.sock";
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        start_ipc_listener(
+            socket,
+            move |stream| {
+                let mut duplex = crate::IpcDuplex::new(stream);
+                let mut line = String::new();
+
+                while duplex.read_line(&mut line).unwrap_or(0) > 0 {
+                    if line == "ping\n" {
+                        duplex.write_all(b"pong\n").unwrap();
+                    }
+
+                    sender.send(line.clone()).unwrap();
+                    line.clear();
+                }
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let mut client = ipc_client_connect_buffered(socket).expect("Failed to connect");
+        client.write_all(b"ping\n").unwrap();
+
+        let mut response = String::new();
+        client.read_line(&mut response).unwrap();
+        assert_eq!(response, "pong\n");
+
+        client.write_all(b"bye\n").unwrap();
+        drop(client);
+
+        assert_eq!(receiver.recv().unwrap(), "ping\n");
+        assert_eq!(receiver.recv().unwrap(), "bye\n");
+    }
+}
@@ -1,28 +1,139 @@
+use std::path::PathBuf;
 use std::{any::Any, io};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum IpcServerError {
-    #[error("Failed to bind to socket: {0}")]
-    BindError(io::Error),
+    #[error("Invalid socket name: {reason}")]
+    InvalidName { reason: String },
+    #[error("Failed to bind to socket '{name}': {source}")]
+    BindError { name: String, source: io::Error },
     #[error("Failed to delete stale socket file: {0}")]
     FileError(io::Error),
-    #[error("The socket is already in use by an instance of the current process.")]
+    #[error("Failed to open or lock the lock file '{}': {source}", path.display())]
+    LockFileError { path: PathBuf, source: io::Error },
+    #[error("Failed to read or write the PID file '{}': {source}", path.display())]
+    PidFileError { path: PathBuf, source: io::Error },
+    #[error("Failed to set socket file permissions: {0}")]
+    PermissionsError(io::Error),
+    #[error("The socket is already in use by a running server.")]
     AlreadyInUseError,
     #[error("Failed to spawn server thread: {0}")]
     ThreadSpawnError(io::Error),
     #[error("Failed to join server thread")]
     ThreadJoinError(Box<dyn Any + Send + 'static>),
+    #[error("The client failed to authenticate")]
+    AuthFailed,
+    #[cfg(feature = "ctrlc")]
+    #[error("Failed to install the Ctrl-C handler: {0}")]
+    SignalHandlerError(ctrlc::Error),
+}
+
+/// The servers in an `IpcServerGroup` that failed, along with the socket each of them was started on.
+#[derive(Error, Debug)]
+#[error("{} of the servers in the group failed", .0.len())]
+pub struct IpcServerGroupError(pub Vec<(String, IpcServerError)>);
+
+impl IpcServerError {
+    /// Returns a function that wraps an error binding the given socket in a `BindError`, for use with `map_err`.
+    pub(crate) fn bind(name: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| IpcServerError::BindError {
+            name: name.to_string(),
+            source,
+        }
+    }
+}
+
+/// What a server should do after its error handler is invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorAction {
+    /// Keep accepting connections.
+    #[default]
+    Continue,
+    /// Stop accepting connections, ending the server thread.
+    Stop,
 }
 
 #[derive(Error, Debug)]
 pub enum IpcClientError {
-    #[error("Failed to connect to socket: {0}")]
-    ConnectError(#[from] io::Error),
+    #[error("Failed to connect to socket '{name}': {source}")]
+    ConnectError { name: String, source: io::Error },
     #[error("Failed to read from socket: {0}")]
     ReadError(#[from] IpcStreamReadError),
     #[error("Failed to write to socket: {0}")]
     WriteError(#[from] IpcStreamWriteError),
+    #[error("The server rejected the authentication handshake")]
+    AuthFailed,
+    #[error("The query was cancelled")]
+    Cancelled,
+}
+
+impl IpcClientError {
+    /// Returns a function that wraps an error connecting to the given socket in a `ConnectError`, for use with `map_err`.
+    pub(crate) fn connect(name: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| IpcClientError::ConnectError {
+            name: name.to_string(),
+            source,
+        }
+    }
+
+    /// Returns `true` if the request failed to serialize, meaning nothing was sent to the server.
+    pub fn is_serialization_error(&self) -> bool {
+        matches!(
+            self,
+            IpcClientError::WriteError(IpcStreamWriteError::SerializeError(_))
+        )
+    }
+
+    /// Returns `true` if the response was received but failed to deserialize.
+    pub fn is_deserialization_error(&self) -> bool {
+        matches!(
+            self,
+            IpcClientError::ReadError(IpcStreamReadError::DeserializeError(_))
+        )
+    }
+
+    /// Returns `true` if connecting, reading or writing timed out, such as when a deadline passed.
+    pub fn is_timeout(&self) -> bool {
+        let io = match self {
+            IpcClientError::ConnectError { source: e, .. }
+            | IpcClientError::ReadError(IpcStreamReadError::ReadError(e))
+            | IpcClientError::WriteError(IpcStreamWriteError::WriteError(e)) => e,
+            _ => return false,
+        };
+
+        io.kind() == io::ErrorKind::TimedOut
+    }
+
+    /// Returns `true` if connecting failed, or if the connection broke while reading or writing.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            IpcClientError::ConnectError { .. }
+                | IpcClientError::ReadError(IpcStreamReadError::ReadError(_))
+                | IpcClientError::ReadError(IpcStreamReadError::IncompleteMessage { .. })
+                | IpcClientError::WriteError(IpcStreamWriteError::WriteError(_))
+        )
+    }
+}
+
+/// An error from the codec selected by the `codec-*` features, serializing or deserializing a typed message.
+///
+/// Only the variants of the enabled codecs exist.
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[cfg(feature = "codec-bincode")]
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+    #[cfg(feature = "codec-json")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "codec-msgpack")]
+    #[error(transparent)]
+    MsgpackEncode(#[from] rmp_serde::encode::Error),
+    #[cfg(feature = "codec-msgpack")]
+    #[error(transparent)]
+    MsgpackDecode(#[from] rmp_serde::decode::Error),
 }
 
 #[derive(Error, Debug)]
@@ -30,7 +141,30 @@ pub enum IpcStreamReadError {
     #[error("Failed to read from socket: {0}")]
     ReadError(#[from] io::Error),
     #[error("Failed to deserialize data from socket: {0}")]
-    DeserializeError(#[from] bincode::Error),
+    DeserializeError(#[from] CodecError),
+    #[cfg(feature = "json")]
+    #[error("Failed to deserialize JSON data from socket: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Connection closed after {got} of {expected} message bytes were received")]
+    IncompleteMessage { expected: usize, got: usize },
+    #[error("Message checksum mismatch: expected {expected:#010x}, got {actual:#010x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("Message of {size} bytes exceeds the maximum size of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+    #[error("Unsupported protocol version {got}, expected version {expected}")]
+    UnsupportedVersion { got: u8, expected: u8 },
+    #[error("Bad magic header {got:?}, expected {expected:?}")]
+    BadMagic { got: [u8; 4], expected: [u8; 4] },
+    #[error("Varint length prefix is longer than 5 bytes or overflows a u32")]
+    InvalidLengthPrefix,
+    #[error("Message is too short to hold a type tag")]
+    MissingTypeTag,
+    #[error("No handler is registered for message type tag {0}")]
+    UnknownTypeTag(u16),
+    #[error("Message is too short to hold a request id")]
+    MissingRequestId,
+    #[error("The deadline of the request passed before the server handled it")]
+    DeadlineExceeded,
 }
 
 #[derive(Error, Debug)]
@@ -38,7 +172,12 @@ pub enum IpcStreamWriteError {
     #[error("Failed to write to socket: {0}")]
     WriteError(#[from] io::Error),
     #[error("Failed to serialize data for socket: {0}")]
-    SerializeError(#[from] bincode::Error),
+    SerializeError(#[from] CodecError),
+    #[cfg(feature = "json")]
+    #[error("Failed to serialize JSON data for socket: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Message of {size} bytes exceeds the maximum size of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
 }
 
 #[derive(Error, Debug)]
@@ -48,3 +187,88 @@ pub enum IpcStreamError {
     #[error("Failed to write to socket: {0}")]
     WriteError(#[from] IpcStreamWriteError),
 }
+
+#[cfg(feature = "codec-bincode")]
+impl From<bincode::Error> for IpcStreamReadError {
+    fn from(error: bincode::Error) -> Self {
+        IpcStreamReadError::DeserializeError(error.into())
+    }
+}
+
+#[cfg(feature = "codec-bincode")]
+impl From<bincode::Error> for IpcStreamWriteError {
+    fn from(error: bincode::Error) -> Self {
+        IpcStreamWriteError::SerializeError(error.into())
+    }
+}
+
+impl From<IpcStreamError> for IpcClientError {
+    fn from(error: IpcStreamError) -> Self {
+        match error {
+            IpcStreamError::ReadError(e) => IpcClientError::ReadError(e),
+            IpcStreamError::WriteError(e) => IpcClientError::WriteError(e),
+        }
+    }
+}
+
+/// An error from any part of this crate.
+///
+/// Every other error type converts into this one, along with `io::Error`, `CodecError` and, with the `codec-bincode`
+/// feature, `bincode::Error`, so application code can use a single `Result<_, IpcError>` and the `?` operator
+/// throughout.
+#[derive(Error, Debug)]
+pub enum IpcError {
+    #[error(transparent)]
+    Server(#[from] IpcServerError),
+    #[error(transparent)]
+    Client(#[from] IpcClientError),
+    #[error(transparent)]
+    Read(#[from] IpcStreamReadError),
+    #[error(transparent)]
+    Write(#[from] IpcStreamWriteError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    #[cfg(feature = "codec-bincode")]
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+}
+
+impl From<IpcStreamError> for IpcError {
+    fn from(error: IpcStreamError) -> Self {
+        match error {
+            IpcStreamError::ReadError(e) => IpcError::Read(e),
+            IpcStreamError::WriteError(e) => IpcError::Write(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_convert_into_ipc_error() {
+        fn read() -> Result<(), IpcError> {
+            Err(IpcStreamError::from(
+                IpcStreamReadError::IncompleteMessage {
+                    expected: 4,
+                    got: 2,
+                },
+            ))?
+        }
+
+        fn write() -> Result<(), IpcError> {
+            Err(IpcStreamWriteError::MessageTooLarge { size: 8, max: 4 })?
+        }
+
+        assert!(matches!(read(), Err(IpcError::Read(_))));
+        assert!(matches!(write(), Err(IpcError::Write(_))));
+
+        let client_error = IpcClientError::from(IpcStreamError::from(IpcStreamWriteError::from(
+            io::Error::from(io::ErrorKind::BrokenPipe),
+        )));
+        assert!(client_error.is_connection_error());
+    }
+}
@@ -0,0 +1,28 @@
+/// A job that handles a single connection.
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the work of handling each connection for the concurrent servers, `listen_concurrent` and `serve_concurrent`.
+///
+/// Implement this to dispatch connections onto an existing thread pool, so the server fits the application's own
+/// concurrency strategy. Closures taking a `Job` implement it too, so a Rayon pool can be used with
+/// `.executor(move |job| pool.spawn(job))`. Defaults to `ThreadPerConnection`.
+pub trait Executor: Send + Sync {
+    /// Runs the job, usually on another thread. The accept loop waits for this to return before accepting again.
+    fn execute(&self, job: Job);
+}
+
+/// Runs each connection on its own newly spawned thread.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadPerConnection;
+
+impl Executor for ThreadPerConnection {
+    fn execute(&self, job: Job) {
+        std::thread::spawn(job);
+    }
+}
+
+impl<F: Fn(Job) + Send + Sync> Executor for F {
+    fn execute(&self, job: Job) {
+        self(job)
+    }
+}
@@ -1,42 +1,688 @@
-use crate::{IpcStreamReadError, IpcStreamWriteError};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::utils::process_name;
+use crate::{ChunkStream, FrameReader, Framing, IpcStreamReadError, IpcStreamWriteError};
 use interprocess::local_socket::LocalSocketStream;
 use std::io::prelude::*;
+use std::time::SystemTime;
 
 pub trait SocketExt {
     fn read_serde<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, IpcStreamReadError>;
     fn write_serde<T: serde::Serialize>(&mut self, data: &T) -> Result<(), IpcStreamWriteError>;
+    fn try_read_serde<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<T>, IpcStreamReadError>;
+    fn read_serde_framed<T: serde::de::DeserializeOwned>(
+        &mut self,
+        framing: &Framing,
+    ) -> Result<T, IpcStreamReadError>;
+    fn write_serde_framed<T: serde::Serialize>(
+        &mut self,
+        data: &T,
+        framing: &Framing,
+    ) -> Result<(), IpcStreamWriteError>;
+    fn write_serde_with_deadline<T: serde::Serialize>(
+        &mut self,
+        data: &T,
+        framing: &Framing,
+        deadline: SystemTime,
+    ) -> Result<(), IpcStreamWriteError>;
+    #[cfg(feature = "smallvec")]
+    fn read_serde_small<T: serde::de::DeserializeOwned>(&mut self)
+        -> Result<T, IpcStreamReadError>;
+    fn read_frame(&mut self) -> Result<Vec<u8>, IpcStreamReadError>;
+    fn read_frame_chunked(&mut self) -> Result<FrameReader<'_, Self>, IpcStreamReadError>
+    where
+        Self: Sized;
+    fn write_frame(&mut self, bytes: &[u8]) -> Result<(), IpcStreamWriteError>;
+    fn write_stream<I: IntoIterator<Item = C>, C: AsRef<[u8]>>(
+        &mut self,
+        chunks: I,
+    ) -> Result<(), IpcStreamWriteError>;
+    fn write_stream_from<R: Read>(
+        &mut self,
+        reader: &mut R,
+        chunk_size: usize,
+    ) -> Result<u64, IpcStreamWriteError>;
+    fn read_stream(&mut self) -> ChunkStream<'_, Self>
+    where
+        Self: Sized;
+    fn write_serde_batch<T: serde::Serialize>(
+        &mut self,
+        items: &[T],
+    ) -> Result<(), IpcStreamWriteError>;
+    #[cfg(feature = "codec-bincode")]
+    fn read_serde_with<T: serde::de::DeserializeOwned, O: bincode::Options>(
+        &mut self,
+        options: O,
+    ) -> Result<T, IpcStreamReadError>;
+    #[cfg(feature = "codec-bincode")]
+    fn write_serde_with<T: serde::Serialize, O: bincode::Options>(
+        &mut self,
+        data: &T,
+        options: O,
+    ) -> Result<(), IpcStreamWriteError>;
+    #[cfg(feature = "json")]
+    fn read_json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, IpcStreamReadError>;
+    #[cfg(feature = "json")]
+    fn write_json<T: serde::Serialize>(&mut self, data: &T) -> Result<(), IpcStreamWriteError>;
 }
 
-impl SocketExt for LocalSocketStream {
+/// Extensions that only apply to local sockets, as opposed to any `Read + Write` stream.
+pub trait LocalSocketExt {
+    fn peer_process_name(&self) -> Option<String>;
+    fn is_self_connection(&self) -> bool;
+    fn finish_writing(&self) -> std::io::Result<()>;
+    fn reject_with<T: serde::Serialize>(self, message: &T) -> Result<(), IpcStreamWriteError>;
+    fn has_pending_frame(&self) -> std::io::Result<bool>;
+    fn set_recv_buffer_size(&self, size: usize) -> std::io::Result<()>;
+    fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()>;
+    fn recv_buffer_size(&self) -> std::io::Result<usize>;
+    fn send_buffer_size(&self) -> std::io::Result<usize>;
+    #[cfg(unix)]
+    fn send_fd(&mut self, fd: std::os::unix::io::BorrowedFd<'_>) -> std::io::Result<()>;
+    #[cfg(unix)]
+    fn recv_fd(&mut self) -> std::io::Result<std::os::unix::io::OwnedFd>;
+}
+
+/// The framing methods work over any stream, so they can also be used over TCP, or tested over in-memory buffers.
+impl<S: Read + Write> SocketExt for S {
     /// Read a serializable object from the socket.
     ///
-    /// This reads a `u32` in little endian, then reads that many bytes from the socket, then deserializes the data using `bincode::deserialize`.
+    /// This reads a `u32` in little endian, then reads that many bytes from the socket, then deserializes the data with the codec
+    /// selected by the `codec-*` features, bincode by default, bounded by the `Framing::deserialize_limit`.
+    /// A zero-length frame reads back as a unit type like `()`, and fails to deserialize as anything else.
+    /// To skip such frames instead, use `read_serde_framed` with `Framing::skip_empty_frames`.
+    ///
+    /// The header and the payload are read from the socket separately. When reading many messages, wrap the stream in
+    /// an `IpcDuplex` so they share one underlying read.
     fn read_serde<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, IpcStreamReadError> {
-        let size = self.read_u32::<LittleEndian>()?;
+        self.read_serde_framed(&Framing::default())
+    }
 
-        let bytes = {
-            let mut bytes = vec![0; size as usize];
+    /// Write a serializable object to the socket.
+    ///
+    /// This writes the length of the serialized data as a `u32` in little endian, then serializes the data with the
+    /// selected codec directly into the socket, without holding the whole serialized message in memory.
+    /// The stream is flushed afterwards, so the message is never left sitting in a buffer.
+    ///
+    /// On a non-blocking stream, this can fail with `WouldBlock` after writing part of the message.
+    /// Use a `FrameWriter` instead, which resumes partial writes.
+    fn write_serde<T: serde::Serialize>(&mut self, data: &T) -> Result<(), IpcStreamWriteError> {
+        self.write_serde_framed(data, &Framing::default())
+    }
 
-            self.read_exact(&mut bytes)?;
+    /// Read a serializable object from the socket, or `None` if the peer closed the connection.
+    ///
+    /// This behaves like `read_serde`, except that reaching EOF before any byte of the length prefix is read
+    /// returns `Ok(None)` rather than an error. EOF anywhere after that is still an error, since it means
+    /// the peer disconnected partway through a message.
+    fn try_read_serde<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<T>, IpcStreamReadError> {
+        let framing = Framing::default();
 
-            bytes
+        let Some(size) = framing.try_read_len(self)? else {
+            return Ok(None);
         };
 
-        let result: T = bincode::deserialize(&bytes)?;
+        let bytes = framing.read_payload(self, size)?;
+        let result: T = framing.deserialize(&bytes)?;
+
+        Ok(Some(result))
+    }
+
+    /// Read a serializable object from the socket using the given framing.
+    ///
+    /// Both sides of the connection must use the same framing.
+    fn read_serde_framed<T: serde::de::DeserializeOwned>(
+        &mut self,
+        framing: &Framing,
+    ) -> Result<T, IpcStreamReadError> {
+        let size = framing.read_len(self)?;
+        let bytes = framing.read_payload(self, size)?;
+
+        let result: T = framing.deserialize(&bytes)?;
 
         Ok(result)
     }
 
-    /// Write a serializable object to the socket.
+    /// Write a serializable object to the socket using the given framing.
     ///
-    /// This serializes the data using `bincode::serialize`, writes the length of the serialized data as a `u32` in little endian, then writes the serialized data.
-    fn write_serde<T: serde::Serialize>(&mut self, data: &T) -> Result<(), IpcStreamWriteError> {
-        let bytes = bincode::serialize(data)?;
+    /// Both sides of the connection must use the same framing.
+    fn write_serde_framed<T: serde::Serialize>(
+        &mut self,
+        data: &T,
+        framing: &Framing,
+    ) -> Result<(), IpcStreamWriteError> {
+        framing.write_serialized(self, data)
+    }
+
+    /// Write a serializable object to the socket like `write_serde_framed`, with a deadline in its header.
+    ///
+    /// The deadline is only sent if the framing has `Framing::deadlines` enabled, and otherwise this is the same as
+    /// `write_serde_framed`. A typed server using that framing answers a request whose deadline has already passed
+    /// with a frame that makes reading the response fail with `IpcStreamReadError::DeadlineExceeded`.
+    fn write_serde_with_deadline<T: serde::Serialize>(
+        &mut self,
+        data: &T,
+        framing: &Framing,
+        deadline: SystemTime,
+    ) -> Result<(), IpcStreamWriteError> {
+        framing.write_serialized_until(self, data, Some(deadline))
+    }
+
+    /// Read a serializable object from the socket like `read_serde`, without allocating for small messages.
+    ///
+    /// Payloads of up to `INLINE_MESSAGE_SIZE` bytes are read into a buffer on the stack, and only larger ones are
+    /// read into a heap allocation, which saves an allocation per message on channels that carry small messages.
+    #[cfg(feature = "smallvec")]
+    fn read_serde_small<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<T, IpcStreamReadError> {
+        let framing = Framing::default();
+
+        let size = framing.read_len(self)?;
+        let bytes = framing.read_payload_small(self, size)?;
+
+        framing.deserialize(&bytes)
+    }
+
+    /// Read the raw payload of a message from the socket, without deserializing it.
+    ///
+    /// This reads a `u32` in little endian, then reads and returns that many bytes. Combined with `write_frame`,
+    /// this allows forwarding messages verbatim without knowing their type.
+    fn read_frame(&mut self) -> Result<Vec<u8>, IpcStreamReadError> {
+        Framing::default().read_frame(self)
+    }
+
+    /// Read the header of a message from the socket, and return a `FrameReader` to read its payload in chunks.
+    ///
+    /// This suits payloads too large to hold in memory at once. The reader tracks how much of the payload is left,
+    /// and never reads past it into the next message.
+    fn read_frame_chunked(&mut self) -> Result<FrameReader<'_, Self>, IpcStreamReadError> {
+        Framing::default().frame_reader(self)
+    }
+
+    /// Write a pre-serialized payload to the socket.
+    ///
+    /// This writes the length of the payload as a `u32` in little endian, then writes the payload itself.
+    fn write_frame(&mut self, bytes: &[u8]) -> Result<(), IpcStreamWriteError> {
+        Framing::default().write_frame(self, bytes)
+    }
+
+    /// Write a sequence of byte chunks to the socket as a chunked stream, without holding all of it in memory.
+    ///
+    /// Each chunk is written as its own frame, followed by an empty frame that marks the end of the stream, so empty
+    /// chunks are skipped rather than ending it early. The reader gets the chunks back with `read_stream`.
+    fn write_stream<I: IntoIterator<Item = C>, C: AsRef<[u8]>>(
+        &mut self,
+        chunks: I,
+    ) -> Result<(), IpcStreamWriteError> {
+        for chunk in chunks {
+            let chunk = chunk.as_ref();
+
+            if !chunk.is_empty() {
+                self.write_frame(chunk)?;
+            }
+        }
+
+        self.write_frame(&[])
+    }
+
+    /// Write everything read from the reader to the socket as a chunked stream of up to `chunk_size` bytes per chunk.
+    ///
+    /// This pipes a file or other large payload through the socket incrementally. See `write_stream`.
+    /// Returns the number of bytes written, not counting the framing.
+    fn write_stream_from<R: Read>(
+        &mut self,
+        reader: &mut R,
+        chunk_size: usize,
+    ) -> Result<u64, IpcStreamWriteError> {
+        let mut buffer = vec![0; chunk_size.max(1)];
+        let mut total = 0;
 
-        self.write_u32::<LittleEndian>(bytes.len() as u32)?;
-        self.write_all(&bytes)?;
+        loop {
+            let len = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            self.write_frame(&buffer[..len])?;
+            total += len as u64;
+        }
+
+        self.write_frame(&[])?;
+
+        Ok(total)
+    }
+
+    /// Read a chunked stream written with `write_stream`, returning an iterator over its chunks.
+    ///
+    /// The iterator ends once the empty frame marking the end of the stream is read, leaving any following messages
+    /// on the socket, or after yielding an error, such as the peer disconnecting before the end of the stream.
+    fn read_stream(&mut self) -> ChunkStream<'_, Self> {
+        ChunkStream::new(self)
+    }
+
+    /// Write several serializable objects to the socket at once.
+    ///
+    /// Each object is framed exactly as with `write_serde`, but all of them are written to an intermediate buffer first,
+    /// which is then written to the socket with a single `write_all` and flushed. The reader can read them back one at a
+    /// time with `read_serde`. If any object fails to serialize, nothing is written.
+    fn write_serde_batch<T: serde::Serialize>(
+        &mut self,
+        items: &[T],
+    ) -> Result<(), IpcStreamWriteError> {
+        let framing = Framing::default();
+        let mut buffer = Vec::new();
+
+        for item in items {
+            let bytes = crate::codec::serialize(item)?;
+            framing.write_payload(&mut buffer, &bytes)?;
+        }
+
+        self.write_all(&buffer)?;
+        self.flush()?;
 
         Ok(())
     }
+
+    /// Read a serializable object from the socket, deserializing it with the given bincode options.
+    ///
+    /// The message is framed exactly as with `read_serde`; only the encoding of the payload changes. Both sides of the
+    /// connection must use identical options. The plain methods are equivalent to passing
+    /// `bincode::options().with_fixint_encoding().allow_trailing_bytes().with_limit(limit)`, where `limit` is the
+    /// `Framing::deserialize_limit`, and a limit set on the given options with `with_limit` is enforced the same way.
+    /// The `codec-bincode` feature must be enabled for these methods, whichever codec is in use.
+    #[cfg(feature = "codec-bincode")]
+    fn read_serde_with<T: serde::de::DeserializeOwned, O: bincode::Options>(
+        &mut self,
+        options: O,
+    ) -> Result<T, IpcStreamReadError> {
+        let bytes = self.read_frame()?;
+
+        // `Options::deserialize` drops the limit for slices, so the deserializer is created directly to keep it.
+        let mut deserializer = bincode::Deserializer::from_slice(&bytes, options);
+        Ok(T::deserialize(&mut deserializer)?)
+    }
+
+    /// Write a serializable object to the socket, serializing it with the given bincode options.
+    ///
+    /// See `read_serde_with`.
+    #[cfg(feature = "codec-bincode")]
+    fn write_serde_with<T: serde::Serialize, O: bincode::Options>(
+        &mut self,
+        data: &T,
+        options: O,
+    ) -> Result<(), IpcStreamWriteError> {
+        let bytes = options.serialize(data)?;
+
+        self.write_frame(&bytes)
+    }
+
+    /// Read a JSON message from the socket.
+    ///
+    /// The message is framed exactly as with `read_serde`, but its payload is deserialized with `serde_json` rather
+    /// than the codec, for peers in other languages that write JSON after a `u32` length in little endian.
+    #[cfg(feature = "json")]
+    fn read_json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, IpcStreamReadError> {
+        let bytes = self.read_frame()?;
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Write a message to the socket as JSON.
+    ///
+    /// The payload is the message serialized with `serde_json`, framed like `write_frame`, so a peer in another
+    /// language can parse it as JSON after stripping the 4-byte length prefix. See `read_json`.
+    #[cfg(feature = "json")]
+    fn write_json<T: serde::Serialize>(&mut self, data: &T) -> Result<(), IpcStreamWriteError> {
+        let bytes = serde_json::to_vec(data)?;
+
+        self.write_frame(&bytes)
+    }
+}
+
+impl LocalSocketExt for LocalSocketStream {
+    /// Get the executable name of the process on the other end of the socket.
+    ///
+    /// This looks up the peer's process id, then resolves its name with `sysinfo`. Returns `None` if either step fails,
+    /// e.g. on platforms that can't tell the peer's process id or if the peer process has already exited.
+    /// Without the `sysinfo` feature, this always returns `None`.
+    fn peer_process_name(&self) -> Option<String> {
+        let pid = crate::utils::peer_pid(self).ok()?;
+
+        process_name(pid)
+    }
+
+    /// Check whether the process on the other end of the socket is the current process.
+    ///
+    /// This compares the peer's process id with our own, so it tells apart connections a process makes to its own
+    /// server, such as the one that wakes up the accept loop to shut it down. The process id is looked up with
+    /// `peer_pid` on Linux, Android, Haiku and Windows, with `LOCAL_PEERPID` on macOS and iOS, and with the peer
+    /// credentials socket options on FreeBSD 13 and later, OpenBSD and NetBSD. Returns `false` if it can't be
+    /// determined, e.g. on DragonFly BSD.
+    fn is_self_connection(&self) -> bool {
+        crate::utils::peer_pid(self).is_ok_and(|pid| pid == std::process::id())
+    }
+
+    /// Shut down the write half of the connection, while still allowing reads.
+    ///
+    /// The peer reads EOF once it has read everything written before this call, which lets a client signal the end of a
+    /// streamed request and then read the response over the same connection. Writing afterwards fails.
+    ///
+    /// This is only supported on Unix, since named pipes can't be half-closed. On Windows, it returns an `Unsupported` error.
+    fn finish_writing(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return crate::utils::shutdown_write(self);
+
+        #[cfg(not(unix))]
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Named pipes can't be half-closed",
+        ))
+    }
+
+    /// Write a single serializable object to the socket, such as a "server busy" error, then close the connection
+    /// without reading anything from it.
+    ///
+    /// This lets a listener turn a client away with a reason instead of silently dropping it, for example while the
+    /// server is draining or has too many connections open. Anything the client sent is discarded with the connection,
+    /// and a client that is still writing once it closes gets a broken pipe error, so it should try reading the
+    /// rejection when a write fails.
+    ///
+    /// On Unix, the write half is shut down with `finish_writing` before the stream is dropped, so the client reads EOF
+    /// right after the rejection. Named pipes can't be half-closed, so on Windows the rejection is flushed instead,
+    /// which waits for the client to read it, and the pipe is closed once the stream is dropped.
+    fn reject_with<T: serde::Serialize>(mut self, message: &T) -> Result<(), IpcStreamWriteError> {
+        self.write_serde(message)?;
+
+        #[cfg(unix)]
+        self.finish_writing()?;
+
+        Ok(())
+    }
+
+    /// Check whether the next frame can be read without blocking, without consuming any of it.
+    ///
+    /// This peeks at the connection without blocking, and returns `false` if nothing has arrived yet, so several
+    /// connections can be polled by hand in a select-style loop. A `true` result means at least the first byte of the
+    /// frame is available, so reading the rest of it may still wait briefly. It is also `true` once the peer has
+    /// closed the connection, since reading then returns immediately with an error or `None` from `try_read_serde`.
+    fn has_pending_frame(&self) -> std::io::Result<bool> {
+        crate::utils::has_pending_data(self)
+    }
+
+    /// Set the size of the connection's receive buffer, in bytes, with `SO_RCVBUF`.
+    ///
+    /// The kernel may round the size, and Linux doubles it to leave room for bookkeeping, so check the size it settled
+    /// on with `recv_buffer_size`. Named pipe buffers are fixed when the pipe is created, so on Windows this returns
+    /// an `Unsupported` error. See `IpcServerBuilder::recv_buffer_size`.
+    fn set_recv_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return crate::utils::set_buffer_size(self, libc::SO_RCVBUF, size);
+
+        #[cfg(not(unix))]
+        Err(unsupported_buffer_size(size))
+    }
+
+    /// Set the size of the connection's send buffer, in bytes, with `SO_SNDBUF`. See `set_recv_buffer_size`.
+    fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return crate::utils::set_buffer_size(self, libc::SO_SNDBUF, size);
+
+        #[cfg(not(unix))]
+        Err(unsupported_buffer_size(size))
+    }
+
+    /// Get the size of the connection's receive buffer, in bytes.
+    ///
+    /// On Windows, this is the size the pipe was created with, from this end's point of view.
+    fn recv_buffer_size(&self) -> std::io::Result<usize> {
+        #[cfg(unix)]
+        return crate::utils::buffer_size(self, libc::SO_RCVBUF);
+
+        #[cfg(windows)]
+        crate::utils::pipe_buffer_sizes(self).map(|(recv, _)| recv)
+    }
+
+    /// Get the size of the connection's send buffer, in bytes. See `recv_buffer_size`.
+    fn send_buffer_size(&self) -> std::io::Result<usize> {
+        #[cfg(unix)]
+        return crate::utils::buffer_size(self, libc::SO_SNDBUF);
+
+        #[cfg(windows)]
+        crate::utils::pipe_buffer_sizes(self).map(|(_, send)| send)
+    }
+
+    /// Send an open file descriptor to the process on the other end of the socket.
+    ///
+    /// The descriptor is duplicated into the receiving process, which must call `recv_fd` at the same point in the
+    /// conversation, since it is carried by a single byte of regular data that would otherwise be mixed up with a message.
+    /// The sender keeps its own descriptor and can close it once this returns.
+    #[cfg(unix)]
+    fn send_fd(&mut self, fd: std::os::unix::io::BorrowedFd<'_>) -> std::io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        crate::fd::send_fd(self.as_raw_fd(), fd)
+    }
+
+    /// Receive an open file descriptor sent with `send_fd`.
+    ///
+    /// The received descriptor has close-on-exec set, and is closed when the returned `OwnedFd` is dropped.
+    #[cfg(unix)]
+    fn recv_fd(&mut self) -> std::io::Result<std::os::unix::io::OwnedFd> {
+        use std::os::unix::io::AsRawFd;
+
+        crate::fd::recv_fd(self.as_raw_fd())
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported_buffer_size(_size: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Named pipe buffer sizes can't be changed after the pipe is created",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip_in_memory() {
+        let mut buffer = Cursor::new(Vec::new());
+
+        buffer.write_serde(&"hello".to_string()).unwrap();
+        buffer.write_serde(&42u32).unwrap();
+        buffer.set_position(0);
+
+        assert_eq!(buffer.read_serde::<String>().unwrap(), "hello");
+        assert_eq!(buffer.read_serde::<u32>().unwrap(), 42);
+        assert!(buffer.try_read_serde::<u32>().unwrap().is_none());
+    }
+
+    #[test]
+    // Only where the peer's process id can be looked up, see `LocalSocketExt::is_self_connection`.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "haiku",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        windows
+    ))]
+    fn test_is_self_connection() {
+        let socket = "/tmp/ipc-util-test-self-connection.sock";
+        let listener = crate::bind_ipc_listener(socket).unwrap();
+
+        let client = LocalSocketStream::connect(socket).unwrap();
+        let server = listener.accept().unwrap();
+
+        assert!(client.is_self_connection());
+        assert!(server.is_self_connection());
+    }
+
+    #[test]
+    fn test_batch_matches_sequential_writes() {
+        let items = ["a".to_string(), "bc".to_string(), "def".to_string()];
+
+        let mut sequential = Cursor::new(Vec::new());
+        for item in &items {
+            sequential.write_serde(item).unwrap();
+        }
+
+        let mut batched = Cursor::new(Vec::new());
+        batched.write_serde_batch(&items).unwrap();
+
+        assert_eq!(batched.into_inner(), sequential.into_inner());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_reject_with_reaches_client() {
+        let socket = "/tmp/ipc-util-test-reject-with.sock";
+
+        crate::start_ipc_listener(
+            socket,
+            |stream| stream.reject_with(&"server busy, retry later").unwrap(),
+            None,
+        )
+        .expect("Failed to start server");
+
+        let mut stream = crate::ipc_client_connect(socket).unwrap();
+        assert_eq!(
+            stream.read_serde::<String>().unwrap(),
+            "server busy, retry later"
+        );
+
+        // The connection is closed right after the rejection.
+        assert!(stream.try_read_serde::<String>().unwrap().is_none());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_payload_is_plain_json() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Status {
+            name: String,
+            ready: bool,
+        }
+
+        let status = Status {
+            name: "worker".to_string(),
+            ready: true,
+        };
+
+        let mut stream = std::io::Cursor::new(Vec::new());
+        stream.write_json(&status).unwrap();
+
+        // Past the length prefix, the payload is a JSON document any JSON parser can read.
+        let bytes = stream.get_ref();
+        assert_eq!(bytes[..4], (bytes.len() as u32 - 4).to_le_bytes());
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&bytes[4..]).unwrap(),
+            serde_json::json!({ "name": "worker", "ready": true })
+        );
+
+        stream.set_position(0);
+        assert_eq!(stream.read_json::<Status>().unwrap(), status);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_finish_writing_signals_end_of_request() {
+        let socket = "/tmp/ipc-util-test-finish-writing.sock";
+
+        crate::start_ipc_listener(
+            socket,
+            |mut stream| {
+                let mut request = Vec::new();
+                stream.read_to_end(&mut request).unwrap();
+                stream.write_serde(&request.len()).unwrap();
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let mut stream = LocalSocketStream::connect(socket).unwrap();
+        stream.write_all(b"streamed request").unwrap();
+        stream.finish_writing().unwrap();
+
+        assert_eq!(stream.read_serde::<usize>().unwrap(), 16);
+    }
+
+    #[test]
+    fn test_has_pending_frame_peeks_without_blocking() {
+        let socket = "/tmp/ipc-util-test-pending-frame.sock";
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (release, wait_release) = std::sync::mpsc::channel::<()>();
+        let wait_release = std::sync::Mutex::new(wait_release);
+
+        crate::start_ipc_listener(
+            socket,
+            move |stream| {
+                sender.send(stream.has_pending_frame().unwrap()).unwrap();
+                wait_release.lock().unwrap().recv().unwrap();
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let mut stream = LocalSocketStream::connect(socket).unwrap();
+        assert!(!receiver.recv().unwrap());
+        assert!(!stream.has_pending_frame().unwrap());
+
+        release.send(()).unwrap();
+
+        // The server closes the connection once released, after which a read returns immediately.
+        while !stream.has_pending_frame().unwrap() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(stream.try_read_serde::<u32>().unwrap().is_none());
+    }
+
+    #[cfg(feature = "codec-bincode")]
+    #[test]
+    fn test_serde_with_custom_options() {
+        use bincode::Options;
+
+        let options = bincode::options().with_big_endian().with_varint_encoding();
+
+        let mut buffer = Cursor::new(Vec::new());
+        buffer.write_serde_with(&300u32, options).unwrap();
+
+        // The length prefix is unchanged, but the payload is a big-endian varint.
+        assert_eq!(buffer.get_ref(), &[3, 0, 0, 0, 251, 1, 44]);
+
+        buffer.set_position(0);
+        assert_eq!(buffer.read_serde_with::<u32, _>(options).unwrap(), 300);
+    }
+
+    #[cfg(feature = "codec-bincode")]
+    #[test]
+    fn test_serde_with_keeps_limit() {
+        use bincode::Options;
+
+        let options = bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes();
+
+        let mut buffer = Cursor::new(Vec::new());
+        buffer.write_serde_with(&"a".repeat(16), options).unwrap();
+
+        buffer.set_position(0);
+        let result = buffer.read_serde_with::<String, _>(options.with_limit(8));
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::DeserializeError(crate::CodecError::Bincode(e)))
+                if matches!(*e, bincode::ErrorKind::SizeLimit)
+        ));
+    }
 }
@@ -0,0 +1,176 @@
+//! Passing open file descriptors between processes over a Unix socket, using `SCM_RIGHTS` ancillary data.
+
+use std::io;
+use std::mem;
+use std::os::unix::io::{BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+
+/// The size of the control message buffer needed for a single file descriptor, in `u64`s to keep it aligned for `cmsghdr`.
+fn control_len() -> usize {
+    // SAFETY: `CMSG_SPACE` only computes a size.
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize;
+    space.div_ceil(mem::size_of::<u64>())
+}
+
+/// Sends a file descriptor over the socket, along with a single byte of regular data to carry it.
+pub fn send_fd(socket: RawFd, fd: BorrowedFd<'_>) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: byte.len(),
+    };
+
+    let mut control = vec![0u64; control_len()];
+
+    // SAFETY: `msghdr` is a plain C struct for which all zeroes is a valid, empty value.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * mem::size_of::<u64>()) as _;
+
+    // SAFETY: the control buffer is aligned and large enough for one header carrying one descriptor,
+    // so `CMSG_FIRSTHDR` returns a valid pointer into it, and its data can hold a `RawFd`.
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd.as_raw_fd());
+    }
+
+    loop {
+        // SAFETY: `msg` points to buffers that outlive the call.
+        match unsafe { libc::sendmsg(socket, &msg, 0) } {
+            -1 => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            },
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Receives a file descriptor sent with `send_fd`, consuming the byte of regular data that carried it.
+///
+/// The received descriptor has close-on-exec set.
+pub fn recv_fd(socket: RawFd) -> io::Result<OwnedFd> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: byte.len(),
+    };
+
+    let mut control = vec![0u64; control_len()];
+
+    // SAFETY: `msghdr` is a plain C struct for which all zeroes is a valid, empty value.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * mem::size_of::<u64>()) as _;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let flags = 0;
+
+    loop {
+        // SAFETY: `msg` points to buffers that outlive the call.
+        match unsafe { libc::recvmsg(socket, &mut msg, flags) } {
+            -1 => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            },
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => break,
+        }
+    }
+
+    let mut received = None;
+
+    // SAFETY: `recvmsg` filled in the control buffer and its length, so the headers `CMSG_FIRSTHDR` and
+    // `CMSG_NXTHDR` return are valid. Every descriptor found is taken ownership of exactly once.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>());
+                let fd = OwnedFd::from_raw_fd(fd);
+
+                // Only one descriptor is expected, so any others are closed as they're dropped.
+                if received.is_none() {
+                    received = Some(fd);
+                }
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The file descriptor was truncated",
+        ));
+    }
+
+    let fd = received.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No file descriptor was received",
+        )
+    })?;
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    set_cloexec(&fd)?;
+
+    Ok(fd)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_cloexec(fd: &OwnedFd) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor is open for as long as `fd` is borrowed.
+    match unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LocalSocketExt;
+    use interprocess::local_socket::LocalSocketStream;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::unix::io::{AsFd, FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_send_and_receive_fd() {
+        let (left, right) = UnixStream::pair().unwrap();
+
+        // SAFETY: both ends are connected Unix stream sockets, whose ownership is given up by `into_raw_fd`.
+        let mut sender = unsafe { LocalSocketStream::from_raw_fd(left.into_raw_fd()) };
+        let mut receiver = unsafe { LocalSocketStream::from_raw_fd(right.into_raw_fd()) };
+
+        let path = "/tmp/ipc-util-test-fd.txt";
+        File::create(path).unwrap().write_all(b"hello").unwrap();
+        let file = File::open(path).unwrap();
+
+        sender.send_fd(file.as_fd()).unwrap();
+        drop(file);
+
+        let mut received = File::from(receiver.recv_fd().unwrap());
+        let mut contents = String::new();
+        received.read_to_string(&mut contents).unwrap();
+
+        assert_eq!(contents, "hello");
+    }
+}
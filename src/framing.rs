@@ -0,0 +1,1787 @@
+use crate::{IpcStreamReadError, IpcStreamWriteError};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, prelude::*};
+use std::task::Poll;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+
+/// Gets the size in bytes that a value will have once serialized, not including the framing around it.
+///
+/// This is useful for rejecting messages that exceed the server's `Framing::max_message_size` before sending them.
+pub fn serialized_size<T: serde::Serialize>(value: &T) -> Result<u64, crate::CodecError> {
+    crate::codec::serialized_size(value)
+}
+
+/// Serializes a message and frames it exactly as `SocketExt::write_serde` writes it to a socket.
+///
+/// This decouples the wire format from the transport, so a message can be stored, logged or relayed over another
+/// channel, and later read back with `unframe_message` or from a socket with `read_serde`.
+pub fn frame_message<T: serde::Serialize>(message: &T) -> Result<Vec<u8>, IpcStreamWriteError> {
+    let mut bytes = Vec::new();
+    Framing::default().write_serialized(&mut bytes, message)?;
+    Ok(bytes)
+}
+
+/// Reads a framed message from the start of the given bytes, as written by `frame_message` or `SocketExt::write_serde`.
+///
+/// Returns the message along with the number of bytes it took up, so several messages can be read back from the same
+/// buffer. Any bytes after the message are ignored.
+pub fn unframe_message<T: serde::de::DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<(T, usize), IpcStreamReadError> {
+    let framing = Framing::default();
+    let (payload, used) = framing
+        .parse_frame(bytes)?
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+    let message = framing.deserialize(payload)?;
+
+    Ok((message, used))
+}
+
+/// The most a read reserves up front for a payload, however large its length prefix claims it is.
+///
+/// Larger payloads grow the buffer as their bytes actually arrive, so a peer can't make the reader allocate gigabytes
+/// by sending nothing but a length prefix.
+const MAX_PREALLOCATION: usize = 1024 * 1024;
+
+/// The largest payload, in bytes, that `SocketExt::read_serde_small` reads without allocating.
+#[cfg(feature = "smallvec")]
+pub const INLINE_MESSAGE_SIZE: usize = 64;
+
+/// The most bytes a varint length prefix takes, which is enough for any `u32`.
+const MAX_VARINT_LEN: usize = 5;
+
+/// The most bytes a frame adds around its payload: a version byte, a length prefix, a deadline and a checksum.
+const MAX_FRAME_OVERHEAD: usize = 1 + MAX_VARINT_LEN + 8 + 4;
+
+/// The largest buffer `write_serialized` writes a message through, the same size `BufWriter` uses by default.
+const MAX_WRITE_BUFFER: usize = 8 * 1024;
+
+/// The value of the deadline field that marks the frame a server answers an expired request with, instead of a deadline.
+const DEADLINE_EXCEEDED: u64 = u64::MAX;
+
+/// The encoding of the length prefix written before each message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthPrefix {
+    /// A little endian `u32`. This is the default.
+    #[default]
+    LittleEndian,
+    /// A big endian `u32`, matching the default configuration of `tokio_util::codec::LengthDelimitedCodec`.
+    BigEndian,
+    /// An unsigned LEB128 varint, which takes a single byte for messages of up to 127 bytes, two up to 16383 bytes,
+    /// and at most five. This saves bytes on channels that mostly carry small messages. Checksums are still written
+    /// as little endian `u32`s.
+    Varint,
+}
+
+/// A checksum appended to each message, used to detect corrupted or desynchronized frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// A CRC32 of the payload, written as a `u32` in the same byte order as the length prefix.
+    Crc32,
+}
+
+/// Describes how messages are framed on the wire.
+///
+/// Both sides of a connection must use the same framing, otherwise messages will fail to parse.
+/// The default framing is a little endian `u32` length prefix followed by the serialized message.
+///
+/// A length prefix of zero is a valid, empty frame. Unit types like `()` and unit structs serialize to nothing, so
+/// they are sent as empty frames, and reading them back from one succeeds. Reading any other type from an empty frame
+/// fails with `IpcStreamReadError::DeserializeError`. Use `skip_empty_frames` to treat empty frames as keepalives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Framing {
+    length_prefix: LengthPrefix,
+    checksum: Option<Checksum>,
+    max_message_size: Option<u32>,
+    deserialize_limit: Option<u64>,
+    version: Option<u8>,
+    skip_empty_frames: bool,
+    magic: Option<[u8; 4]>,
+    deadlines: bool,
+}
+
+impl Framing {
+    /// Creates the default framing.
+    pub const fn new() -> Self {
+        Self {
+            length_prefix: LengthPrefix::LittleEndian,
+            checksum: None,
+            max_message_size: None,
+            deserialize_limit: None,
+            version: None,
+            skip_empty_frames: false,
+            magic: None,
+            deadlines: false,
+        }
+    }
+
+    /// Creates a framing compatible with `tokio_util::codec::LengthDelimitedCodec`'s default configuration,
+    /// which uses a big endian `u32` length prefix.
+    pub const fn length_delimited() -> Self {
+        Self::new().length_prefix(LengthPrefix::BigEndian)
+    }
+
+    /// Sets the byte order of the length prefix.
+    pub const fn length_prefix(mut self, length_prefix: LengthPrefix) -> Self {
+        self.length_prefix = length_prefix;
+        self
+    }
+
+    /// Appends a checksum to each message, which is verified when the message is read.
+    ///
+    /// Reading a message whose checksum doesn't match returns `IpcStreamReadError::ChecksumMismatch`.
+    pub const fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// Sets the maximum size of a message's payload, in bytes.
+    ///
+    /// Writing a larger message fails with `IpcStreamWriteError::MessageTooLarge` before anything is sent, and reading
+    /// one fails with `IpcStreamReadError::MessageTooLarge` before its payload is read. By default there is no limit.
+    pub const fn max_message_size(mut self, max_message_size: u32) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// Sets the most bytes bincode may read or allocate while deserializing a message, with `bincode::Options::with_limit`.
+    ///
+    /// This is a second line of defense behind `max_message_size`: a payload small enough to pass the frame size check
+    /// can still claim a string or byte array billions of bytes long, and deserializing it fails with
+    /// `IpcStreamReadError::DeserializeError` instead of allocating for it. Defaults to `max_message_size`, or to the
+    /// largest possible frame if that isn't set either. The JSON and MessagePack codecs ignore it, since they read
+    /// everything they decode from the payload itself.
+    pub const fn deserialize_limit(mut self, limit: u64) -> Self {
+        self.deserialize_limit = Some(limit);
+        self
+    }
+
+    /// Deserializes a message from a payload read with this framing, bounded by its `deserialize_limit`.
+    ///
+    /// The payload is decoded with the codec selected by the `codec-*` features, which is bincode by default.
+    pub fn deserialize<'a, T: serde::Deserialize<'a>>(
+        &self,
+        payload: &'a [u8],
+    ) -> Result<T, IpcStreamReadError> {
+        let limit = self
+            .deserialize_limit
+            .or(self.max_message_size.map(u64::from))
+            .unwrap_or(u64::from(u32::MAX));
+
+        Ok(crate::codec::deserialize(payload, limit)?)
+    }
+
+    /// Prefixes each message with a protocol version byte, which is checked when the message is read.
+    ///
+    /// Both sides declare the version of the message schema they were built against. Reading a message with a
+    /// different version returns `IpcStreamReadError::UnsupportedVersion` before its payload is read, rather than
+    /// failing to deserialize it.
+    pub const fn versioned(mut self, version: u8) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Skips empty frames when reading, treating them as keepalives rather than messages.
+    ///
+    /// Reads wait for the next non-empty frame instead, and reaching EOF after any number of empty frames is treated
+    /// like reaching it before a message. Writing is unaffected. Since unit types serialize to empty frames, they
+    /// can't be read with this framing.
+    pub const fn skip_empty_frames(mut self) -> Self {
+        self.skip_empty_frames = true;
+        self
+    }
+
+    /// Expects each connection to start with the given 4-byte magic header, before any message is sent.
+    ///
+    /// This quickly catches a peer speaking a different protocol, such as an HTTP client connecting by mistake, which
+    /// would otherwise have its first bytes read as a huge length prefix. Typed servers using this framing check the
+    /// header before reading the request, and clients send it with `ipc_client_connect_framed`. For streams used
+    /// directly, call `write_magic` and `read_magic` at the start of the connection.
+    pub const fn magic(mut self, magic: [u8; 4]) -> Self {
+        self.magic = Some(magic);
+        self
+    }
+
+    /// Adds a deadline to the header of each message, after the length prefix, so a client can declare when it stops
+    /// waiting for the response to a request.
+    ///
+    /// The deadline is a `u64` of milliseconds since the Unix epoch, in the byte order of the length prefix, or little
+    /// endian for varint prefixes. Zero means there is no deadline, which is what every write sends unless it's given
+    /// one, such as with `SocketExt::write_serde_with_deadline`. Typed servers using this framing check the deadline
+    /// of each request once it's read, and if it has already passed, answer with a frame that makes the client's read
+    /// fail with `IpcStreamReadError::DeadlineExceeded` instead of invoking the handler. Multiplexed servers ignore
+    /// the deadline, since their responses must carry a request id.
+    ///
+    /// The deadline is compared against the server's system clock, so both sides should have synchronized clocks.
+    pub const fn deadlines(mut self) -> Self {
+        self.deadlines = true;
+        self
+    }
+
+    /// Writes the magic header that starts a connection, if one is set. See `magic`.
+    pub fn write_magic<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if let Some(magic) = self.magic {
+            writer.write_all(&magic)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the magic header that starts a connection, if one is set, and checks that it matches. See `magic`.
+    ///
+    /// Fails with `IpcStreamReadError::BadMagic` if the connection starts with anything else.
+    pub fn read_magic<R: Read>(&self, reader: &mut R) -> Result<(), IpcStreamReadError> {
+        let Some(expected) = self.magic else {
+            return Ok(());
+        };
+
+        let mut got = [0; 4];
+        reader.read_exact(&mut got)?;
+
+        if got != expected {
+            return Err(IpcStreamReadError::BadMagic { got, expected });
+        }
+
+        Ok(())
+    }
+
+    /// Parses the frame at the start of the given bytes without copying it, and returns its payload along with the
+    /// number of bytes the frame took up.
+    ///
+    /// Returns `Ok(None)` if the bytes end before the frame does, so more of them need to be read before trying again.
+    /// This is the same parsing `read_serde` does, for buffers filled some other way, such as by an event loop. It never
+    /// allocates or panics, whatever the bytes are, and fails with a typed error on a bad version byte, a length prefix
+    /// over `max_message_size` or a checksum mismatch. The first two are caught from the header alone, before the
+    /// payload has arrived. The magic header isn't part of a frame, so it isn't expected here.
+    pub fn parse_frame<'a>(
+        &self,
+        bytes: &'a [u8],
+    ) -> Result<Option<(&'a [u8], usize)>, IpcStreamReadError> {
+        let checksum_len = if self.checksum.is_some() { 4 } else { 0 };
+        let mut used = 0;
+
+        loop {
+            let rest = &bytes[used..];
+
+            let prefix = match (self.version, rest) {
+                (Some(expected), [got, ..]) if *got != expected => {
+                    return Err(IpcStreamReadError::UnsupportedVersion {
+                        got: *got,
+                        expected,
+                    });
+                }
+                (Some(_), [_, prefix @ ..]) | (None, prefix) => prefix,
+                (Some(_), []) => return Ok(None),
+            };
+
+            let Some((len, prefix_len)) = self.decode_prefix(prefix)? else {
+                return Ok(None);
+            };
+
+            self.check_size(len)?;
+            let header_len = usize::from(self.version.is_some()) + prefix_len + self.deadline_len();
+
+            // On 32-bit targets, a length near `u32::MAX` could overflow, and no buffer can hold that many bytes anyway.
+            let frame_len = (len as usize).saturating_add(header_len + checksum_len);
+
+            let Some(frame) = rest.get(..frame_len) else {
+                return Ok(None);
+            };
+
+            let (payload, checksum) = frame[header_len..].split_at(len as usize);
+
+            if let Some(Checksum::Crc32) = self.checksum {
+                let expected = self.decode_u32(checksum);
+                let actual = crc32fast::hash(payload);
+
+                if expected != actual {
+                    return Err(IpcStreamReadError::ChecksumMismatch { expected, actual });
+                }
+            }
+
+            if payload.is_empty()
+                && self.decode_deadline_field(&frame[..header_len]) == DEADLINE_EXCEEDED
+            {
+                return Err(IpcStreamReadError::DeadlineExceeded);
+            }
+
+            used += frame_len;
+
+            if !(payload.is_empty() && self.skip_empty_frames) {
+                return Ok(Some((payload, used)));
+            }
+        }
+    }
+
+    /// Reads the header of a message, and returns its length, or `None` if the reader hit EOF before the first byte of it.
+    ///
+    /// The header is the version byte, if enabled, followed by the length prefix and the deadline, if enabled. Empty
+    /// frames are skipped here if `skip_empty_frames` is set, so every read sees past them.
+    pub(crate) fn try_read_len<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<u32>, IpcStreamReadError> {
+        Ok(self.try_read_frame_header(reader)?.map(|(len, _)| len))
+    }
+
+    /// Like `read_len`, but also returns the deadline of the message, if `deadlines` is enabled and it has one.
+    pub(crate) fn read_len_with_deadline<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<(u32, Option<SystemTime>), IpcStreamReadError> {
+        let (len, deadline) = self
+            .try_read_frame_header(reader)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+        Ok((len, decode_deadline(deadline)))
+    }
+
+    /// Reads the header of the next message that isn't skipped, and returns its length and raw deadline field.
+    ///
+    /// Fails with `IpcStreamReadError::DeadlineExceeded` on the frame a server answers an expired request with.
+    fn try_read_frame_header<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<(u32, u64)>, IpcStreamReadError> {
+        loop {
+            match self.try_read_header(reader)? {
+                Some((0, DEADLINE_EXCEEDED)) => {
+                    self.verify_checksum(reader, &[])?;
+                    return Err(IpcStreamReadError::DeadlineExceeded);
+                }
+                Some((0, _)) if self.skip_empty_frames => self.verify_checksum(reader, &[])?,
+                header => return Ok(header),
+            }
+        }
+    }
+
+    fn try_read_header<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<(u32, u64)>, IpcStreamReadError> {
+        let version_len = usize::from(self.version.is_some());
+
+        // A fixed-width prefix is read along with the version byte and deadline in one go, but a varint's length is
+        // only known once its last byte is, so reading more than its first byte up front could read into the payload.
+        let min_len = match self.length_prefix {
+            LengthPrefix::Varint => version_len + 1,
+            _ => version_len + 4 + self.deadline_len(),
+        };
+
+        let mut header = [0; 1 + MAX_VARINT_LEN + 8];
+
+        let got = loop {
+            match reader.read(&mut header[..min_len]) {
+                Ok(0) => return Ok(None),
+                Ok(got) => break got,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        match self.version {
+            Some(expected) if header[0] != expected => {
+                return Err(IpcStreamReadError::UnsupportedVersion {
+                    got: header[0],
+                    expected,
+                });
+            }
+            _ => reader.read_exact(&mut header[got..min_len])?,
+        }
+
+        // Read the rest of a varint a byte at a time.
+        let mut got = min_len;
+
+        let (len, prefix_len) = loop {
+            if let Some(prefix) = self.decode_prefix(&header[version_len..got])? {
+                break prefix;
+            }
+
+            reader.read_exact(&mut header[got..got + 1])?;
+            got += 1;
+        };
+
+        // The deadline of a varint-prefixed message follows its last byte.
+        let header_len = version_len + prefix_len + self.deadline_len();
+        if got < header_len {
+            reader.read_exact(&mut header[got..header_len])?;
+        }
+
+        Ok(Some((
+            len,
+            self.decode_deadline_field(&header[..header_len]),
+        )))
+    }
+
+    /// Gets the number of bytes the deadline takes up in each header, which is zero unless `deadlines` is enabled.
+    const fn deadline_len(&self) -> usize {
+        if self.deadlines {
+            8
+        } else {
+            0
+        }
+    }
+
+    /// Decodes the deadline field at the end of a header, which is zero if `deadlines` isn't enabled.
+    fn decode_deadline_field(&self, header: &[u8]) -> u64 {
+        let field = &header[header.len() - self.deadline_len()..];
+
+        match (self.deadlines, self.length_prefix) {
+            (false, _) => 0,
+            (true, LengthPrefix::BigEndian) => BigEndian::read_u64(field),
+            (true, _) => LittleEndian::read_u64(field),
+        }
+    }
+
+    /// Decodes the length prefix at the start of the bytes, returning the length and the size of the prefix,
+    /// or `None` if the bytes end before it does.
+    fn decode_prefix(&self, bytes: &[u8]) -> Result<Option<(u32, usize)>, IpcStreamReadError> {
+        if self.length_prefix == LengthPrefix::Varint {
+            return decode_varint(bytes);
+        }
+
+        Ok(bytes.get(..4).map(|prefix| (self.decode_u32(prefix), 4)))
+    }
+
+    /// Decodes a `u32` in the byte order of the length prefix, or little endian for varint prefixes.
+    fn decode_u32(&self, bytes: &[u8]) -> u32 {
+        match self.length_prefix {
+            LengthPrefix::LittleEndian | LengthPrefix::Varint => LittleEndian::read_u32(bytes),
+            LengthPrefix::BigEndian => BigEndian::read_u32(bytes),
+        }
+    }
+
+    /// Reads the header of a message and returns its length, treating EOF as an error.
+    pub(crate) fn read_len<R: Read>(&self, reader: &mut R) -> Result<u32, IpcStreamReadError> {
+        self.try_read_len(reader)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+
+    /// Writes the header of a message, which is the version byte if enabled, followed by the length prefix, and a
+    /// deadline of zero if deadlines are enabled.
+    pub(crate) fn write_len<W: Write>(&self, writer: &mut W, len: u32) -> io::Result<()> {
+        self.write_header(writer, len, 0)
+    }
+
+    /// Like `write_len`, but writes the given raw deadline field, which is left out unless deadlines are enabled.
+    fn write_header<W: Write>(&self, writer: &mut W, len: u32, deadline: u64) -> io::Result<()> {
+        if let Some(version) = self.version {
+            writer.write_all(&[version])?;
+        }
+
+        self.write_prefix(writer, len)?;
+
+        match (self.deadlines, self.length_prefix) {
+            (false, _) => Ok(()),
+            (true, LengthPrefix::BigEndian) => writer.write_u64::<BigEndian>(deadline),
+            (true, _) => writer.write_u64::<LittleEndian>(deadline),
+        }
+    }
+
+    /// Writes the frame that answers a request whose deadline had passed, which makes the client's read fail with
+    /// `IpcStreamReadError::DeadlineExceeded`. It's an empty frame whose deadline field is set to a reserved value.
+    pub(crate) fn write_deadline_exceeded<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), IpcStreamWriteError> {
+        self.write_header(writer, 0, DEADLINE_EXCEEDED)?;
+
+        if let Some(Checksum::Crc32) = self.checksum {
+            self.write_u32(writer, crc32fast::hash(&[]))?;
+        }
+
+        flush(writer)?;
+
+        Ok(())
+    }
+
+    fn write_prefix<W: Write>(&self, writer: &mut W, len: u32) -> io::Result<()> {
+        if self.length_prefix == LengthPrefix::Varint {
+            return write_varint(writer, len);
+        }
+
+        self.write_u32(writer, len)
+    }
+
+    fn read_u32<R: Read>(&self, reader: &mut R) -> io::Result<u32> {
+        match self.length_prefix {
+            LengthPrefix::LittleEndian | LengthPrefix::Varint => reader.read_u32::<LittleEndian>(),
+            LengthPrefix::BigEndian => reader.read_u32::<BigEndian>(),
+        }
+    }
+
+    fn write_u32<W: Write>(&self, writer: &mut W, value: u32) -> io::Result<()> {
+        match self.length_prefix {
+            LengthPrefix::LittleEndian | LengthPrefix::Varint => {
+                writer.write_u32::<LittleEndian>(value)
+            }
+            LengthPrefix::BigEndian => writer.write_u32::<BigEndian>(value),
+        }
+    }
+
+    /// Reads the header of the next message and returns a `FrameReader` to read its payload in chunks.
+    ///
+    /// The maximum message size is checked against the header before anything else is read.
+    pub fn frame_reader<'a, R: Read>(
+        &self,
+        reader: &'a mut R,
+    ) -> Result<FrameReader<'a, R>, IpcStreamReadError> {
+        let len = self.read_len(reader)?;
+        self.check_size(len)?;
+
+        Ok(FrameReader {
+            reader,
+            framing: *self,
+            len,
+            remaining: len,
+            hasher: self
+                .checksum
+                .map(|Checksum::Crc32| crc32fast::Hasher::new()),
+        })
+    }
+
+    /// Creates a `FrameWriter` that queues messages with this framing, for writing to non-blocking streams.
+    pub fn frame_writer(&self) -> FrameWriter {
+        FrameWriter {
+            framing: *self,
+            buffer: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Reads the next message from the reader and returns its raw payload, without deserializing it.
+    ///
+    /// The header is validated, and the checksum verified if enabled, exactly as when reading a serialized message.
+    pub fn read_frame<R: Read>(&self, reader: &mut R) -> Result<Vec<u8>, IpcStreamReadError> {
+        let len = self.read_len(reader)?;
+        self.read_payload(reader, len)
+    }
+
+    /// Writes a raw payload to the writer as one message, with its header and checksum if enabled, then flushes it.
+    pub fn write_frame<W: Write>(
+        &self,
+        writer: &mut W,
+        bytes: &[u8],
+    ) -> Result<(), IpcStreamWriteError> {
+        self.write_payload(writer, bytes)
+    }
+
+    fn check_size(&self, len: u32) -> Result<(), IpcStreamReadError> {
+        match self.max_message_size {
+            Some(max) if len > max => Err(IpcStreamReadError::MessageTooLarge {
+                size: len as usize,
+                max: max as usize,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the payload of a message whose length prefix has already been read, verifying its checksum if enabled.
+    ///
+    /// Returns `IpcStreamReadError::IncompleteMessage` if the reader hits EOF before the whole payload is read.
+    pub(crate) fn read_payload<R: Read>(
+        &self,
+        reader: &mut R,
+        len: u32,
+    ) -> Result<Vec<u8>, IpcStreamReadError> {
+        let mut bytes = Vec::new();
+        self.read_payload_into(reader, len, &mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    /// Like `read_payload`, but reads the payload into the given buffer, replacing its contents and reusing its capacity.
+    pub(crate) fn read_payload_into<R: Read>(
+        &self,
+        reader: &mut R,
+        len: u32,
+        bytes: &mut Vec<u8>,
+    ) -> Result<(), IpcStreamReadError> {
+        self.check_size(len)?;
+
+        // Reading into spare capacity avoids zero-filling the buffer up front, which is measurable for large messages.
+        bytes.clear();
+        bytes.reserve((len as usize).min(MAX_PREALLOCATION));
+        reader.take(len as u64).read_to_end(bytes)?;
+
+        if bytes.len() < len as usize {
+            return Err(IpcStreamReadError::IncompleteMessage {
+                expected: len as usize,
+                got: bytes.len(),
+            });
+        }
+
+        self.verify_checksum(reader, bytes)
+    }
+
+    /// Like `read_payload`, but reads payloads of up to `INLINE_MESSAGE_SIZE` bytes into a buffer on the stack,
+    /// only allocating for larger ones.
+    #[cfg(feature = "smallvec")]
+    pub(crate) fn read_payload_small<R: Read>(
+        &self,
+        reader: &mut R,
+        len: u32,
+    ) -> Result<SmallVec<[u8; INLINE_MESSAGE_SIZE]>, IpcStreamReadError> {
+        if len as usize > INLINE_MESSAGE_SIZE {
+            return self.read_payload(reader, len).map(SmallVec::from_vec);
+        }
+
+        self.check_size(len)?;
+
+        let mut bytes = SmallVec::from_elem(0, len as usize);
+        let mut got = 0;
+
+        while got < bytes.len() {
+            match reader.read(&mut bytes[got..]) {
+                Ok(0) => {
+                    return Err(IpcStreamReadError::IncompleteMessage {
+                        expected: len as usize,
+                        got,
+                    })
+                }
+                Ok(read) => got += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        self.verify_checksum(reader, &bytes)?;
+
+        Ok(bytes)
+    }
+
+    /// Reads the checksum following a payload, if enabled, and checks that it matches the payload.
+    fn verify_checksum<R: Read>(
+        &self,
+        reader: &mut R,
+        bytes: &[u8],
+    ) -> Result<(), IpcStreamReadError> {
+        if let Some(Checksum::Crc32) = self.checksum {
+            let expected = self.read_u32(reader)?;
+            let actual = crc32fast::hash(bytes);
+
+            if expected != actual {
+                return Err(IpcStreamReadError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a length prefix followed by the payload and its checksum if enabled, then flushes the writer.
+    ///
+    /// Flushing ensures the message isn't left sitting in a buffer when the stream is wrapped in a `BufWriter`,
+    /// which would leave the peer blocked waiting for it.
+    pub(crate) fn write_payload<W: Write>(
+        &self,
+        writer: &mut W,
+        bytes: &[u8],
+    ) -> Result<(), IpcStreamWriteError> {
+        let max = self.max_message_size.unwrap_or(u32::MAX) as usize;
+
+        if bytes.len() > max {
+            return Err(IpcStreamWriteError::MessageTooLarge {
+                size: bytes.len(),
+                max,
+            });
+        }
+
+        self.write_len(writer, bytes.len() as u32)?;
+        writer.write_all(bytes)?;
+
+        if let Some(Checksum::Crc32) = self.checksum {
+            self.write_u32(writer, crc32fast::hash(bytes))?;
+        }
+
+        flush(writer)?;
+
+        Ok(())
+    }
+
+    /// Serializes a message straight into the writer, framed exactly as `write_payload` would frame its serialized bytes.
+    ///
+    /// The size is computed up front with `serialized_size`, so the length prefix can be written without
+    /// holding the whole serialized message in memory. Writes go through a `BufWriter` to avoid a syscall per field,
+    /// sized for the frame up to 8 KiB, so small messages don't allocate a full buffer each.
+    ///
+    /// If serializing fails, whatever is still buffered is discarded rather than flushed, so a message that fits in the
+    /// buffer leaves nothing on the writer. A larger one may already have been partly written.
+    pub(crate) fn write_serialized<W: Write, T: serde::Serialize>(
+        &self,
+        writer: &mut W,
+        data: &T,
+    ) -> Result<(), IpcStreamWriteError> {
+        self.write_serialized_until(writer, data, None)
+    }
+
+    /// Like `write_serialized`, but sends the given deadline in the header if deadlines are enabled.
+    pub(crate) fn write_serialized_until<W: Write, T: serde::Serialize>(
+        &self,
+        writer: &mut W,
+        data: &T,
+        deadline: Option<SystemTime>,
+    ) -> Result<(), IpcStreamWriteError> {
+        let size = crate::codec::serialized_size(data)?;
+        let max = self.max_message_size.unwrap_or(u32::MAX);
+
+        if size > max as u64 {
+            return Err(IpcStreamWriteError::MessageTooLarge {
+                size: size as usize,
+                max: max as usize,
+            });
+        }
+
+        let capacity = (size as usize).saturating_add(MAX_FRAME_OVERHEAD);
+        let mut writer = io::BufWriter::with_capacity(capacity.min(MAX_WRITE_BUFFER), writer);
+
+        let result = self.write_frame_into(&mut writer, size as u32, deadline, data);
+
+        if result.is_err() {
+            // Dropping the `BufWriter` would flush the partial frame, so its buffer is taken apart and dropped instead.
+            let _ = writer.into_parts();
+            return result;
+        }
+
+        flush(&mut writer)?;
+
+        Ok(())
+    }
+
+    /// Writes the header, serialized payload and checksum of a message of the given size, for `write_serialized`.
+    fn write_frame_into<W: Write, T: serde::Serialize>(
+        &self,
+        writer: &mut io::BufWriter<W>,
+        size: u32,
+        deadline: Option<SystemTime>,
+        data: &T,
+    ) -> Result<(), IpcStreamWriteError> {
+        self.write_header(writer, size, encode_deadline(deadline))?;
+
+        let mut payload = ChecksumWriter {
+            writer: &mut *writer,
+            hasher: self
+                .checksum
+                .map(|Checksum::Crc32| crc32fast::Hasher::new()),
+        };
+
+        crate::codec::serialize_into(&mut payload, data)?;
+
+        if let Some(hasher) = payload.hasher {
+            self.write_u32(writer, hasher.finalize())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Queues framed messages and writes them to a non-blocking stream, resuming where it left off after `WouldBlock`.
+///
+/// `write_serde` writes a message with `write_all`, so on a non-blocking stream it can fail with `WouldBlock` after
+/// writing part of the frame, which desyncs the protocol. Instead, queue messages here, where each one is serialized
+/// and framed up front, then call `poll_write` whenever the stream is writable until it returns `Poll::Ready`.
+/// A partially written frame is continued from where it stopped, so the peer always sees whole frames.
+///
+/// Created by `Framing::frame_writer`, or with the default framing by `FrameWriter::default`.
+#[derive(Debug, Default)]
+pub struct FrameWriter {
+    framing: Framing,
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+impl FrameWriter {
+    /// Serializes a message and queues it to be written, framed like `write_serde`.
+    ///
+    /// Nothing is queued if serializing fails, so a failed message never leaves a partial frame behind.
+    pub fn queue_serde<T: serde::Serialize>(
+        &mut self,
+        message: &T,
+    ) -> Result<(), IpcStreamWriteError> {
+        let len = self.buffer.len();
+        let result = self.framing.write_serialized(&mut self.buffer, message);
+
+        if result.is_err() {
+            self.buffer.truncate(len);
+        }
+
+        result
+    }
+
+    /// Queues a raw payload to be written as a single frame, like `write_frame`.
+    pub fn queue_frame(&mut self, bytes: &[u8]) -> Result<(), IpcStreamWriteError> {
+        self.framing.write_payload(&mut self.buffer, bytes)
+    }
+
+    /// Gets the number of queued bytes that haven't been written yet.
+    pub fn pending(&self) -> usize {
+        self.buffer.len() - self.written
+    }
+
+    /// Returns `true` if everything queued has been written.
+    pub fn is_empty(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Writes as much of the queue as the writer accepts, then flushes it once the queue is empty.
+    ///
+    /// Returns `Poll::Pending` if the writer fails with `WouldBlock`, in which case call this again once it's writable.
+    /// Returns `Poll::Ready(Ok(()))` once everything queued has been written and flushed. Any other error is returned
+    /// as is, and what was left of the queue stays queued.
+    pub fn poll_write<W: Write>(&mut self, writer: &mut W) -> Poll<io::Result<()>> {
+        while self.written < self.buffer.len() {
+            match writer.write(&self.buffer[self.written..]) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(written) => self.written += written,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+
+        self.buffer.clear();
+        self.written = 0;
+
+        match flush(writer) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+/// Reads the payload of a single message in bounded chunks, without holding all of it in memory.
+///
+/// Created by `Framing::frame_reader` or `SocketExt::read_frame_chunked` once the message's header has been read.
+/// Reads never go past the end of the payload, so the next message is left intact on the stream. If checksums are
+/// enabled, the checksum is computed as the payload is read and verified once the last chunk has been read.
+///
+/// Dropping the reader before the whole payload is read leaves the rest of it on the stream. Call `finish` to skip it.
+pub struct FrameReader<'a, R> {
+    reader: &'a mut R,
+    framing: Framing,
+    len: u32,
+    remaining: u32,
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl<R: Read> FrameReader<'_, R> {
+    /// Gets the length of the whole payload, as declared by the message's header.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Checks whether the payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the number of payload bytes that haven't been read yet.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Reads the next chunk of the payload into `buf`, and returns the number of bytes read.
+    ///
+    /// At most `remaining()` bytes are read, and `Ok(0)` is returned once the whole payload has been read.
+    /// Returns `IpcStreamReadError::IncompleteMessage` if the stream ends before the payload does, and
+    /// `IpcStreamReadError::ChecksumMismatch` from the call that reads the last chunk if the checksum doesn't match.
+    pub fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize, IpcStreamReadError> {
+        if self.remaining == 0 {
+            // Only does anything for an empty payload, since otherwise the checksum was verified with the last chunk.
+            self.verify_checksum()?;
+            return Ok(0);
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.remaining as usize);
+
+        let read = loop {
+            match self.reader.read(&mut buf[..len]) {
+                Ok(0) => {
+                    return Err(IpcStreamReadError::IncompleteMessage {
+                        expected: self.len as usize,
+                        got: (self.len - self.remaining) as usize,
+                    })
+                }
+                Ok(read) => break read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+
+        self.remaining -= read as u32;
+
+        if self.remaining == 0 {
+            self.verify_checksum()?;
+        }
+
+        Ok(read)
+    }
+
+    /// Skips whatever is left of the payload, verifying the checksum if enabled, so the next message can be read.
+    pub fn finish(mut self) -> Result<(), IpcStreamReadError> {
+        let mut buf = [0; 8 * 1024];
+        while self.read_chunk(&mut buf)? > 0 {}
+
+        Ok(())
+    }
+
+    fn verify_checksum(&mut self) -> Result<(), IpcStreamReadError> {
+        if let Some(hasher) = self.hasher.take() {
+            let expected = self.framing.read_u32(self.reader)?;
+            let actual = hasher.finalize();
+
+            if expected != actual {
+                return Err(IpcStreamReadError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An iterator over the chunks of a chunked stream, created by `SocketExt::read_stream`.
+///
+/// Each chunk is the payload of one frame, and the stream ends at the first empty frame.
+pub struct ChunkStream<'a, R> {
+    reader: Option<&'a mut R>,
+}
+
+impl<'a, R: Read> ChunkStream<'a, R> {
+    pub(crate) fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader: Some(reader),
+        }
+    }
+}
+
+impl<R: Read> Iterator for ChunkStream<'_, R> {
+    type Item = Result<Vec<u8>, IpcStreamReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = self.reader.as_mut()?;
+        let framing = Framing::default();
+
+        let result = framing
+            .read_len(reader)
+            .and_then(|len| framing.read_payload(reader, len));
+
+        match result {
+            Ok(chunk) if !chunk.is_empty() => Some(Ok(chunk)),
+            Ok(_) => {
+                self.reader = None;
+                None
+            }
+            Err(e) => {
+                self.reader = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R: Read> std::iter::FusedIterator for ChunkStream<'_, R> {}
+
+/// A writer that computes the checksum of everything written through it, if checksums are enabled.
+struct ChecksumWriter<W> {
+    writer: W,
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes a value as an unsigned LEB128 varint, seven bits per byte starting from the lowest, with the high bit set on
+/// every byte but the last.
+fn write_varint<W: Write>(writer: &mut W, mut value: u32) -> io::Result<()> {
+    let mut bytes = [0; MAX_VARINT_LEN];
+    let mut len = 0;
+
+    loop {
+        bytes[len] = (value & 0x7f) as u8;
+        value >>= 7;
+        len += 1;
+
+        if value == 0 {
+            break;
+        }
+
+        bytes[len - 1] |= 0x80;
+    }
+
+    writer.write_all(&bytes[..len])
+}
+
+/// Decodes an unsigned LEB128 varint from the start of the bytes, returning the value and the number of bytes it took,
+/// or `None` if the bytes end before its last byte.
+///
+/// Fails with `IpcStreamReadError::InvalidLengthPrefix` if the varint runs past five bytes or overflows a `u32`.
+fn decode_varint(bytes: &[u8]) -> Result<Option<(u32, usize)>, IpcStreamReadError> {
+    let mut value = 0;
+
+    for (i, &byte) in bytes.iter().take(MAX_VARINT_LEN).enumerate() {
+        // The fifth byte only has room for the top four bits, and can't be followed by another.
+        if i == MAX_VARINT_LEN - 1 && byte > 0x0f {
+            return Err(IpcStreamReadError::InvalidLengthPrefix);
+        }
+
+        value |= u32::from(byte & 0x7f) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Encodes a deadline as milliseconds since the Unix epoch, or zero if there is none.
+fn encode_deadline(deadline: Option<SystemTime>) -> u64 {
+    let Some(deadline) = deadline else {
+        return 0;
+    };
+
+    let millis = deadline
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis());
+
+    // Zero means there is no deadline and the largest value is reserved, so a deadline is kept between them.
+    millis.clamp(1, u128::from(DEADLINE_EXCEEDED - 1)) as u64
+}
+
+/// Decodes a deadline field, which is zero if there is no deadline.
+fn decode_deadline(millis: u64) -> Option<SystemTime> {
+    (millis != 0).then(|| UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Flushes the writer, retrying if it is interrupted by a signal.
+///
+/// The `read_exact` and `write_all` calls used for everything else already retry on `ErrorKind::Interrupted`,
+/// but `flush` doesn't.
+fn flush<W: Write>(writer: &mut W) -> io::Result<()> {
+    loop {
+        match writer.flush() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use std::io::BufWriter;
+    use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+    /// A transport that fails every other call with `ErrorKind::Interrupted`, like a blocking call hit by a signal.
+    struct Interrupting {
+        inner: std::io::Cursor<Vec<u8>>,
+        interrupt: bool,
+    }
+
+    impl Interrupting {
+        fn interrupted(&mut self) -> bool {
+            self.interrupt = !self.interrupt;
+            self.interrupt
+        }
+    }
+
+    impl Read for Interrupting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.interrupted() {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            // Return a single byte at a time, so every read is followed by an interrupted one.
+            let len = buf.len().min(1);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for Interrupting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.interrupted() {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if self.interrupted() {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn test_retries_interrupted_calls() {
+        use crate::SocketExt;
+
+        let mut transport = Interrupting {
+            inner: std::io::Cursor::new(Vec::new()),
+            interrupt: false,
+        };
+
+        for framing in [Framing::new(), Framing::new().checksum(Checksum::Crc32)] {
+            transport.inner.set_position(0);
+            transport.write_serde_framed(&"hello", &framing).unwrap();
+
+            transport.inner.set_position(0);
+            let message: String = transport.read_serde_framed(&framing).unwrap();
+            assert_eq!(message, "hello");
+        }
+    }
+
+    #[test]
+    fn test_length_delimited_codec_interop() {
+        let framing = Framing::length_delimited();
+        let mut codec = LengthDelimitedCodec::new();
+
+        // Written by us, read by the codec.
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, b"hello").unwrap();
+
+        let mut buffer = BytesMut::from(&written[..]);
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(&frame[..], b"hello");
+
+        // Written by the codec, read by us.
+        let mut buffer = BytesMut::new();
+        codec
+            .encode(bytes::Bytes::from_static(b"world"), &mut buffer)
+            .unwrap();
+
+        let mut reader = &buffer[..];
+        let len = framing.read_len(&mut reader).unwrap();
+        let payload = framing.read_payload(&mut reader, len).unwrap();
+        assert_eq!(payload, b"world");
+    }
+
+    #[test]
+    fn test_write_payload_flushes_buffered_writer() {
+        let mut writer = BufWriter::new(Vec::new());
+
+        Framing::default()
+            .write_payload(&mut writer, b"hello")
+            .unwrap();
+
+        assert_eq!(
+            writer.get_ref(),
+            &[5, 0, 0, 0, b'h', b'e', b'l', b'l', b'o']
+        );
+    }
+
+    #[test]
+    fn test_frame_message_round_trip() {
+        use crate::SocketExt;
+
+        let mut bytes = frame_message(&"hello").unwrap();
+        bytes.extend(frame_message(&42u32).unwrap());
+
+        let (first, used): (String, usize) = unframe_message(&bytes).unwrap();
+        assert_eq!(first, "hello");
+
+        let (second, rest): (u32, usize) = unframe_message(&bytes[used..]).unwrap();
+        assert_eq!(second, 42);
+        assert_eq!(used + rest, bytes.len());
+
+        // The bytes match what's written to a socket.
+        let mut written = std::io::Cursor::new(Vec::new());
+        written.write_serde(&"hello").unwrap();
+        assert_eq!(written.get_ref()[..], bytes[..used]);
+
+        assert!(unframe_message::<String>(&bytes[..used - 1]).is_err());
+    }
+
+    // Only bincode serializes unit types to nothing.
+    #[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+    #[test]
+    fn test_empty_frames() {
+        use crate::SocketExt;
+
+        let mut stream = std::io::Cursor::new(Vec::new());
+        stream.write_frame(&[]).unwrap();
+        stream.write_frame(&[]).unwrap();
+        stream.write_serde(&7u32).unwrap();
+
+        // Unit types serialize to nothing, so an empty frame reads back as one, but not as anything else.
+        stream.set_position(0);
+        stream.read_serde::<()>().unwrap();
+        assert!(matches!(
+            stream.read_serde::<u32>(),
+            Err(IpcStreamReadError::DeserializeError(_))
+        ));
+
+        stream.set_position(0);
+        let framing = Framing::new().skip_empty_frames();
+        assert_eq!(stream.read_serde_framed::<u32>(&framing).unwrap(), 7);
+        assert!(framing.try_read_len(&mut stream).unwrap().is_none());
+
+        // The checksum of a skipped frame is consumed along with it.
+        let framing = framing.checksum(Checksum::Crc32);
+        let mut stream = std::io::Cursor::new(Vec::new());
+        stream.write_serde_framed(&(), &framing).unwrap();
+        stream.write_serde_framed(&7u32, &framing).unwrap();
+
+        stream.set_position(0);
+        assert_eq!(stream.read_serde_framed::<u32>(&framing).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_magic_header() {
+        use crate::SocketExt;
+
+        let framing = Framing::new().magic(*b"IPCU");
+
+        let mut stream = std::io::Cursor::new(Vec::new());
+        framing.write_magic(&mut stream).unwrap();
+        stream.write_serde_framed(&"hello", &framing).unwrap();
+
+        stream.set_position(0);
+        framing.read_magic(&mut stream).unwrap();
+        let message: String = stream.read_serde_framed(&framing).unwrap();
+        assert_eq!(message, "hello");
+
+        // An HTTP request is rejected before its first bytes are taken for a length prefix.
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\n";
+        assert!(matches!(
+            framing.read_magic(&mut reader),
+            Err(IpcStreamReadError::BadMagic { got, .. }) if &got == b"GET "
+        ));
+
+        // Without a magic header, nothing is read or written.
+        let mut written = Vec::new();
+        Framing::new().write_magic(&mut written).unwrap();
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn test_chunked_stream() {
+        use crate::SocketExt;
+
+        let mut stream = std::io::Cursor::new(Vec::new());
+        stream.write_stream([&b"ab"[..], b"", b"cde"]).unwrap();
+
+        let mut file: &[u8] = b"0123456789";
+        assert_eq!(stream.write_stream_from(&mut file, 4).unwrap(), 10);
+        stream.write_serde(&"after").unwrap();
+
+        stream.set_position(0);
+        let chunks = stream.read_stream().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks, [b"ab".to_vec(), b"cde".to_vec()]);
+
+        let file = stream.read_stream().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(file.concat(), b"0123456789");
+        assert_eq!(file.len(), 3);
+
+        // The stream ends at its sentinel, leaving the next message intact.
+        assert_eq!(stream.read_serde::<String>().unwrap(), "after");
+
+        // A stream cut off before its sentinel ends with an error.
+        let mut truncated = std::io::Cursor::new(vec![2, 0, 0, 0, b'a', b'b']);
+        let mut chunks = truncated.read_stream();
+        assert!(chunks.next().unwrap().is_ok());
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    /// A transport that accepts a few bytes at a time, and fails every other call with `ErrorKind::WouldBlock`,
+    /// like a non-blocking socket with a small buffer.
+    struct Congested {
+        written: Vec<u8>,
+        block: bool,
+    }
+
+    impl Write for Congested {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.block = !self.block;
+
+            if self.block {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            let len = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_frame_writer_resumes_partial_writes() {
+        use crate::SocketExt;
+
+        let framing = Framing::new().checksum(Checksum::Crc32);
+        let mut writer = framing.frame_writer();
+        let mut stream = Congested {
+            written: Vec::new(),
+            block: false,
+        };
+
+        writer.queue_serde(&"hello".to_string()).unwrap();
+        writer.queue_frame(b"raw").unwrap();
+
+        let mut polls = 0;
+        while writer.poll_write(&mut stream).is_pending() {
+            polls += 1;
+
+            // Messages can still be queued while a frame is half written.
+            if polls == 1 {
+                writer.queue_serde(&42u32).unwrap();
+            }
+        }
+
+        assert!(polls > 1);
+        assert!(writer.is_empty());
+
+        let mut written = std::io::Cursor::new(stream.written);
+        assert_eq!(
+            written.read_serde_framed::<String>(&framing).unwrap(),
+            "hello"
+        );
+        let len = framing.read_len(&mut written).unwrap();
+        assert_eq!(framing.read_payload(&mut written, len).unwrap(), b"raw");
+        assert_eq!(written.read_serde_framed::<u32>(&framing).unwrap(), 42);
+
+        // A message that fails to serialize leaves nothing behind.
+        let mut writer = Framing::new().max_message_size(4).frame_writer();
+        assert!(writer.queue_serde(&"too long".to_string()).is_err());
+        assert!(writer.is_empty());
+    }
+
+    /// A xorshift generator, so the random inputs are the same on every run.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_random_input() {
+        let framings = [
+            Framing::new(),
+            Framing::length_delimited().max_message_size(64),
+            Framing::new().versioned(1).checksum(Checksum::Crc32),
+            Framing::new().skip_empty_frames().max_message_size(16),
+            Framing::new()
+                .length_prefix(LengthPrefix::Varint)
+                .checksum(Checksum::Crc32),
+            Framing::new().deadlines().checksum(Checksum::Crc32),
+            Framing::new()
+                .length_prefix(LengthPrefix::Varint)
+                .deadlines(),
+        ];
+        let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..20_000 {
+            let framing = framings[rng.next() as usize % framings.len()];
+            let len = rng.next() as usize % 48;
+            let mut bytes = rng.bytes(len);
+
+            // Small length prefixes are far more interesting than random ones, which are almost never complete.
+            if rng.next() & 1 == 0 {
+                let header = usize::from(framing.version.is_some());
+                let prefix = (rng.next() % 40) as u32;
+                let mut framed = rng.bytes(header);
+                framing.write_prefix(&mut framed, prefix).unwrap();
+                bytes.splice(..bytes.len().min(framed.len()), framed);
+            }
+
+            // The reader takes the same path as `read_serde`, and must agree with the parser.
+            let mut reader = &bytes[..];
+            let read = framing
+                .read_len(&mut reader)
+                .and_then(|len| framing.read_payload(&mut reader, len));
+
+            match framing.parse_frame(&bytes) {
+                Ok(Some((payload, used))) => {
+                    assert!(used <= bytes.len());
+                    assert!(payload.len() as u32 <= framing.max_message_size.unwrap_or(u32::MAX));
+                    assert_eq!(read.unwrap(), payload);
+                    assert_eq!(bytes.len() - reader.len(), used);
+                }
+                Ok(None) => assert!(read.is_err()),
+                Err(e) => assert_eq!(read.unwrap_err().to_string(), e.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_varint_length_prefix() {
+        let framing = Framing::new().length_prefix(LengthPrefix::Varint);
+
+        for (len, prefix_len) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (2_097_151, 3),
+            (2_097_152, 4),
+        ] {
+            let payload = vec![7; len];
+            let mut bytes = Vec::new();
+            framing.write_payload(&mut bytes, &payload).unwrap();
+            assert_eq!(
+                bytes.len(),
+                prefix_len + len,
+                "prefix of a {len} byte payload"
+            );
+
+            let mut reader = &bytes[..];
+            let read_len = framing.read_len(&mut reader).unwrap();
+            assert_eq!(
+                framing.read_payload(&mut reader, read_len).unwrap(),
+                payload
+            );
+            assert_eq!(
+                framing.parse_frame(&bytes).unwrap(),
+                Some((&payload[..], bytes.len()))
+            );
+        }
+
+        let mut prefix = Vec::new();
+        write_varint(&mut prefix, u32::MAX).unwrap();
+        assert_eq!(prefix, [0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(decode_varint(&prefix).unwrap(), Some((u32::MAX, 5)));
+
+        // Overflowing a u32, running past five bytes, or ending early are all caught.
+        assert!(matches!(
+            decode_varint(&[0xff, 0xff, 0xff, 0xff, 0x10]),
+            Err(IpcStreamReadError::InvalidLengthPrefix)
+        ));
+        assert!(matches!(
+            decode_varint(&[0x80; 6]),
+            Err(IpcStreamReadError::InvalidLengthPrefix)
+        ));
+        assert_eq!(decode_varint(&[0x80, 0x80]).unwrap(), None);
+
+        // A 50 byte message, 42 bytes after their bincode length, takes a single byte of prefix.
+        #[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+        {
+            use crate::SocketExt;
+
+            let mut stream = std::io::Cursor::new(Vec::new());
+            stream.write_serde_framed(&vec![1u8; 42], &framing).unwrap();
+            assert_eq!(stream.get_ref().len(), 51);
+            stream.set_position(0);
+            assert_eq!(
+                stream.read_serde_framed::<Vec<u8>>(&framing).unwrap(),
+                [1; 42]
+            );
+        }
+    }
+
+    #[test]
+    fn test_huge_length_prefix_is_not_preallocated() {
+        let mut reader: &[u8] = &[0xff, 0xff, 0xff, 0xff, 1, 2];
+        let framing = Framing::new();
+
+        let len = framing.read_len(&mut reader).unwrap();
+        assert!(matches!(
+            framing.read_payload(&mut reader, len),
+            Err(IpcStreamReadError::IncompleteMessage { got: 2, .. })
+        ));
+
+        assert!(matches!(
+            framing.max_message_size(1024).parse_frame(&[0xff; 8]),
+            Err(IpcStreamReadError::MessageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_incomplete_message() {
+        let framing = Framing::default();
+
+        // Only the length prefix is sent before the peer disconnects.
+        let mut reader: &[u8] = &[5, 0, 0, 0];
+        let len = framing.read_len(&mut reader).unwrap();
+        let result = framing.read_payload(&mut reader, len);
+
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::IncompleteMessage {
+                expected: 5,
+                got: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_serialized_matches_write_payload() {
+        let message = ("hello".to_string(), vec![1u64, 2, 3], Some(-4i32));
+        let bytes = crate::codec::serialize(&message).unwrap();
+
+        for framing in [
+            Framing::new(),
+            Framing::length_delimited().versioned(3),
+            Framing::new().checksum(Checksum::Crc32),
+        ] {
+            let mut buffered = Vec::new();
+            framing.write_payload(&mut buffered, &bytes).unwrap();
+
+            let mut streamed = Vec::new();
+            framing.write_serialized(&mut streamed, &message).unwrap();
+
+            assert_eq!(streamed, buffered);
+        }
+    }
+
+    #[test]
+    fn test_write_serialized_discards_failed_frame() {
+        use serde::ser::{Error, SerializeTuple};
+
+        /// Serializes fine while its size is computed, then fails halfway through being written.
+        struct FailsWhenWritten(std::cell::Cell<u32>);
+
+        impl serde::Serialize for FailsWhenWritten {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.set(self.0.get() + 1);
+
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&1u32)?;
+
+                if self.0.get() > 1 {
+                    return Err(S::Error::custom("failed halfway"));
+                }
+
+                tuple.serialize_element(&2u32)?;
+                tuple.end()
+            }
+        }
+
+        let mut written = Vec::new();
+        let result = Framing::new().write_serialized(&mut written, &FailsWhenWritten(0.into()));
+
+        assert!(matches!(
+            result,
+            Err(IpcStreamWriteError::SerializeError(_))
+        ));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn test_frame_reader_reads_in_chunks() {
+        let framing = Framing::new().checksum(Checksum::Crc32);
+        let payload = (0..100u8).collect::<Vec<_>>();
+
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, &payload).unwrap();
+        framing.write_payload(&mut written, b"next").unwrap();
+
+        let mut reader = &written[..];
+        let mut frame = framing.frame_reader(&mut reader).unwrap();
+        assert_eq!(frame.len(), 100);
+
+        let mut read = Vec::new();
+        let mut chunk = [0; 32];
+
+        loop {
+            let n = frame.read_chunk(&mut chunk).unwrap();
+            if n == 0 {
+                break;
+            }
+
+            read.extend_from_slice(&chunk[..n]);
+            assert_eq!(frame.remaining() as usize, payload.len() - read.len());
+        }
+
+        assert_eq!(read, payload);
+
+        // The next message is left intact, and can be partially read and then skipped.
+        let mut frame = framing.frame_reader(&mut reader).unwrap();
+        assert_eq!(frame.read_chunk(&mut chunk[..2]).unwrap(), 2);
+        frame.finish().unwrap();
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "smallvec")]
+    fn test_read_payload_small() {
+        let framing = Framing::new().checksum(Checksum::Crc32);
+        let large = vec![7; INLINE_MESSAGE_SIZE + 1];
+
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, b"hello").unwrap();
+        framing.write_payload(&mut written, &large).unwrap();
+
+        let mut reader = &written[..];
+
+        let len = framing.read_len(&mut reader).unwrap();
+        let small = framing.read_payload_small(&mut reader, len).unwrap();
+        assert!(!small.spilled());
+        assert_eq!(&small[..], b"hello");
+
+        let len = framing.read_len(&mut reader).unwrap();
+        let spilled = framing.read_payload_small(&mut reader, len).unwrap();
+        assert!(spilled.spilled());
+        assert_eq!(&spilled[..], &large[..]);
+    }
+
+    #[test]
+    fn test_matching_version() {
+        let framing = Framing::new().versioned(2);
+
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, b"hello").unwrap();
+        assert_eq!(written[..5], [2, 5, 0, 0, 0]);
+
+        let mut reader = &written[..];
+        let len = framing.read_len(&mut reader).unwrap();
+
+        assert_eq!(framing.read_payload(&mut reader, len).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_mismatched_version() {
+        let mut written = Vec::new();
+        Framing::new()
+            .versioned(1)
+            .write_payload(&mut written, b"hello")
+            .unwrap();
+
+        let result = Framing::new().versioned(2).read_len(&mut &written[..]);
+
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::UnsupportedVersion {
+                got: 1,
+                expected: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let framing = Framing::new().checksum(Checksum::Crc32);
+
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, b"hello").unwrap();
+
+        // Flip a byte of the payload.
+        written[4] ^= 0xff;
+
+        let mut reader = &written[..];
+        let len = framing.read_len(&mut reader).unwrap();
+        let result = framing.read_payload(&mut reader, len);
+
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_max_message_size() {
+        let framing = Framing::new().max_message_size(4);
+
+        let mut written = Vec::new();
+        let result = framing.write_payload(&mut written, b"hello");
+        assert!(matches!(
+            result,
+            Err(IpcStreamWriteError::MessageTooLarge { size: 5, max: 4 })
+        ));
+        assert!(written.is_empty());
+
+        let mut reader: &[u8] = &[5, 0, 0, 0, b'h', b'e', b'l', b'l', b'o'];
+        let len = framing.read_len(&mut reader).unwrap();
+        let result = framing.read_payload(&mut reader, len);
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::MessageTooLarge { size: 5, max: 4 })
+        ));
+    }
+
+    // The limit only applies to bincode, whose length prefixes can claim more than the payload holds.
+    #[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+    #[test]
+    fn test_deserialize_limit() {
+        use crate::SocketExt;
+
+        // A 12 byte payload claiming to hold a string of a billion bytes fails without allocating for it.
+        let mut payload = 1_000_000_000u64.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"abcd");
+
+        let mut framed = Vec::new();
+        Framing::new().write_payload(&mut framed, &payload).unwrap();
+
+        let framing = Framing::new().max_message_size(64);
+        let result = std::io::Cursor::new(framed).read_serde_framed::<String>(&framing);
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::DeserializeError(_))
+        ));
+
+        // The limit can be set tighter than the frame size.
+        let message = "a".repeat(16);
+        let bytes = bincode::serialize(&message).unwrap();
+        assert_eq!(
+            Framing::new().deserialize::<String>(&bytes).unwrap(),
+            message
+        );
+
+        let result = Framing::new()
+            .deserialize_limit(8)
+            .deserialize::<String>(&bytes);
+        assert!(matches!(
+            result,
+            Err(IpcStreamReadError::DeserializeError(crate::CodecError::Bincode(e)))
+                if matches!(*e, bincode::ErrorKind::SizeLimit)
+        ));
+    }
+
+    #[test]
+    fn test_large_payload() {
+        let framing = Framing::default();
+        let payload = (0..4 * 1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let mut written = Vec::new();
+        framing.write_payload(&mut written, &payload).unwrap();
+
+        let mut reader = &written[..];
+        let len = framing.read_len(&mut reader).unwrap();
+        let read = framing.read_payload(&mut reader, len).unwrap();
+
+        assert_eq!(read, payload);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{IpcServerBuilder, IpcServerError, IpcServerGroupError};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+/// Tracks several servers started by one process, so they can be stopped and joined together.
+///
+/// Each server is started with a shutdown channel, as with `IpcServerBuilder::shutdown_on`, so `shutdown_all`
+/// stops every server once it finishes the connection it's handling. `join_all` then waits for all of them,
+/// removes their socket files, and collects the errors of any that failed.
+#[derive(Default)]
+pub struct IpcServerGroup {
+    servers: Vec<GroupedServer>,
+}
+
+struct GroupedServer {
+    socket: String,
+    socket_file: Option<String>,
+    shutdown: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl IpcServerGroup {
+    /// Creates an empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a server from the builder and adds it to the group.
+    ///
+    /// `start` is passed the builder with the group's shutdown channel set, and starts the server on its own thread,
+    /// for example with `|builder| builder.serve(handler)`. If it fails, the error is returned and nothing is added.
+    pub fn start(
+        &mut self,
+        builder: IpcServerBuilder,
+        start: impl FnOnce(IpcServerBuilder) -> Result<JoinHandle<()>, IpcServerError>,
+    ) -> Result<(), IpcServerError> {
+        let socket = builder.socket_name();
+
+        #[cfg(unix)]
+        let socket_file = builder.socket_file();
+        #[cfg(not(unix))]
+        let socket_file = None;
+
+        let (shutdown, receiver) = mpsc::channel();
+        let handle = start(builder.shutdown_on(receiver))?;
+
+        self.servers.push(GroupedServer {
+            socket,
+            socket_file,
+            shutdown,
+            handle,
+        });
+
+        Ok(())
+    }
+
+    /// Gets the number of servers in the group.
+    pub fn len(&self) -> usize {
+        self.servers.len()
+    }
+
+    /// Returns `true` if no servers have been added to the group.
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Returns an iterator over the socket names of the servers in the group, in the order they were started.
+    pub fn sockets(&self) -> impl Iterator<Item = &str> {
+        self.servers.iter().map(|server| server.socket.as_str())
+    }
+
+    /// Signals every server in the group to stop. Servers that already stopped are skipped.
+    pub fn shutdown_all(&self) {
+        for server in &self.servers {
+            let _ = server.shutdown.send(());
+        }
+    }
+
+    /// Waits for every server in the group to stop, then removes their socket files.
+    ///
+    /// This doesn't stop the servers itself, so call `shutdown_all` first unless they stop on their own.
+    /// Fails with the `IpcServerError::ThreadJoinError` of each server whose thread panicked.
+    pub fn join_all(self) -> Result<(), IpcServerGroupError> {
+        let mut errors = Vec::new();
+
+        for server in self.servers {
+            if let Err(payload) = server.handle.join() {
+                errors.push((server.socket, IpcServerError::ThreadJoinError(payload)));
+            }
+
+            if let Some(socket_file) = server.socket_file {
+                let _ = std::fs::remove_file(socket_file);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(IpcServerGroupError(errors));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_shuts_down_and_joins_all() {
+        let sockets = [
+            "/tmp/ipc-util-test-group-a.sock",
+            "/tmp/ipc-util-test-group-b.sock",
+        ];
+        let mut group = IpcServerGroup::new();
+
+        for socket in sockets {
+            group
+                .start(IpcServerBuilder::new(socket), |builder| {
+                    builder.serve(|request: u32| Some(request + 1))
+                })
+                .expect("Failed to start server");
+        }
+
+        assert_eq!(group.len(), 2);
+        assert!(group.sockets().eq(sockets));
+
+        for socket in sockets {
+            assert_eq!(crate::send_ipc_query::<u32, u32>(socket, &1).unwrap(), 2);
+        }
+
+        group.shutdown_all();
+        group.join_all().unwrap();
+
+        #[cfg(unix)]
+        for socket in sockets {
+            assert!(!std::path::Path::new(socket).exists());
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::builder::InstanceGuard;
+use crate::{IpcServerBuilder, IpcServerError};
+use futures_core::Stream;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// An async stream of the connections accepted on a socket, returned by `incoming_async`.
+///
+/// Connections are accepted on a background thread, one ahead of the consumer, so the stream can be polled from any
+/// async runtime. Dropping the stream stops accepting and closes the listener.
+pub struct IncomingAsync {
+    receiver: mpsc::Receiver<io::Result<LocalSocketStream>>,
+    name: String,
+}
+
+impl Stream for IncomingAsync {
+    type Item = io::Result<LocalSocketStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for IncomingAsync {
+    fn drop(&mut self) {
+        // The accept thread notices the stream is gone the next time it accepts a connection, so make one.
+        self.receiver.close();
+        crate::utils::poke(&self.name);
+    }
+}
+
+/// Binds to the given socket, performing the same stale socket recovery as `start_ipc_listener`, and returns an async
+/// stream of the incoming connections.
+///
+/// This is the async counterpart to `bind_ipc_listener`, for handling connections with your own concurrency control,
+/// such as `for_each_concurrent`. The streams it yields are blocking, so handle them with `spawn_blocking` or similar.
+pub fn incoming_async(socket: impl Into<String>) -> Result<IncomingAsync, IpcServerError> {
+    IpcServerBuilder::new(socket).incoming_async()
+}
+
+impl IncomingAsync {
+    /// Spawns the thread accepting connections on the listener, which holds the instance guard until it stops.
+    pub(crate) fn spawn(
+        listener: LocalSocketListener,
+        instance_guard: Option<InstanceGuard>,
+        name: String,
+    ) -> Result<Self, IpcServerError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let poked = name.clone();
+
+        std::thread::Builder::new()
+            .name(format!("ipc incoming '{name}'"))
+            .spawn(move || {
+                let _instance_guard = instance_guard;
+
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) if crate::utils::take_poke(&stream, &poked) => break,
+                        // Once the stream is dropped, drop connections until its poke arrives, so it isn't left behind.
+                        Ok(stream) => {
+                            let _ = sender.blocking_send(Ok(stream));
+                        }
+                        Err(e) => {
+                            if sender.blocking_send(Err(e)).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .map_err(IpcServerError::ThreadSpawnError)?;
+
+        Ok(Self { receiver, name })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SocketExt;
+    use std::future::poll_fn;
+
+    #[test]
+    fn test_incoming_async() {
+        let socket = "/tmp/ipc-util-test-incoming-async.sock";
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut incoming = incoming_async(socket).expect("Failed to bind");
+
+        for i in 0..3u32 {
+            let client = std::thread::spawn(move || crate::send_ipc_message(socket, &i).unwrap());
+
+            let mut stream = runtime
+                .block_on(poll_fn(|cx| Pin::new(&mut incoming).poll_next(cx)))
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(stream.read_serde::<u32>().unwrap(), i);
+            client.join().unwrap();
+        }
+
+        drop(incoming);
+
+        // The listener is closed once the stream is dropped.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(crate::ipc_client_connect(socket).is_err());
+    }
+}
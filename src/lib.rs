@@ -2,7 +2,7 @@
 //!
 //! # Examples
 //!
-//! ```
+//! ```no_run
 //! use interprocess::local_socket::NameTypeSupport;
 //! use ipc_util::{send_ipc_message, send_ipc_query, start_ipc_listener, SocketExt};
 //! use serde::{Deserialize, Serialize};
@@ -87,19 +87,103 @@ pub use errors::*;
 mod ext;
 pub use ext::*;
 
+mod executor;
+pub use executor::*;
+
+mod framing;
+pub use framing::*;
+
+mod metrics;
+pub use metrics::*;
+
+#[cfg(feature = "test-transport")]
+mod memory;
+#[cfg(feature = "test-transport")]
+pub use memory::{MemoryListener, MemoryStream};
+
+#[cfg(feature = "auth")]
+mod auth;
+#[cfg(feature = "auth")]
+pub use auth::AuthConfig;
+
+mod builder;
+pub use builder::*;
+
+#[cfg(unix)]
+mod cancel;
+#[cfg(unix)]
+pub use cancel::*;
+
+mod client;
+pub use client::*;
+
+mod codec;
+pub use codec::CODEC;
+
+mod connection;
+pub use connection::*;
+
+mod discovery;
+pub use discovery::*;
+
+mod duplex;
+pub use duplex::*;
+
+mod group;
+pub use group::*;
+
+#[cfg(feature = "tokio")]
+mod incoming;
+#[cfg(feature = "tokio")]
+pub use incoming::{incoming_async, IncomingAsync};
+
+mod name;
+pub use name::*;
+
+mod pool;
+pub use pool::*;
+
+mod pubsub;
+pub use pubsub::*;
+
+mod query;
+pub use query::*;
+
+mod router;
+pub use router::*;
+
+mod tcp;
+pub use tcp::*;
+
+mod throttle;
+pub use throttle::*;
+
+#[cfg(unix)]
+mod datagram;
+#[cfg(unix)]
+pub use datagram::*;
+
+#[cfg(unix)]
+mod fd;
+
 mod utils;
-use utils::current_process_instance_count;
 
 use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::io;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 
 /// Attempts to spin up a thread that will listen for incoming connections on the given socket.
 ///
 /// If the socket is already in use, it will check if there is more than one instance of the current process.
 /// If there is, it will exit with an error.
+/// Otherwise the socket file is assumed to be stale and is deleted, which `IpcServerBuilder::stale_socket_policy`
+/// can turn off.
 ///
 /// It then creates a new thread where it will listen for incoming connections, and
 /// invoke the passed `handle_connection` function.
@@ -109,48 +193,83 @@ use std::thread::JoinHandle;
 /// * `socket` - The socket name to listen on.
 /// * `handle_connection` - A function that will be invoked for each incoming connection.
 /// * `handle_error` - An optional function that will be invoked if there is an error accepting a connection.
+///   It returns an `ErrorAction` deciding whether to keep accepting connections. When `None`, the server always continues.
 pub fn start_ipc_listener<F: Fn(LocalSocketStream) + Send + 'static>(
-    socket: &str,
+    socket: impl Into<String>,
     on_connection: F,
-    on_connection_error: Option<fn(io::Error)>,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
 ) -> Result<JoinHandle<()>, IpcServerError> {
-    let listener = match LocalSocketListener::bind(socket) {
-        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
-            if current_process_instance_count() > 1 {
-                return Err(IpcServerError::AlreadyInUseError);
-            }
+    default_builder(socket, on_connection_error).listen(on_connection)
+}
 
-            // The address was in use but there's no instances of this process running,
-            // so it's likely a leftover socket file that we can delete.
-            eprintln!("WARNING: Socket file already in use, deleting it and trying again.");
+/// A variant of `start_ipc_listener` where `on_connection` also receives mutable access to a state value.
+///
+/// The state is owned by the server thread and shared across all connections, which are handled one at a time.
+/// See `IpcServerBuilder::listen_with_state`.
+pub fn start_ipc_listener_with_state<S, F>(
+    socket: impl Into<String>,
+    state: S,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError>
+where
+    S: Send + 'static,
+    F: Fn(&mut S, LocalSocketStream) + Send + 'static,
+{
+    default_builder(socket, on_connection_error).listen_with_state(state, on_connection)
+}
 
-            std::fs::remove_file(socket).map_err(IpcServerError::FileError)?;
-            LocalSocketListener::bind(socket).map_err(IpcServerError::BindError)?
-        }
-        Err(e) => return Err(IpcServerError::BindError(e)),
-        Ok(listener) => listener,
-    };
+/// A variant of `start_ipc_listener` where `on_connection` also receives a `ConnectionInfo` describing the connection,
+/// such as its id and the peer's process id. See `IpcServerBuilder::listen_with_info`.
+pub fn start_ipc_listener_with_info<F: Fn(LocalSocketStream, &ConnectionInfo) + Send + 'static>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).listen_with_info(on_connection)
+}
 
-    let error_handler = move |inc: Result<LocalSocketStream, io::Error>| match inc {
-        Ok(conn) => Some(conn),
-        Err(e) => {
-            if let Some(on_connection_error) = on_connection_error {
-                on_connection_error(e);
-            }
-            None
-        }
-    };
+/// Like `start_ipc_listener`, but accepts connections on an already-bound listening Unix socket rather than binding one.
+///
+/// This is meant for zero-downtime restarts, where a supervisor hands the listener off across `exec`.
+/// See `IpcServerBuilder::inherit_listener` for details.
+#[cfg(unix)]
+pub fn start_ipc_listener_from_fd<F: Fn(LocalSocketStream) + Send + 'static>(
+    fd: std::os::unix::io::OwnedFd,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    use std::os::unix::io::AsRawFd;
 
-    let thread = std::thread::Builder::new()
-        .name(format!("ipc server '{socket}'"))
-        .spawn(move || {
-            for stream in listener.incoming().filter_map(error_handler) {
-                on_connection(stream);
-            }
-        })
-        .map_err(IpcServerError::ThreadSpawnError)?;
+    let name = format!("fd {}", fd.as_raw_fd());
+
+    default_builder(name, on_connection_error)
+        .inherit_listener(fd)
+        .listen(on_connection)
+}
+
+/// Binds to the given socket, performing the same stale socket recovery as `start_ipc_listener`, and returns the listener.
+///
+/// Unlike `start_ipc_listener`, this doesn't spawn a thread or start accepting connections. This is useful for driving
+/// `incoming()` yourself, e.g. from an existing event loop or with custom accept logic.
+pub fn bind_ipc_listener(socket: impl Into<String>) -> Result<LocalSocketListener, IpcServerError> {
+    bind_ipc_listener_with_outcome(socket).map(|(listener, _)| listener)
+}
 
-    Ok(thread)
+/// Like `bind_ipc_listener`, but also returns whether a stale socket file had to be deleted to bind the socket.
+pub fn bind_ipc_listener_with_outcome(
+    socket: impl Into<String>,
+) -> Result<(LocalSocketListener, BindOutcome), IpcServerError> {
+    let bound = IpcServerBuilder::new(socket).bind_socket()?;
+    Ok((bound.listener, bound.outcome))
+}
+
+/// Checks whether `start_ipc_listener` could bind to the given socket, without binding it for good or starting a thread.
+///
+/// Fails with the same errors `start_ipc_listener` would, such as `IpcServerError::AlreadyInUseError` when another
+/// instance of the program is running. See `IpcServerBuilder::can_bind`.
+pub fn can_bind(socket: impl Into<String>) -> Result<(), IpcServerError> {
+    IpcServerBuilder::new(socket).can_bind()
 }
 
 /// A wrapper around `start_ipc_listener`.
@@ -162,47 +281,831 @@ pub fn start_ipc_server<
     TResponse: Serialize,
     F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
 >(
-    socket: &str,
+    socket: impl Into<String>,
     on_connection: F,
-    on_connection_error: Option<fn(io::Error)>,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
 ) -> Result<JoinHandle<()>, IpcServerError> {
-    start_ipc_listener(
-        socket,
-        move |mut stream| {
-            let request: TRequest = stream.read_serde().unwrap();
+    default_builder(socket, on_connection_error).serve(on_connection)
+}
 
-            if let Some(response) = on_connection(request) {
-                stream.write_serde(&response).unwrap();
-            }
-        },
-        on_connection_error,
-    )
+/// A variant of `start_ipc_server` that takes an `FnMut` handler, so it can mutate its own state without interior
+/// mutability.
+///
+/// Requests are handled one at a time on the listener thread, so this can't be combined with the concurrent or
+/// pooled servers, such as `IpcServerBuilder::serve_concurrent`. See `IpcServerBuilder::serve_mut`.
+pub fn start_ipc_server_mut<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: FnMut(TRequest) -> Option<TResponse> + Send + 'static,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_mut(on_connection)
+}
+
+/// A variant of `start_ipc_server` where `on_connection` also receives a `ConnectionInfo` describing the connection,
+/// such as its id and the peer's process id. See `IpcServerBuilder::serve_with_info`.
+pub fn start_ipc_server_with_info<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest, &ConnectionInfo) -> Option<TResponse> + Send + 'static,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_with_info(on_connection)
+}
+
+/// A variant of `start_ipc_server` where the `on_connection` callback also receives a mutable reference to the stream.
+///
+/// This bridges the gap between `start_ipc_listener` and `start_ipc_server`: the request is read and deserialized for you,
+/// but the handler can still write additional messages or use the stream directly before optionally returning a response.
+pub fn start_ipc_server_with_stream<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest, &mut LocalSocketStream) -> Option<TResponse> + Send + 'static,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_with_stream(on_connection)
+}
+
+/// A variant of `start_ipc_server_with_stream` where the handler can also stop the server.
+///
+/// Returning `ControlFlow::Break` from `on_connection` writes the response, if there is one, and then finishes
+/// the accept loop, so the returned `JoinHandle` can be joined. This suits an administrative `Shutdown` request
+/// that tells a daemon to exit. See `IpcServerBuilder::serve_with_control` for details.
+pub fn start_ipc_server_with_control<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest, &mut LocalSocketStream) -> ControlFlow<Option<TResponse>, Option<TResponse>>
+        + Send
+        + 'static,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_with_control(on_connection)
+}
+
+/// A variant of `start_ipc_server` that stops after handling `n` connections, then removes the socket file.
+///
+/// See `IpcServerBuilder::serve_n` for details, and `serve_ipc_once` for the common case of a single connection.
+pub fn start_ipc_server_n<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+>(
+    socket: impl Into<String>,
+    n: usize,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_n(n, on_connection)
+}
+
+/// A variant of `start_ipc_server` that stops after handling a single connection, then removes the socket file.
+pub fn serve_ipc_once<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    default_builder(socket, on_connection_error).serve_once(on_connection)
+}
+
+/// Binds the socket and serves requests on the current thread until Ctrl-C is pressed, then removes the socket file.
+///
+/// This is a one-call way to run a daemon that shuts down cleanly. The request being handled when the signal arrives
+/// is allowed to finish. See `IpcServerBuilder::serve_until_ctrl_c` for details.
+#[cfg(feature = "ctrlc")]
+pub fn serve_until_ctrl_c<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest) -> Option<TResponse>,
+>(
+    socket: impl Into<String>,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<(), IpcServerError> {
+    default_builder(socket, on_connection_error).serve_until_ctrl_c(on_connection)
+}
+
+fn default_builder(
+    socket: impl Into<String>,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> IpcServerBuilder {
+    let builder = IpcServerBuilder::new(socket);
+
+    match on_connection_error {
+        Some(on_connection_error) => builder.on_connection_error(on_connection_error),
+        None => builder,
+    }
 }
 
 /// Connects to the socket and writes a serializable object to it.
 /// Meant to be used for requests that don't expect a response from the server.
 pub fn send_ipc_message<TRequest: Serialize>(
-    socket_name: &str,
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+) -> Result<(), IpcClientError> {
+    #[cfg(feature = "test-transport")]
+    if let Some(mut stream) = MemoryStream::connect_registered(socket_name.as_ref()) {
+        stream.write_serde(&request)?;
+        return Ok(());
+    }
+
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    stream.write_serde(&request)?;
+    Ok(())
+}
+
+/// Connects to the socket and writes a serializable object to it, then waits for the server to acknowledge it.
+///
+/// The server acknowledges the message once its handler has returned, so unlike `send_ipc_message`, a successful
+/// return means the message was actually handled. The server must be configured with `IpcServerBuilder::acknowledge_messages`.
+/// This costs an extra round trip compared to `send_ipc_message`.
+pub fn send_ipc_message_acked<TRequest: Serialize>(
+    socket_name: impl AsRef<str>,
     request: &TRequest,
 ) -> Result<(), IpcClientError> {
-    let mut stream = LocalSocketStream::connect(socket_name)?;
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
     stream.write_serde(&request)?;
+    stream.read_frame()?;
     Ok(())
 }
 
+/// Like `send_ipc_message`, but retries up to `retries` times if the server is unreachable or drops the connection,
+/// such as while it restarts.
+///
+/// Connecting and writing are retried on `ConnectionRefused`, `ConnectionReset` and `BrokenPipe`, and connecting is also
+/// retried on `NotFound`, since the socket file may briefly be missing. Retries back off exponentially, starting at 10ms.
+///
+/// A write is only retried if none of the message's bytes were accepted by the socket, so the server can't have received
+/// any of it. This keeps delivery at-most-once: a message is never delivered twice, but if the connection breaks after
+/// part of it was sent, the error is returned even though the server may not have handled it. Pair the server with
+/// `acknowledge_messages` and use `send_ipc_message_acked` in your own retry loop if you need at-least-once delivery,
+/// and make the handler idempotent since it may then see a message twice.
+pub fn send_ipc_message_resilient<TRequest: Serialize>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+    retries: u32,
+) -> Result<(), IpcClientError> {
+    let socket_name = socket_name.as_ref();
+    let bytes = codec::serialize(request).map_err(IpcStreamWriteError::from)?;
+    let mut delay = Duration::from_millis(10);
+
+    for _ in 0..retries {
+        match try_send_frame(socket_name, &bytes) {
+            Err(SendAttempt::Retryable(_)) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(SendAttempt::Failed(e)) => return Err(e),
+            Ok(()) => return Ok(()),
+        }
+    }
+
+    match try_send_frame(socket_name, &bytes) {
+        Err(SendAttempt::Retryable(e) | SendAttempt::Failed(e)) => Err(e),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// A failed attempt to send a message, and whether it's safe to try again.
+enum SendAttempt {
+    /// Nothing was received by the server, and the error may go away.
+    Retryable(IpcClientError),
+    Failed(IpcClientError),
+}
+
+/// Connects to the socket and writes a pre-serialized message, tracking whether any of it was sent.
+fn try_send_frame(socket_name: &str, bytes: &[u8]) -> Result<(), SendAttempt> {
+    let is_transient = |e: &io::Error| {
+        matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::BrokenPipe
+        )
+    };
+
+    let stream = LocalSocketStream::connect(socket_name).map_err(|e| {
+        if is_transient(&e) || e.kind() == io::ErrorKind::NotFound {
+            SendAttempt::Retryable(IpcClientError::connect(socket_name)(e))
+        } else {
+            SendAttempt::Failed(IpcClientError::connect(socket_name)(e))
+        }
+    })?;
+
+    let mut writer = CountingWriter {
+        inner: stream,
+        written: 0,
+    };
+
+    Framing::default()
+        .write_payload(&mut writer, bytes)
+        .map_err(|e| match &e {
+            IpcStreamWriteError::WriteError(io) if writer.written == 0 && is_transient(io) => {
+                SendAttempt::Retryable(e.into())
+            }
+            _ => SendAttempt::Failed(e.into()),
+        })
+}
+
+/// A writer that counts the bytes the inner writer has accepted.
+struct CountingWriter<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: io::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// Connect to the socket and write a serializable object to it, then immediately read a deserializable object from it,
 /// blocking until a response is received. Meant to be used for requests that expect a response from the server.
 pub fn send_ipc_query<TRequest: Serialize, TResponse: DeserializeOwned>(
-    socket_name: &str,
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+) -> Result<TResponse, IpcClientError> {
+    #[cfg(feature = "test-transport")]
+    if let Some(mut stream) = MemoryStream::connect_registered(socket_name.as_ref()) {
+        stream.write_serde(&request)?;
+        return Ok(stream.read_serde()?);
+    }
+
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    stream.write_serde(&request)?;
+    let response: TResponse = stream.read_serde()?;
+    Ok(response)
+}
+
+/// Connect to the socket and write an already framed request to it, then read the raw payload of the response,
+/// without deserializing either of them.
+///
+/// The request is written as is, so it must be a complete frame, such as one built with `frame_message` or recorded
+/// from another client. This suits proxies and tools that forward requests without knowing their types. The response
+/// payload can be deserialized with `Framing::deserialize`, or framed again with `Framing::write_frame`.
+pub fn send_ipc_query_raw(
+    socket_name: impl AsRef<str>,
+    request: &[u8],
+) -> Result<Vec<u8>, IpcClientError> {
+    #[cfg(feature = "test-transport")]
+    if let Some(mut stream) = MemoryStream::connect_registered(socket_name.as_ref()) {
+        write_raw(&mut stream, request)?;
+        return Ok(stream.read_frame()?);
+    }
+
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    write_raw(&mut stream, request)?;
+    let response = stream.read_frame()?;
+    Ok(response)
+}
+
+/// Writes bytes that are already framed to the stream, and flushes it.
+fn write_raw<W: io::Write>(stream: &mut W, bytes: &[u8]) -> Result<(), IpcStreamWriteError> {
+    stream.write_all(bytes)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Like `send_ipc_query`, but connects, writes and reads on a new thread, and returns a handle to join for the response.
+///
+/// This allows firing several queries at once and collecting the responses later. The request is serialized before
+/// the thread is spawned, so it only needs to be borrowed, and a serialization error is returned when joining.
+pub fn send_ipc_query_spawn<TRequest: Serialize, TResponse: DeserializeOwned + Send + 'static>(
+    socket_name: impl Into<String>,
+    request: &TRequest,
+) -> JoinHandle<Result<TResponse, IpcClientError>> {
+    let socket_name = socket_name.into();
+    let request = codec::serialize(request).map_err(IpcStreamWriteError::from);
+
+    std::thread::spawn(move || {
+        let request = request?;
+
+        let mut stream = ipc_client_connect(socket_name.as_str())?;
+        stream.write_frame(&request)?;
+        let response: TResponse = stream.read_serde()?;
+
+        Ok(response)
+    })
+}
+
+/// Like `send_ipc_query`, but also returns the round-trip latency of the query.
+///
+/// The latency is measured from just before the request is written until just after the response is read,
+/// so it excludes the time spent connecting. Time the whole call instead to include connecting.
+pub fn send_ipc_query_timed<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+) -> Result<(TResponse, Duration), IpcClientError> {
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+
+    let start = Instant::now();
+    stream.write_serde(&request)?;
+    let response: TResponse = stream.read_serde()?;
+
+    Ok((response, start.elapsed()))
+}
+
+/// Like `send_ipc_message`, but fails with a `TimedOut` error if connecting and writing don't finish before the deadline.
+///
+/// See `send_ipc_query_until`. Only available on Unix, since the write is bounded with socket timeouts, which named
+/// pipes don't support.
+#[cfg(unix)]
+pub fn send_ipc_message_until<TRequest: Serialize>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+    deadline: Instant,
+) -> Result<(), IpcClientError> {
+    let mut stream = connect_until(socket_name.as_ref(), deadline)?;
+    let mut stream = utils::DeadlineStream::new(&mut stream, deadline);
+
+    stream.write_serde(&request)?;
+    Ok(())
+}
+
+/// Like `send_ipc_query`, but fails with a `TimedOut` error if the whole query doesn't finish before the deadline.
+///
+/// The deadline spans connecting, writing the request and reading the response, so each step only gets the time
+/// the previous ones left over. Use `IpcClientError::is_timeout` to tell a missed deadline apart from other errors.
+///
+/// Connecting is bounded like `ipc_client_connect_timeout`: the socket connects in nonblocking mode and is polled until
+/// the deadline. Writing and reading then shorten the socket's timeouts before every call, so a server trickling in
+/// one byte at a time can't extend the deadline. Only available on Unix, since named pipes don't support those
+/// timeouts.
+#[cfg(unix)]
+pub fn send_ipc_query_until<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
     request: &TRequest,
+    deadline: Instant,
 ) -> Result<TResponse, IpcClientError> {
-    let mut stream = LocalSocketStream::connect(socket_name)?;
+    let mut stream = connect_until(socket_name.as_ref(), deadline)?;
+    let mut stream = utils::DeadlineStream::new(&mut stream, deadline);
+
     stream.write_serde(&request)?;
     let response: TResponse = stream.read_serde()?;
     Ok(response)
 }
 
+/// Like `send_ipc_query`, but uses the given framing and sends the request with a deadline, for a server using
+/// `Framing::deadlines`.
+///
+/// If the deadline has passed by the time the server reads the request, the server skips the handler and this fails
+/// with `IpcStreamReadError::DeadlineExceeded`. Unlike `send_ipc_query_until`, the deadline doesn't limit how long
+/// this waits. It tells a busy server which requests are no longer worth handling.
+pub fn send_ipc_query_with_deadline<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    framing: &Framing,
+    request: &TRequest,
+    deadline: SystemTime,
+) -> Result<TResponse, IpcClientError> {
+    let mut stream = ipc_client_connect_framed(socket_name, framing)?;
+    stream.write_serde_with_deadline(request, framing, deadline)?;
+    let response: TResponse = stream.read_serde_framed(framing)?;
+    Ok(response)
+}
+
+/// Connects to the socket, giving up on it once the deadline passes.
+fn connect_until(
+    socket_name: &str,
+    deadline: Instant,
+) -> Result<LocalSocketStream, IpcClientError> {
+    utils::connect_until(socket_name, deadline).map_err(IpcClientError::connect(socket_name))
+}
+
+/// Connects to the socket and writes a serializable object to it, then returns an iterator over the responses.
+///
+/// Each item is a deserializable object read from the socket, blocking until it is received. The iterator ends when the server
+/// closes the connection, and yields an `Err` if a response fails to be read, after which it ends as well.
+/// On the server side, this pairs with `start_ipc_server_with_stream`, where the handler writes each response to the stream.
+pub fn send_ipc_query_stream<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    request: &TRequest,
+) -> Result<IpcResponseStream<TResponse>, IpcClientError> {
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    stream.write_serde(&request)?;
+    Ok(IpcResponseStream {
+        stream: Some(stream),
+        _response: PhantomData,
+    })
+}
+
+/// An iterator over the responses to a query, returned by `send_ipc_query_stream`.
+pub struct IpcResponseStream<TResponse> {
+    stream: Option<LocalSocketStream>,
+    _response: PhantomData<fn() -> TResponse>,
+}
+
+impl<TResponse: DeserializeOwned> Iterator for IpcResponseStream<TResponse> {
+    type Item = Result<TResponse, IpcStreamReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stream = self.stream.as_mut()?;
+
+        match stream.try_read_serde() {
+            Ok(Some(response)) => Some(Ok(response)),
+            Ok(None) => {
+                self.stream = None;
+                None
+            }
+            Err(e) => {
+                self.stream = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<TResponse: DeserializeOwned> FusedIterator for IpcResponseStream<TResponse> {}
+
 /// Connects to the socket and returns the stream.
-pub fn ipc_client_connect(socket_name: &str) -> Result<LocalSocketStream, IpcClientError> {
-    LocalSocketStream::connect(socket_name).map_err(IpcClientError::ConnectError)
+pub fn ipc_client_connect(
+    socket_name: impl AsRef<str>,
+) -> Result<LocalSocketStream, IpcClientError> {
+    LocalSocketStream::connect(socket_name.as_ref())
+        .map_err(IpcClientError::connect(socket_name.as_ref()))
+}
+
+/// Like `ipc_client_connect`, but fails with a `TimedOut` error if connecting doesn't finish within the timeout.
+///
+/// Connecting can block while the server's accept backlog is full, or on Windows while every instance of the pipe
+/// is busy, such as when the server is overloaded or still starting up. On Unix, this connects in nonblocking mode
+/// and polls until the timeout, and on Windows it waits for a free pipe instance with `WaitNamedPipe`.
+/// Only connecting is bounded, so reads and writes on the returned stream block as usual.
+pub fn ipc_client_connect_timeout(
+    socket_name: impl AsRef<str>,
+    timeout: Duration,
+) -> Result<LocalSocketStream, IpcClientError> {
+    connect_until(socket_name.as_ref(), Instant::now() + timeout)
+}
+
+/// Connects to the socket and writes the magic header of the given framing, if it has one, then returns the stream.
+///
+/// Use this to talk to a server configured with `Framing::magic`, then read and write messages with
+/// `read_serde_framed` and `write_serde_framed`.
+pub fn ipc_client_connect_framed(
+    socket_name: impl AsRef<str>,
+    framing: &Framing,
+) -> Result<LocalSocketStream, IpcClientError> {
+    let mut stream = ipc_client_connect(socket_name)?;
+    framing
+        .write_magic(&mut stream)
+        .map_err(IpcStreamWriteError::from)?;
+    Ok(stream)
+}
+
+/// Connects to the socket and returns the stream wrapped in read and write buffers, as an `IpcDuplex`.
+///
+/// This suits code written against generic `Read` and `Write` streams, such as `serde_json::from_reader`.
+/// Buffered writes are flushed before each read and when the `IpcDuplex` is dropped.
+pub fn ipc_client_connect_buffered(
+    socket_name: impl AsRef<str>,
+) -> Result<IpcDuplex, IpcClientError> {
+    ipc_client_connect(socket_name).map(IpcDuplex::new)
+}
+
+/// Connects to the socket and performs the authentication handshake, then returns the stream.
+///
+/// The server must be configured with the same secret using `IpcServerBuilder::auth`.
+#[cfg(feature = "auth")]
+pub fn ipc_client_connect_with_auth(
+    socket_name: impl AsRef<str>,
+    auth: &AuthConfig,
+) -> Result<LocalSocketStream, IpcClientError> {
+    let mut stream = ipc_client_connect(socket_name)?;
+    auth::authenticate_to_server(&mut stream, auth)?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_stream() {
+        let socket = "/tmp/ipc-util-test-query-stream.sock";
+
+        start_ipc_server_with_stream(
+            socket,
+            |count: u32, stream: &mut LocalSocketStream| {
+                for i in 0..count {
+                    stream.write_serde(&i).unwrap();
+                }
+                None::<()>
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let responses = send_ipc_query_stream::<u32, u32>(socket, &3)
+            .expect("Failed to send query")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Failed to read responses");
+
+        assert_eq!(responses, vec![0, 1, 2]);
+    }
+
+    #[test]
+    #[cfg(feature = "sysinfo")]
+    fn test_peer_process_name() {
+        let socket = "/tmp/ipc-util-test-peer-name.sock";
+
+        start_ipc_server_with_stream(
+            socket,
+            |_: (), stream: &mut LocalSocketStream| Some(stream.peer_process_name()),
+            None,
+        )
+        .expect("Failed to start server");
+
+        let name: Option<String> = send_ipc_query(socket, &()).expect("Failed to send query");
+
+        let current_exe = std::env::current_exe().unwrap();
+        let current_name = current_exe.file_name().unwrap().to_string_lossy();
+        assert_eq!(name.as_deref(), Some(&current_name[..]));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_listener_from_fd() {
+        use std::os::unix::{io::OwnedFd, net::UnixListener};
+
+        let socket = "/tmp/ipc-util-test-from-fd.sock";
+        let _ = std::fs::remove_file(socket);
+
+        let listener = UnixListener::bind(socket).unwrap();
+
+        start_ipc_listener_from_fd(
+            OwnedFd::from(listener),
+            |mut stream| {
+                let request: u32 = stream.read_serde().unwrap();
+                stream.write_serde(&(request * 2)).unwrap();
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let response: u32 = send_ipc_query(socket, &21u32).expect("Failed to send query");
+        assert_eq!(response, 42);
+    }
+
+    #[test]
+    fn test_message_acked() {
+        let socket = "/tmp/ipc-util-test-acked.sock";
+
+        IpcServerBuilder::new(socket)
+            .acknowledge_messages(true)
+            .serve(|_: String| None::<()>)
+            .expect("Failed to start server");
+
+        send_ipc_message_acked(socket, &"hello").expect("Failed to send message");
+    }
+
+    #[test]
+    fn test_message_resilient_waits_for_server() {
+        let socket = "/tmp/ipc-util-test-resilient.sock";
+        let _ = std::fs::remove_file(socket);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        // Start the server only after the first attempts have failed.
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+
+            IpcServerBuilder::new(socket)
+                .serve(move |request: String| {
+                    sender.send(request).unwrap();
+                    None::<()>
+                })
+                .expect("Failed to start server");
+        });
+
+        send_ipc_message_resilient(socket, &"hello", 10).expect("Failed to send message");
+
+        let received = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(received, "hello");
+    }
+
+    #[test]
+    fn test_owned_socket_name() {
+        let socket = std::env::temp_dir()
+            .join(format!("ipc-util-test-owned-{}.sock", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        start_ipc_server(socket.clone(), |request: u32| Some(request + 1), None)
+            .expect("Failed to start server");
+
+        let response: u32 = send_ipc_query(&socket, &1u32).expect("Failed to send query");
+        assert_eq!(response, 2);
+    }
+
+    #[test]
+    fn test_errors_name_the_socket() {
+        let socket = "/tmp/ipc-util-test-missing.sock";
+
+        let error = send_ipc_query::<_, ()>(socket, &()).unwrap_err();
+        assert!(matches!(&error, IpcClientError::ConnectError { name, .. } if name == socket));
+        assert!(error.to_string().contains(socket));
+
+        let socket = "/tmp/ipc-util-test-missing-dir/server.sock";
+
+        let error = bind_ipc_listener(socket).unwrap_err();
+        assert!(matches!(&error, IpcServerError::BindError { name, .. } if name == socket));
+    }
+
+    #[test]
+    fn test_server_mut_keeps_state() {
+        let socket = "/tmp/ipc-util-test-server-mut.sock";
+        let mut seen = Vec::new();
+
+        start_ipc_server_mut(
+            socket,
+            move |request: String| {
+                seen.push(request);
+                Some(seen.join(","))
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        for (request, expected) in [("a", "a"), ("b", "a,b"), ("c", "a,b,c")] {
+            let response: String = send_ipc_query(socket, &request).unwrap();
+            assert_eq!(response, expected);
+        }
+    }
+
+    #[test]
+    fn test_server_n_stops_after_n_connections() {
+        let socket = "/tmp/ipc-util-test-server-n.sock";
+
+        let server = start_ipc_server_n(socket, 2, |request: u32| Some(request + 1), None)
+            .expect("Failed to start server");
+
+        for i in 0..2u32 {
+            let response: u32 = send_ipc_query(socket, &i).expect("Failed to send query");
+            assert_eq!(response, i + 1);
+        }
+
+        server.join().unwrap();
+
+        #[cfg(unix)]
+        assert!(!std::path::Path::new(socket).exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_query_until_deadline() {
+        let socket = "/tmp/ipc-util-test-query-until.sock";
+
+        start_ipc_server(
+            socket,
+            |delay: u64| {
+                std::thread::sleep(Duration::from_millis(delay));
+                Some(delay)
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let response: u64 = send_ipc_query_until(socket, &0u64, deadline).unwrap();
+        assert_eq!(response, 0);
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let result = send_ipc_query_until::<u64, u64>(socket, &500, deadline);
+        assert!(result.unwrap_err().is_timeout());
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        let socket = "/tmp/ipc-util-test-connect-timeout.sock";
+
+        start_ipc_server(socket, |request: u32| Some(request), None)
+            .expect("Failed to start server");
+
+        let mut stream = ipc_client_connect_timeout(socket, Duration::from_secs(5)).unwrap();
+        stream.write_serde(&7u32).unwrap();
+        assert_eq!(stream.read_serde::<u32>().unwrap(), 7);
+
+        let missing = ipc_client_connect_timeout("/tmp/ipc-util-test-missing.sock", Duration::ZERO);
+        assert!(missing.unwrap_err().is_connection_error());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_connect_timeout_with_full_backlog() {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::UnixListener;
+
+        let socket = "/tmp/ipc-util-test-connect-backlog.sock";
+        let _ = std::fs::remove_file(socket);
+
+        // A listener that never accepts, with room for a single pending connection.
+        let listener = UnixListener::bind(socket).unwrap();
+        assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+
+        let mut pending = Vec::new();
+        let error = loop {
+            match ipc_client_connect_timeout(socket, Duration::from_millis(50)) {
+                Ok(stream) => pending.push(stream),
+                Err(e) => break e,
+            }
+        };
+
+        assert!(error.is_timeout());
+        assert!(!pending.is_empty());
+    }
+
+    #[test]
+    fn test_query_spawn() {
+        let socket = "/tmp/ipc-util-test-query-spawn.sock";
+
+        start_ipc_server(socket, |request: u32| Some(request * 2), None)
+            .expect("Failed to start server");
+
+        let handles = (0..3u32)
+            .map(|i| send_ipc_query_spawn(socket, &i))
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let response: u32 = handle.join().unwrap().expect("Failed to send query");
+            assert_eq!(response, i as u32 * 2);
+        }
+    }
+
+    #[test]
+    fn test_query_raw() {
+        let socket = "/tmp/ipc-util-test-query-raw.sock";
+
+        start_ipc_server(socket, |request: String| Some(request.len()), None)
+            .expect("Failed to start server");
+
+        let request = frame_message(&"hello".to_string()).unwrap();
+        let response = send_ipc_query_raw(socket, &request).unwrap();
+
+        assert_eq!(response, codec::serialize(&5usize).unwrap());
+    }
+
+    #[test]
+    fn test_query_timed() {
+        let socket = "/tmp/ipc-util-test-query-timed.sock";
+
+        start_ipc_server(
+            socket,
+            |request: u32| {
+                std::thread::sleep(Duration::from_millis(20));
+                Some(request + 1)
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let (response, latency): (u32, _) =
+            send_ipc_query_timed(socket, &1u32).expect("Failed to send query");
+
+        assert_eq!(response, 2);
+        assert!(latency >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_server_with_control_stops() {
+        let socket = "/tmp/ipc-util-test-control.sock";
+
+        let server = start_ipc_server_with_control(
+            socket,
+            |request: String, _| match request.as_str() {
+                "shutdown" => ControlFlow::Break(Some("bye".to_string())),
+                _ => ControlFlow::Continue(Some(request)),
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let echo: String = send_ipc_query(socket, &"hello").expect("Failed to send query");
+        assert_eq!(echo, "hello");
+
+        let reply: String = send_ipc_query(socket, &"shutdown").expect("Failed to send query");
+        assert_eq!(reply, "bye");
+
+        server.join().expect("Server thread panicked");
+    }
 }
@@ -0,0 +1,216 @@
+//! An in-memory transport for testing code that talks to an IPC server, without binding real sockets.
+//!
+//! A `MemoryListener` registers its name in a process-wide registry. While it is bound, `send_ipc_message` and
+//! `send_ipc_query` connect to it instead of a socket with that name, so application code can be tested unchanged.
+//! Streams use the same framing as sockets, since `SocketExt` works with any `Read + Write` type.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+
+/// The listeners currently bound, by name, along with the channel that hands them new connections.
+fn registry() -> MutexGuard<'static, HashMap<String, Sender<MemoryStream>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Sender<MemoryStream>>>> = OnceLock::new();
+
+    REGISTRY
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// An in-memory listener that accepts connections made to its name with `MemoryStream::connect`.
+///
+/// The name is unregistered when the listener is dropped.
+pub struct MemoryListener {
+    name: String,
+    receiver: Receiver<MemoryStream>,
+}
+
+impl MemoryListener {
+    /// Registers a listener with the given name.
+    ///
+    /// Fails with `AddrInUse` if another in-memory listener with the same name is already bound.
+    pub fn bind(name: impl Into<String>) -> io::Result<Self> {
+        let name = name.into();
+        let mut registry = registry();
+
+        if registry.contains_key(&name) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        registry.insert(name.clone(), sender);
+
+        Ok(Self { name, receiver })
+    }
+
+    /// Blocks until a client connects, and returns the server end of the connection.
+    pub fn accept(&self) -> io::Result<MemoryStream> {
+        // The sender stays in the registry until this listener is dropped, so this only fails if it was removed.
+        self.receiver
+            .recv()
+            .map_err(|_| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Returns an iterator over incoming connections, which blocks waiting for each of them.
+    pub fn incoming(&self) -> impl Iterator<Item = io::Result<MemoryStream>> + '_ {
+        std::iter::repeat_with(|| self.accept())
+    }
+
+    /// Gets the name the listener is registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        registry().remove(&self.name);
+    }
+}
+
+/// One end of an in-memory connection, which implements `Read` and `Write`.
+///
+/// Reads block until the other end writes or is dropped, after which they return `Ok(0)`.
+/// Writes never block, and fail with `BrokenPipe` once the other end is dropped.
+pub struct MemoryStream {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+}
+
+impl MemoryStream {
+    /// Connects to the in-memory listener with the given name.
+    ///
+    /// Fails with `NotFound` if no listener is bound to the name, like a socket file that doesn't exist.
+    pub fn connect(name: &str) -> io::Result<Self> {
+        Self::connect_registered(name).ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    /// Connects to the in-memory listener with the given name, if there is one.
+    pub(crate) fn connect_registered(name: &str) -> Option<Self> {
+        let sender = registry().get(name)?.clone();
+
+        let (client, server) = Self::pair();
+        sender.send(server).ok()?;
+
+        Some(client)
+    }
+
+    /// Creates two streams connected to each other.
+    pub fn pair() -> (Self, Self) {
+        let (a, b) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+
+        (
+            Self {
+                incoming: a.clone(),
+                outgoing: b.clone(),
+            },
+            Self {
+                incoming: b,
+                outgoing: a,
+            },
+        )
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.incoming.lock();
+
+        while state.buffer.is_empty() && !state.closed {
+            state = self
+                .incoming
+                .ready
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+
+        let len = buf.len().min(state.buffer.len());
+
+        for (byte, value) in buf.iter_mut().zip(state.buffer.drain(..len)) {
+            *byte = value;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.outgoing.lock();
+
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        state.buffer.extend(buf);
+        self.outgoing.ready.notify_all();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+/// The bytes in flight in one direction of a connection.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct PipeState {
+    buffer: VecDeque<u8>,
+    /// Set once either end is dropped.
+    closed: bool,
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{send_ipc_message, send_ipc_query, SocketExt};
+
+    #[test]
+    fn test_client_functions_route_to_memory_listener() {
+        let listener = MemoryListener::bind("memory-test").unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap();
+            let request: u32 = stream.read_serde().unwrap();
+            stream.write_serde(&(request * 2)).unwrap();
+
+            let mut stream = listener.accept().unwrap();
+            stream.read_serde::<String>().unwrap()
+        });
+
+        let response: u32 = send_ipc_query("memory-test", &21u32).unwrap();
+        assert_eq!(response, 42);
+
+        send_ipc_message("memory-test", &"hello").unwrap();
+        assert_eq!(server.join().unwrap(), "hello");
+
+        assert!(MemoryStream::connect("memory-test").is_err());
+    }
+}
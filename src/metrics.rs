@@ -0,0 +1,30 @@
+use crate::IpcStreamError;
+
+/// Hooks invoked by the server at key points of each connection's lifetime, for collecting metrics.
+///
+/// All methods have empty default implementations, so implementors only need to override the ones they care about.
+/// Set an implementation with `IpcServerBuilder::metrics`. When no metrics are set, none of these calls are made.
+///
+/// The message hooks and `on_error` are only invoked by the typed servers (`serve` and its variants), since
+/// `listen` hands the raw stream to the callback and can't observe what is read or written on it.
+pub trait ServerMetrics: Send + Sync {
+    /// Invoked when a connection is accepted, before it is handled.
+    fn on_connection_opened(&self) {}
+
+    /// Invoked after a message is read, with the size of its payload in bytes.
+    fn on_message_read(&self, _bytes: usize) {}
+
+    /// Invoked after a message is written, with the size of its payload in bytes.
+    fn on_message_written(&self, _bytes: usize) {}
+
+    /// Invoked when reading or writing a message fails.
+    fn on_error(&self, _error: &IpcStreamError) {}
+
+    /// Invoked after a connection has been handled.
+    fn on_connection_closed(&self) {}
+
+    /// Invoked with `IpcServerError::AuthFailed` when a client fails the authentication handshake, or doesn't complete
+    /// it in time, before its connection is closed. See `IpcServerBuilder::auth`.
+    #[cfg(feature = "auth")]
+    fn on_auth_failed(&self, _error: &crate::IpcServerError) {}
+}
@@ -0,0 +1,181 @@
+use crate::IpcServerError;
+use interprocess::local_socket::NameTypeSupport;
+use std::fmt;
+use std::str::FromStr;
+
+/// The kind of name a local socket is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameType {
+    /// A file system path. Binding creates a socket file, which can be left behind if the server crashes
+    /// and has permissions that can be changed.
+    Path,
+    /// A name in a dedicated namespace, such as a Windows named pipe or a Linux abstract socket.
+    /// There is no file, so there is nothing to clean up and no permissions to change.
+    Namespaced,
+}
+
+/// Determines how the given socket name is interpreted on the current platform.
+///
+/// On Windows, every name is a named pipe. Elsewhere, names starting with `@` are namespaced, which is only supported
+/// on Linux, and every other name is a path.
+pub fn resolve_name_type(name: &str) -> NameType {
+    match NameTypeSupport::query() {
+        NameTypeSupport::OnlyNamespaced => NameType::Namespaced,
+        NameTypeSupport::OnlyPaths | NameTypeSupport::Both if name.starts_with('@') => {
+            NameType::Namespaced
+        }
+        NameTypeSupport::OnlyPaths | NameTypeSupport::Both => NameType::Path,
+    }
+}
+
+/// Returns `true` if the given socket name is namespaced on the current platform. See `resolve_name_type`.
+pub fn is_namespaced(name: &str) -> bool {
+    resolve_name_type(name) == NameType::Namespaced
+}
+
+/// A socket name that has been checked to be bindable on the current platform.
+///
+/// Binding an invalid name fails deep in the OS with an unhelpful error, so `SocketName::new` catches the usual
+/// mistakes up front: empty names, interior nul bytes, a namespace prefix with no name after it, and names longer
+/// than the platform allows, such as the roughly 108 byte limit on socket paths. The bind functions validate their
+/// name the same way, and accept a `SocketName` anywhere they accept a `String`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SocketName(String);
+
+impl SocketName {
+    /// Validates the given socket name.
+    ///
+    /// Fails with `IpcServerError::InvalidName` describing the problem if the name can't be bound.
+    pub fn new(name: impl Into<String>) -> Result<Self, IpcServerError> {
+        let name = name.into();
+        validate_name(&name)?;
+        Ok(Self(name))
+    }
+
+    /// Gets the name as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Determines how the name is interpreted on the current platform. See `resolve_name_type`.
+    pub fn name_type(&self) -> NameType {
+        resolve_name_type(&self.0)
+    }
+}
+
+/// Checks that the given socket name can be bound on the current platform. See `SocketName`.
+pub(crate) fn validate_name(name: &str) -> Result<(), IpcServerError> {
+    let invalid = |reason: String| Err(IpcServerError::InvalidName { reason });
+
+    if name.is_empty() {
+        return invalid("the name is empty".to_string());
+    }
+
+    if name.contains('\0') {
+        return invalid("the name contains a nul byte".to_string());
+    }
+
+    if name == "@" && resolve_name_type(name) == NameType::Namespaced {
+        return invalid("the name has a namespace prefix but nothing after it".to_string());
+    }
+
+    if name.len() > MAX_NAME_LEN {
+        return invalid(format!(
+            "the name is {} bytes long, but at most {MAX_NAME_LEN} are supported on this platform",
+            name.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The longest name that can be bound, in bytes.
+///
+/// Unix socket addresses hold the path in `sockaddr_un::sun_path`, which is 108 bytes on Linux and 104 on macOS and
+/// the BSDs, and the path needs a nul terminator. A namespaced name's `@` is replaced with a leading nul, so the same
+/// limit applies to it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAX_NAME_LEN: usize = 107;
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+const MAX_NAME_LEN: usize = 103;
+
+/// Pipe paths can be at most 256 characters long, including the `\\.\pipe\` prefix that is added to the name.
+#[cfg(windows)]
+const MAX_NAME_LEN: usize = 256 - r"\\.\pipe\".len();
+
+impl TryFrom<String> for SocketName {
+    type Error = IpcServerError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::new(name)
+    }
+}
+
+impl TryFrom<&str> for SocketName {
+    type Error = IpcServerError;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        Self::new(name)
+    }
+}
+
+impl FromStr for SocketName {
+    type Err = IpcServerError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::new(name)
+    }
+}
+
+impl AsRef<str> for SocketName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<SocketName> for String {
+    fn from(name: SocketName) -> Self {
+        name.0
+    }
+}
+
+impl fmt::Display for SocketName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_name_type() {
+        assert!(is_namespaced("@my-app.sock"));
+
+        let expected = if cfg!(windows) {
+            NameType::Namespaced
+        } else {
+            NameType::Path
+        };
+        assert_eq!(resolve_name_type("/tmp/my-app.sock"), expected);
+    }
+
+    #[test]
+    fn test_socket_name_validation() {
+        assert!(SocketName::new("/tmp/my-app.sock").is_ok());
+        assert!(SocketName::new("").is_err());
+        assert!(SocketName::new("my\0app").is_err());
+
+        let long = format!("/tmp/{}.sock", "a".repeat(200));
+        assert!(matches!(
+            SocketName::new(long.as_str()),
+            Err(IpcServerError::InvalidName { .. })
+        ));
+        assert!(matches!(
+            crate::bind_ipc_listener(long),
+            Err(IpcServerError::InvalidName { .. })
+        ));
+    }
+}
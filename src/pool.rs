@@ -0,0 +1,240 @@
+use crate::{IpcClientError, SocketExt};
+use interprocess::local_socket::LocalSocketStream;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Read};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A bounded pool of reusable client connections to a socket.
+///
+/// Connections are handed out by `get` and returned to the pool when the `PooledConnection` is dropped,
+/// which saves a connect for each query when many threads talk to the same server. The server must keep
+/// connections open and handle several requests on each of them, for example with a `start_ipc_server_with_stream`
+/// handler or a `start_ipc_listener` callback that loops until the client disconnects.
+///
+/// Cloning the pool is cheap, and clones share the same connections.
+#[derive(Clone)]
+pub struct IpcClientPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    socket: String,
+    max_size: usize,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<LocalSocketStream>,
+    /// The number of connections that are either idle or checked out.
+    open: usize,
+}
+
+impl IpcClientPool {
+    /// Creates a pool that keeps at most `max_size` connections to the given socket open.
+    ///
+    /// No connections are opened until they are first needed.
+    pub fn new(socket: impl Into<String>, max_size: usize) -> Self {
+        assert!(max_size > 0, "pool size must be at least 1");
+
+        Self {
+            inner: Arc::new(PoolInner {
+                socket: socket.into(),
+                max_size,
+                state: Mutex::new(PoolState {
+                    idle: Vec::new(),
+                    open: 0,
+                }),
+                returned: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Gets a connection from the pool, connecting a new one if none are idle.
+    ///
+    /// Idle connections that the server has closed are discarded and replaced. If `max_size` connections
+    /// are already checked out, this blocks until one is returned.
+    pub fn get(&self) -> Result<PooledConnection, IpcClientError> {
+        let mut state = self.inner.state.lock().unwrap();
+
+        loop {
+            while let Some(mut stream) = state.idle.pop() {
+                if is_alive(&mut stream) {
+                    return Ok(self.checked_out(stream));
+                }
+
+                state.open -= 1;
+            }
+
+            if state.open < self.inner.max_size {
+                break;
+            }
+
+            state = self.inner.returned.wait(state).unwrap();
+        }
+
+        // Reserve the slot before connecting, so the lock isn't held while waiting on the server.
+        state.open += 1;
+        drop(state);
+
+        match LocalSocketStream::connect(self.inner.socket.as_str()) {
+            Ok(stream) => Ok(self.checked_out(stream)),
+            Err(e) => {
+                self.inner.release();
+                Err(IpcClientError::connect(&self.inner.socket)(e))
+            }
+        }
+    }
+
+    /// Gets a connection from the pool, writes a serializable object to it, and reads a deserializable object in response.
+    ///
+    /// This is the pooled equivalent of `send_ipc_query`.
+    pub fn query<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        self.get()?.query(request)
+    }
+
+    fn checked_out(&self, stream: LocalSocketStream) -> PooledConnection {
+        PooledConnection {
+            stream: Some(stream),
+            pool: self.inner.clone(),
+            broken: false,
+        }
+    }
+}
+
+impl PoolInner {
+    /// Gives up a slot in the pool after a connection is closed.
+    fn release(&self) {
+        self.state.lock().unwrap().open -= 1;
+        self.returned.notify_one();
+    }
+}
+
+/// Checks that the server hasn't closed an idle connection, without blocking.
+///
+/// Since the connection is idle, there shouldn't be anything to read. Unread data means a previous
+/// exchange was cut short, so the connection is out of sync and can't be reused either.
+fn is_alive(stream: &mut LocalSocketStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let alive = matches!(
+        stream.read(&mut [0]),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock
+    );
+
+    alive && stream.set_nonblocking(false).is_ok()
+}
+
+/// A connection checked out of an `IpcClientPool`, which is returned to the pool when dropped.
+///
+/// If the connection is used directly and an error occurs, call `discard` so it isn't handed out again.
+pub struct PooledConnection {
+    stream: Option<LocalSocketStream>,
+    pool: Arc<PoolInner>,
+    broken: bool,
+}
+
+impl PooledConnection {
+    /// Writes a serializable object to the connection and reads a deserializable object in response.
+    ///
+    /// If either fails, the connection is discarded rather than returned to the pool when dropped.
+    pub fn query<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &mut self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        let result = self.exchange(request);
+        self.broken |= result.is_err();
+        result
+    }
+
+    fn exchange<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &mut self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        self.write_serde(request)?;
+        Ok(self.read_serde()?)
+    }
+
+    /// Closes the connection instead of returning it to the pool.
+    pub fn discard(mut self) {
+        self.broken = true;
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = LocalSocketStream;
+
+    fn deref(&self) -> &Self::Target {
+        self.stream.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.stream.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(stream) = self.stream.take() else {
+            return;
+        };
+
+        if self.broken {
+            drop(stream);
+            self.pool.release();
+            return;
+        }
+
+        self.pool.state.lock().unwrap().idle.push(stream);
+        self.pool.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpcServerBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_pool_reuses_connections() {
+        static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+        let socket = "/tmp/ipc-util-test-pool.sock";
+
+        IpcServerBuilder::new(socket)
+            .listen(|mut stream| {
+                CONNECTIONS.fetch_add(1, Ordering::SeqCst);
+
+                while let Ok(Some(request)) = stream.try_read_serde::<u32>() {
+                    if stream.write_serde(&(request * 2)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to start server");
+
+        let pool = IpcClientPool::new(socket, 1);
+
+        for i in 0..3 {
+            let response: u32 = pool.query(&i).expect("Failed to send query");
+            assert_eq!(response, i * 2);
+        }
+
+        assert_eq!(CONNECTIONS.load(Ordering::SeqCst), 1);
+
+        pool.get().unwrap().discard();
+
+        let response: u32 = pool.query(&5).expect("Failed to send query");
+        assert_eq!(response, 10);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    frame_message, send_ipc_query_stream, IpcClientError, IpcResponseStream, IpcServerBuilder,
+    IpcServerError, IpcStreamWriteError, LocalSocketExt, SocketExt,
+};
+use interprocess::local_socket::LocalSocketStream;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+/// The request a client sends to subscribe to a topic of a `PubSubServer`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscribe {
+    /// The topic to receive the messages of.
+    pub topic: String,
+}
+
+/// The open subscriber connections, by topic.
+type Topics = Arc<Mutex<HashMap<String, Vec<LocalSocketStream>>>>;
+
+/// A server that publishes messages to the clients subscribed to a topic.
+///
+/// Clients connect with `ipc_subscribe`, which sends a `Subscribe` request and then iterates over the messages
+/// published to that topic, so each connection only receives the topic it asked for. Subscribers are removed once
+/// their connection closes, which is noticed the next time a message is published to their topic, or when
+/// `subscriber_count` is called. Clients don't send anything after subscribing, and any that do are dropped as well.
+///
+/// Messages are written to each subscriber in turn on the publishing thread, so a subscriber that stops reading
+/// eventually blocks `publish` once its socket buffer fills up, along with any other call to `publish`,
+/// `subscriber_count` or `topics` until it reads again or disconnects. The subscribers of the topic are taken out of
+/// the map while they're written to, so new subscriptions are still accepted in the meantime.
+pub struct PubSubServer<T> {
+    topics: Topics,
+    /// Held while a message is written, so the accept thread never waits on a blocked subscriber for `topics`.
+    publishing: Mutex<()>,
+    handle: JoinHandle<()>,
+    _message: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> PubSubServer<T> {
+    /// Starts a server on the given socket, accepting subscriptions on its own thread.
+    pub fn start(socket: impl Into<String>) -> Result<Self, IpcServerError> {
+        Self::from_builder(IpcServerBuilder::new(socket))
+    }
+
+    /// Starts a server configured by the builder, accepting subscriptions on its own thread.
+    pub fn from_builder(builder: IpcServerBuilder) -> Result<Self, IpcServerError> {
+        let topics = Topics::default();
+        let subscribers = topics.clone();
+
+        let handle = builder.listen(move |mut stream| {
+            if let Ok(Subscribe { topic }) = stream.read_serde() {
+                lock(&subscribers).entry(topic).or_default().push(stream);
+            }
+        })?;
+
+        Ok(Self {
+            topics,
+            publishing: Mutex::new(()),
+            handle,
+            _message: PhantomData,
+        })
+    }
+
+    /// Sends the message to every subscriber of the topic, and returns how many of them it was sent to.
+    ///
+    /// The message is serialized once, and framed like `write_serde`. Subscribers whose connection fails while
+    /// it's being written are removed.
+    pub fn publish(&self, topic: &str, message: &T) -> Result<usize, IpcStreamWriteError> {
+        let bytes = frame_message(message)?;
+        let _publishing = lock(&self.publishing);
+
+        let Some(mut subscribers) = lock(&self.topics).remove(topic) else {
+            return Ok(0);
+        };
+
+        subscribers.retain_mut(|stream| stream.write_all(&bytes).is_ok());
+        let sent = subscribers.len();
+
+        if !subscribers.is_empty() {
+            // Clients that subscribed while the message was being written are put back after the others.
+            let mut topics = lock(&self.topics);
+            let joined = topics.entry(topic.to_string()).or_default();
+            subscribers.append(joined);
+            *joined = subscribers;
+        }
+
+        Ok(sent)
+    }
+
+    /// Gets the number of clients subscribed to the topic, after removing those that disconnected.
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        let _publishing = lock(&self.publishing);
+        let mut topics = lock(&self.topics);
+
+        let Some(subscribers) = topics.get_mut(topic) else {
+            return 0;
+        };
+
+        // Subscribers never send anything, so a readable connection is one the client closed.
+        subscribers.retain(|stream| matches!(stream.has_pending_frame(), Ok(false)));
+        let count = subscribers.len();
+
+        if subscribers.is_empty() {
+            topics.remove(topic);
+        }
+
+        count
+    }
+
+    /// Returns the topics that currently have subscribers.
+    pub fn topics(&self) -> Vec<String> {
+        let _publishing = lock(&self.publishing);
+        lock(&self.topics).keys().cloned().collect()
+    }
+
+    /// Gets the handle of the thread accepting subscriptions.
+    pub fn thread(&self) -> &JoinHandle<()> {
+        &self.handle
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Subscribes to a topic of a `PubSubServer`, and returns an iterator over the messages published to it.
+///
+/// The iterator blocks waiting for each message, and ends once the server closes the connection.
+/// Drop it to unsubscribe.
+pub fn ipc_subscribe<T: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    topic: impl Into<String>,
+) -> Result<IpcResponseStream<T>, IpcClientError> {
+    send_ipc_query_stream(
+        socket_name,
+        &Subscribe {
+            topic: topic.into(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn wait_for_subscribers(server: &PubSubServer<String>, topic: &str, count: usize) {
+        while server.subscriber_count(topic) != count {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_publish_to_topic_subscribers() {
+        let socket = "/tmp/ipc-util-test-pubsub.sock";
+        let server = PubSubServer::<String>::start(socket).expect("Failed to start server");
+
+        let mut news = ipc_subscribe::<String>(socket, "news").unwrap();
+        let mut sports = ipc_subscribe::<String>(socket, "sports").unwrap();
+
+        wait_for_subscribers(&server, "news", 1);
+        wait_for_subscribers(&server, "sports", 1);
+
+        assert_eq!(server.publish("news", &"headline".to_string()).unwrap(), 1);
+        assert_eq!(server.publish("sports", &"score".to_string()).unwrap(), 1);
+        assert_eq!(server.publish("weather", &"rain".to_string()).unwrap(), 0);
+
+        assert_eq!(news.next().unwrap().unwrap(), "headline");
+        assert_eq!(sports.next().unwrap().unwrap(), "score");
+
+        drop(news);
+        wait_for_subscribers(&server, "news", 0);
+
+        assert_eq!(server.publish("news", &"ignored".to_string()).unwrap(), 0);
+        assert_eq!(server.topics(), ["sports"]);
+    }
+}
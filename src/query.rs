@@ -0,0 +1,75 @@
+use crate::{send_ipc_query, IpcClientError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A request type paired with the type of its response.
+///
+/// Queries sent with `send_ipc_query_typed` or `IpcClient::query_typed` infer the response type from the request,
+/// so a client can't ask for the wrong one and only find out when it fails to deserialize. Servers answer them with
+/// `IpcServerBuilder::serve_query`, which makes the handler return the paired type as well.
+///
+/// ```
+/// use ipc_util::Query;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct GetVersion;
+///
+/// impl Query for GetVersion {
+///     type Response = String;
+/// }
+/// ```
+pub trait Query: Serialize {
+    /// The type the server responds to this request with.
+    type Response: DeserializeOwned;
+}
+
+/// Like `send_ipc_query`, but the response type is the one paired with the request by its `Query` implementation.
+pub fn send_ipc_query_typed<Q: Query>(
+    socket_name: impl AsRef<str>,
+    query: &Q,
+) -> Result<Q::Response, IpcClientError> {
+    send_ipc_query(socket_name, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpcClient, IpcServerBuilder};
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Add(u32, u32);
+
+    impl Query for Add {
+        type Response = u32;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Greet(String);
+
+    impl Query for Greet {
+        type Response = String;
+    }
+
+    #[test]
+    fn test_typed_queries_infer_response() {
+        let socket = "/tmp/ipc-util-test-typed-query.sock";
+
+        IpcServerBuilder::new(socket)
+            .serve_query(|Add(a, b)| a + b)
+            .expect("Failed to start server");
+
+        assert_eq!(send_ipc_query_typed(socket, &Add(2, 3)).unwrap(), 5);
+        assert_eq!(IpcClient::new(socket).query_typed(&Add(1, 1)).unwrap(), 2);
+
+        let greet_socket = "/tmp/ipc-util-test-typed-query-greet.sock";
+
+        IpcServerBuilder::new(greet_socket)
+            .serve_query(|Greet(name)| format!("Hello, {name}!"))
+            .expect("Failed to start server");
+
+        let greeting = send_ipc_query_typed(greet_socket, &Greet("Ada".to_string())).unwrap();
+        assert_eq!(greeting, "Hello, Ada!");
+    }
+}
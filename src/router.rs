@@ -0,0 +1,201 @@
+use crate::{
+    ipc_client_connect, Framing, IpcClientError, IpcStreamError, IpcStreamReadError,
+    IpcStreamWriteError, SocketExt,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A typed handler that deserializes a request payload with the server's framing and serializes its response.
+type Route = Box<dyn Fn(&Framing, &[u8]) -> Result<Option<Vec<u8>>, IpcStreamError> + Send>;
+
+/// Dispatches requests to typed handlers by a `u16` type tag, so one server can handle several request types.
+///
+/// Each request payload starts with its type tag in little-endian, followed by the serialized request. The tag picks
+/// the handler, and the rest of the payload is only deserialized once it's known which type to deserialize it as.
+/// Responses are written like those of `serve`, without a tag. Clients send tagged requests with
+/// `send_ipc_message_tagged` and `send_ipc_query_tagged`.
+///
+/// Serve a router with `IpcServerBuilder::serve_router`.
+#[derive(Default)]
+pub struct RpcRouter {
+    routes: HashMap<u16, Route>,
+}
+
+impl RpcRouter {
+    /// Creates a router with no routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler for requests with the given type tag.
+    ///
+    /// Like `serve`, the handler receives the deserialized request, and its response is written back if there is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a handler is already registered for the tag.
+    pub fn route_tag<TRequest, TResponse, F>(mut self, tag: u16, handler: F) -> Self
+    where
+        TRequest: DeserializeOwned,
+        TResponse: Serialize,
+        F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+    {
+        let route: Route = Box::new(move |framing, bytes| {
+            let request: TRequest = framing.deserialize(bytes)?;
+
+            let response = handler(request)
+                .map(|response| crate::codec::serialize(&response))
+                .transpose()
+                .map_err(IpcStreamWriteError::from)?;
+
+            Ok(response)
+        });
+
+        assert!(
+            self.routes.insert(tag, route).is_none(),
+            "a handler is already registered for type tag {tag}"
+        );
+
+        self
+    }
+
+    /// Passes a tagged request payload to the handler registered for its tag, and returns the serialized response.
+    ///
+    /// The request is deserialized with the server's framing, so its `deserialize_limit` applies.
+    pub(crate) fn dispatch(
+        &self,
+        framing: &Framing,
+        payload: &[u8],
+    ) -> Result<Option<Vec<u8>>, IpcStreamError> {
+        let (tag, body) = split_tag(payload)?;
+
+        let route = self
+            .routes
+            .get(&tag)
+            .ok_or(IpcStreamReadError::UnknownTypeTag(tag))?;
+
+        route(framing, body)
+    }
+}
+
+/// Splits the type tag off the start of a request payload.
+fn split_tag(payload: &[u8]) -> Result<(u16, &[u8]), IpcStreamReadError> {
+    match payload {
+        [a, b, body @ ..] => Ok((u16::from_le_bytes([*a, *b]), body)),
+        _ => Err(IpcStreamReadError::MissingTypeTag),
+    }
+}
+
+/// Serializes a request with its type tag in front.
+fn tagged_payload<TRequest: Serialize>(
+    tag: u16,
+    request: &TRequest,
+) -> Result<Vec<u8>, IpcStreamWriteError> {
+    let mut payload = tag.to_le_bytes().to_vec();
+    crate::codec::serialize_into(&mut payload, request)?;
+    Ok(payload)
+}
+
+/// Like `send_ipc_message`, but sends the request with a type tag, for a server using an `RpcRouter`.
+pub fn send_ipc_message_tagged<TRequest: Serialize>(
+    socket_name: impl AsRef<str>,
+    tag: u16,
+    request: &TRequest,
+) -> Result<(), IpcClientError> {
+    let payload = tagged_payload(tag, request)?;
+
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    stream.write_frame(&payload)?;
+    Ok(())
+}
+
+/// Like `send_ipc_query`, but sends the request with a type tag, for a server using an `RpcRouter`.
+///
+/// If the server has no handler for the tag, it closes the connection and this fails with a read error.
+pub fn send_ipc_query_tagged<TRequest: Serialize, TResponse: DeserializeOwned>(
+    socket_name: impl AsRef<str>,
+    tag: u16,
+    request: &TRequest,
+) -> Result<TResponse, IpcClientError> {
+    let payload = tagged_payload(tag, request)?;
+
+    let mut stream = ipc_client_connect(socket_name.as_ref())?;
+    stream.write_frame(&payload)?;
+    let response: TResponse = stream.read_serde()?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpcServerBuilder, ServerMetrics};
+    use serde::Deserialize;
+    use std::sync::mpsc::Sender;
+    use std::sync::Mutex;
+
+    #[derive(Serialize, Deserialize)]
+    enum MathRequest {
+        Double(u32),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum TextRequest {
+        Upper(String),
+    }
+
+    struct ErrorReporter(Mutex<Sender<String>>);
+
+    impl ServerMetrics for ErrorReporter {
+        fn on_error(&self, error: &IpcStreamError) {
+            let _ = self.0.lock().unwrap().send(error.to_string());
+        }
+    }
+
+    #[test]
+    fn test_router_dispatches_by_tag() {
+        let socket = "/tmp/ipc-util-test-router.sock";
+        let (sender, errors) = std::sync::mpsc::channel();
+
+        let router = RpcRouter::new()
+            .route_tag(1, |MathRequest::Double(n)| Some(n * 2))
+            .route_tag(2, |TextRequest::Upper(s)| Some(s.to_uppercase()));
+
+        IpcServerBuilder::new(socket)
+            .metrics(ErrorReporter(Mutex::new(sender)))
+            .serve_router(router)
+            .expect("Failed to start server");
+
+        let doubled: u32 = send_ipc_query_tagged(socket, 1, &MathRequest::Double(4)).unwrap();
+        assert_eq!(doubled, 8);
+
+        let upper: String =
+            send_ipc_query_tagged(socket, 2, &TextRequest::Upper("hi".to_string())).unwrap();
+        assert_eq!(upper, "HI");
+
+        assert!(send_ipc_query_tagged::<_, u32>(socket, 3, &MathRequest::Double(1)).is_err());
+        assert_eq!(
+            errors.recv().unwrap(),
+            IpcStreamError::from(IpcStreamReadError::UnknownTypeTag(3)).to_string()
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "codec-json", feature = "codec-msgpack")))]
+    fn test_router_applies_deserialize_limit() {
+        let socket = "/tmp/ipc-util-test-router-limit.sock";
+
+        // A bincode string takes its 8-byte length prefix plus its bytes.
+        IpcServerBuilder::new(socket)
+            .framing(Framing::new().deserialize_limit(16))
+            .serve_router(RpcRouter::new().route_tag(1, |s: String| Some(s.len() as u32)))
+            .expect("Failed to start server");
+
+        let len: u32 = send_ipc_query_tagged(socket, 1, &"short").unwrap();
+        assert_eq!(len, 5);
+
+        assert!(
+            send_ipc_query_tagged::<_, u32>(socket, 1, &"far too long to deserialize").is_err()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! A TCP backend, for environments where local sockets are unreliable but TCP on the loopback interface works.
+//!
+//! Messages are framed exactly like over local sockets, since `SocketExt` works with any `Read + Write` stream, so a
+//! client and server can switch transports without changing their message types. Unlike local sockets, a TCP port
+//! can be connected to by any user on the machine, and by other machines if bound to a non-loopback address, so
+//! don't rely on it for access control.
+
+use crate::{ErrorAction, IpcClientError, IpcServerError, SocketExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread::JoinHandle;
+
+/// Like `start_ipc_listener`, but listens for TCP connections on the given address, such as `127.0.0.1:7878`.
+///
+/// Binding fails with `IpcServerError::BindError` if the address is in use. There are no socket files,
+/// so there is nothing stale to clean up.
+pub fn start_ipc_listener_tcp<F: Fn(TcpStream) + Send + 'static>(
+    addr: impl ToSocketAddrs,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    let (addrs, name) = resolve(addr);
+    let listener = addrs
+        .and_then(|addrs| TcpListener::bind(&addrs[..]))
+        .map_err(IpcServerError::bind(&name))?;
+
+    // The bound address is the one that matters if several were resolved, or if the port was chosen by the system.
+    let name = listener.local_addr().map_or(name, |addr| addr.to_string());
+
+    std::thread::Builder::new()
+        .name(format!("ipc server '{name}'"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        // Requests and responses are small, so waiting to coalesce them only adds latency.
+                        let _ = stream.set_nodelay(true);
+                        on_connection(stream);
+                    }
+                    Err(e) => {
+                        if let Some(ErrorAction::Stop) = on_connection_error.map(|f| f(e)) {
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+        .map_err(IpcServerError::ThreadSpawnError)
+}
+
+/// Like `start_ipc_server`, but listens for TCP connections on the given address. See `start_ipc_listener_tcp`.
+pub fn start_ipc_server_tcp<
+    TRequest: DeserializeOwned,
+    TResponse: Serialize,
+    F: Fn(TRequest) -> Option<TResponse> + Send + 'static,
+>(
+    addr: impl ToSocketAddrs,
+    on_connection: F,
+    on_connection_error: Option<fn(io::Error) -> ErrorAction>,
+) -> Result<JoinHandle<()>, IpcServerError> {
+    start_ipc_listener_tcp(
+        addr,
+        move |mut stream| {
+            let Ok(request) = stream.read_serde() else {
+                return;
+            };
+
+            if let Some(response) = on_connection(request) {
+                let _ = stream.write_serde(&response);
+            }
+        },
+        on_connection_error,
+    )
+}
+
+/// Connects to a TCP server started with `start_ipc_listener_tcp` or `start_ipc_server_tcp`.
+pub fn ipc_client_connect_tcp(addr: impl ToSocketAddrs) -> Result<TcpStream, IpcClientError> {
+    let (addrs, name) = resolve(addr);
+    let connect_error = IpcClientError::connect(&name);
+
+    let connect = || {
+        let stream = TcpStream::connect(&addrs?[..])?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    };
+
+    connect().map_err(connect_error)
+}
+
+/// Resolves an address, and returns the resolved addresses along with a name for them to use in errors.
+fn resolve(addr: impl ToSocketAddrs) -> (io::Result<Vec<SocketAddr>>, String) {
+    let addrs = addr.to_socket_addrs().map(Vec::from_iter);
+
+    let name = match &addrs {
+        Ok(addrs) => addrs
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(_) => "<unresolved address>".to_string(),
+    };
+
+    (addrs, name)
+}
+
+/// Like `send_ipc_message`, but sends the message over TCP.
+pub fn send_ipc_message_tcp<TRequest: Serialize>(
+    addr: impl ToSocketAddrs,
+    request: &TRequest,
+) -> Result<(), IpcClientError> {
+    let mut stream = ipc_client_connect_tcp(addr)?;
+    stream.write_serde(request)?;
+    Ok(())
+}
+
+/// Like `send_ipc_query`, but sends the request and reads the response over TCP.
+pub fn send_ipc_query_tcp<TRequest: Serialize, TResponse: DeserializeOwned>(
+    addr: impl ToSocketAddrs,
+    request: &TRequest,
+) -> Result<TResponse, IpcClientError> {
+    let mut stream = ipc_client_connect_tcp(addr)?;
+    stream.write_serde(request)?;
+    let response: TResponse = stream.read_serde()?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_query_and_message() {
+        // Find a free port, then release it for the server to bind.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+
+        let handle = start_ipc_server_tcp(
+            addr,
+            move |request: String| {
+                sender.lock().unwrap().send(request.clone()).unwrap();
+                Some(request.len())
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let name = format!("ipc server '{addr}'");
+        assert_eq!(handle.thread().name(), Some(name.as_str()));
+
+        let len: usize = send_ipc_query_tcp(addr, &"hello".to_string()).unwrap();
+        assert_eq!(len, 5);
+
+        send_ipc_message_tcp(addr, &"bye".to_string()).unwrap();
+        assert_eq!(receiver.recv().unwrap(), "hello");
+        assert_eq!(receiver.recv().unwrap(), "bye");
+    }
+}
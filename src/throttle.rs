@@ -0,0 +1,136 @@
+use crate::{send_ipc_message, send_ipc_query, IpcClientError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A client that caps the number of requests in flight to a socket at once.
+///
+/// Callers beyond the limit block until an earlier request completes, which keeps many threads from
+/// overwhelming a single-threaded server. Cloning the client is cheap, and clones share the same limit.
+#[derive(Clone)]
+pub struct ThrottledClient {
+    socket: String,
+    slots: Arc<Slots>,
+}
+
+struct Slots {
+    max: usize,
+    in_flight: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl ThrottledClient {
+    /// Creates a client that allows at most `max_in_flight` concurrent requests to the given socket.
+    pub fn new(socket: impl Into<String>, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be at least 1");
+
+        Self {
+            socket: socket.into(),
+            slots: Arc::new(Slots {
+                max: max_in_flight,
+                in_flight: Mutex::new(0),
+                freed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Like `send_ipc_query`, but waits for a free slot first.
+    pub fn query<TRequest: Serialize, TResponse: DeserializeOwned>(
+        &self,
+        request: &TRequest,
+    ) -> Result<TResponse, IpcClientError> {
+        let _slot = self.slots.acquire();
+
+        send_ipc_query(&self.socket, request)
+    }
+
+    /// Like `send_ipc_message`, but waits for a free slot first.
+    pub fn send<TRequest: Serialize>(&self, request: &TRequest) -> Result<(), IpcClientError> {
+        let _slot = self.slots.acquire();
+
+        send_ipc_message(&self.socket, request)
+    }
+
+    /// Gets the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        *self.slots.in_flight.lock().unwrap()
+    }
+}
+
+impl Slots {
+    /// Blocks until a slot is free, then takes it until the returned guard is dropped.
+    fn acquire(&self) -> SlotGuard<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+
+        while *in_flight >= self.max {
+            in_flight = self.freed.wait(in_flight).unwrap();
+        }
+
+        *in_flight += 1;
+
+        SlotGuard { slots: self }
+    }
+}
+
+/// Frees its slot when dropped, even if the request panics.
+struct SlotGuard<'a> {
+    slots: &'a Slots,
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        *self.slots.in_flight.lock().unwrap() -= 1;
+        self.slots.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{start_ipc_listener, SocketExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_throttled_client_limits_in_flight() {
+        static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+        static MAX_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+        let socket = "/tmp/ipc-util-test-throttle.sock";
+
+        // Handle each connection on its own thread, so only the client limits concurrency.
+        start_ipc_listener(
+            socket,
+            |mut stream| {
+                std::thread::spawn(move || {
+                    let active = ACTIVE.fetch_add(1, Ordering::SeqCst) + 1;
+                    MAX_ACTIVE.fetch_max(active, Ordering::SeqCst);
+
+                    let request: u32 = stream.read_serde().unwrap();
+                    std::thread::sleep(Duration::from_millis(20));
+
+                    ACTIVE.fetch_sub(1, Ordering::SeqCst);
+                    stream.write_serde(&request).unwrap();
+                });
+            },
+            None,
+        )
+        .expect("Failed to start server");
+
+        let client = ThrottledClient::new(socket, 2);
+
+        let threads = (0..6)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || client.query::<u32, u32>(&i).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        for (i, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap(), i as u32);
+        }
+
+        assert!(MAX_ACTIVE.load(Ordering::SeqCst) <= 2);
+        assert_eq!(client.in_flight(), 0);
+    }
+}
@@ -1,7 +1,52 @@
+use crate::is_namespaced;
+use crate::LocalSocketExt;
+use interprocess::local_socket::LocalSocketStream;
 use std::env;
-use sysinfo::{System, SystemExt};
+use std::path::PathBuf;
+use std::sync::Mutex;
+#[cfg(feature = "sysinfo")]
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+
+/// The sockets this process has connected to just to wake up their accept loops, once per pending connection.
+static POKES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Connects to a socket of this process to wake up its accept loop, such as to let it notice a shutdown.
+///
+/// The connection is recorded so that `take_poke` can drop it when it's accepted, which keeps it from reaching
+/// a handler even if the socket has been bound again by another server in the meantime.
+pub fn poke(name: &str) {
+    lock_pokes().push(name.to_string());
+
+    if LocalSocketStream::connect(name).is_err() {
+        forget_poke(name);
+    }
+}
+
+/// Checks whether an accepted connection is a pending `poke` of the socket, and if so, marks it as handled.
+///
+/// A poke is recognized by the peer being this process, so this relies on `peer_pid` being supported.
+pub fn take_poke(stream: &LocalSocketStream, name: &str) -> bool {
+    stream.is_self_connection() && forget_poke(name)
+}
+
+fn forget_poke(name: &str) -> bool {
+    let mut pokes = lock_pokes();
+
+    match pokes.iter().position(|poked| poked == name) {
+        Some(index) => {
+            pokes.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn lock_pokes() -> std::sync::MutexGuard<'static, Vec<String>> {
+    POKES.lock().unwrap_or_else(|e| e.into_inner())
+}
 
 /// Gets the instance count of the current process name.
+#[cfg(feature = "sysinfo")]
 pub fn current_process_instance_count() -> usize {
     let current_process_name = env::current_exe()
         .unwrap()
@@ -17,3 +62,1209 @@ pub fn current_process_instance_count() -> usize {
         .processes_by_exact_name(&current_process_name)
         .count()
 }
+
+/// Without the `sysinfo` feature, other processes can't be listed, so only the current process is counted.
+#[cfg(not(feature = "sysinfo"))]
+pub fn current_process_instance_count() -> usize {
+    1
+}
+
+/// Gets the executable name of the process with the given id, if it exists.
+///
+/// This prefers the file name of the executable path, since on Linux the process name is truncated to 15 characters.
+#[cfg(feature = "sysinfo")]
+pub fn process_name(pid: u32) -> Option<String> {
+    let pid = Pid::from_u32(pid);
+
+    let mut system = System::new();
+    system.refresh_process(pid);
+
+    let process = system.process(pid)?;
+
+    let name = match process.exe().file_name() {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None => process.name().to_string(),
+    };
+
+    Some(name)
+}
+
+/// Without the `sysinfo` feature, process names can't be resolved.
+#[cfg(not(feature = "sysinfo"))]
+pub fn process_name(_pid: u32) -> Option<String> {
+    None
+}
+
+/// Reports a warning when no `on_warning` callback was given.
+///
+/// With the `tracing` feature, warnings are emitted as `tracing` events. Otherwise they are printed to stderr.
+pub fn default_warning(message: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("{message}");
+
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("WARNING: {message}");
+}
+
+/// Gets the path of the lock file used to guard the given socket.
+///
+/// Path-based sockets get a `.lock` file next to the socket file, while namespaced sockets get one in the
+/// system temporary directory.
+pub fn lock_file_path(socket: &str) -> PathBuf {
+    instance_file_path(socket, "lock")
+}
+
+/// Gets the path of the PID file used to guard the given socket, which is placed like the lock file.
+pub fn pid_file_path(socket: &str) -> PathBuf {
+    instance_file_path(socket, "pid")
+}
+
+fn instance_file_path(socket: &str, extension: &str) -> PathBuf {
+    if is_namespaced(socket) {
+        let name = socket.strip_prefix('@').unwrap_or(socket);
+        env::temp_dir().join(format!("{name}.{extension}"))
+    } else {
+        PathBuf::from(format!("{socket}.{extension}"))
+    }
+}
+
+/// Checks whether a process with the given id is running.
+#[cfg(feature = "sysinfo")]
+pub fn is_process_running(pid: u32) -> bool {
+    let mut system = System::new();
+    system.refresh_process(Pid::from_u32(pid))
+}
+
+/// Checks whether a process with the given id is running, by sending it the null signal.
+///
+/// Fails with `EPERM` if the process exists but belongs to another user, so that counts as running too.
+#[cfg(all(unix, not(feature = "sysinfo")))]
+pub fn is_process_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Checks whether a process with the given id is running, by opening it and checking that it hasn't exited.
+#[cfg(all(windows, not(feature = "sysinfo")))]
+pub fn is_process_running(pid: u32) -> bool {
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+
+        if process.is_null() {
+            return false;
+        }
+
+        let mut exit_code = 0;
+        let running = GetExitCodeProcess(process, &mut exit_code) != 0 && exit_code == STILL_ACTIVE;
+        CloseHandle(process);
+
+        running
+    }
+}
+
+/// Checks whether the process on the other end of the stream is running as the same user as the current process.
+///
+/// Returns `false` if the peer process or either process token can't be queried.
+#[cfg(windows)]
+pub fn is_peer_current_user(stream: &interprocess::local_socket::LocalSocketStream) -> bool {
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+    use winapi::um::{handleapi::CloseHandle, securitybaseapi::EqualSid};
+
+    let Ok(pid) = stream.peer_pid() else {
+        return false;
+    };
+
+    unsafe {
+        let Some(own_user) = process_token_user(GetCurrentProcess()) else {
+            return false;
+        };
+
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return false;
+        }
+
+        let peer_user = process_token_user(process);
+        CloseHandle(process);
+
+        match peer_user {
+            Some(peer_user) => EqualSid(own_user.sid(), peer_user.sid()) != 0,
+            None => false,
+        }
+    }
+}
+
+/// The `TOKEN_USER` information of a process token, stored in an 8-byte aligned buffer.
+#[cfg(windows)]
+struct TokenUser(Vec<u64>);
+
+#[cfg(windows)]
+impl TokenUser {
+    fn sid(&self) -> winapi::um::winnt::PSID {
+        let token_user = self.0.as_ptr() as *const winapi::um::winnt::TOKEN_USER;
+        unsafe { (*token_user).User.Sid }
+    }
+}
+
+#[cfg(windows)]
+unsafe fn process_token_user(process: winapi::um::winnt::HANDLE) -> Option<TokenUser> {
+    use std::ptr;
+    use winapi::um::processthreadsapi::OpenProcessToken;
+    use winapi::um::winnt::{TokenUser as TokenUserClass, TOKEN_QUERY};
+    use winapi::um::{handleapi::CloseHandle, securitybaseapi::GetTokenInformation};
+
+    let mut token = ptr::null_mut();
+    if OpenProcessToken(process, TOKEN_QUERY, &mut token) == 0 {
+        return None;
+    }
+
+    let mut len = 0;
+    GetTokenInformation(token, TokenUserClass, ptr::null_mut(), 0, &mut len);
+
+    let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+    let ok = GetTokenInformation(
+        token,
+        TokenUserClass,
+        buffer.as_mut_ptr().cast(),
+        len,
+        &mut len,
+    );
+    CloseHandle(token);
+
+    (ok != 0).then_some(TokenUser(buffer))
+}
+
+/// How a `PipeListener` creates its pipe instances. See `IpcServerBuilder::message_mode` and
+/// `IpcServerBuilder::restrict_to_current_user`.
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PipeOptions {
+    pub message_mode: bool,
+    pub restrict_to_current_user: bool,
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+}
+
+/// A named pipe listener that creates its pipe instances itself, since `interprocess` always creates byte-mode pipes
+/// with the default security descriptor.
+#[cfg(windows)]
+pub(crate) struct PipeListener {
+    /// The full path of the pipe, nul-terminated.
+    path: Vec<u16>,
+    options: PipeOptions,
+    security: Option<SecurityDescriptor>,
+    nonblocking: std::sync::atomic::AtomicBool,
+    /// The instance waiting for the next client, which is replaced with a new one once a client connects to it.
+    instance: Mutex<std::os::windows::io::OwnedHandle>,
+}
+
+#[cfg(windows)]
+impl PipeListener {
+    /// Binds a named pipe with the given name, which is prefixed with `\\.\pipe\` like a local socket name.
+    ///
+    /// Like binding a local socket, this fails with `PermissionDenied` if a pipe with the name already exists.
+    pub fn bind(name: &str, options: PipeOptions) -> std::io::Result<Self> {
+        use std::os::windows::ffi::OsStrExt;
+
+        let path: Vec<u16> = std::ffi::OsStr::new(&format!(r"\\.\pipe\{name}"))
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+
+        let security = match options.restrict_to_current_user {
+            true => Some(SecurityDescriptor::current_user_only()?),
+            false => None,
+        };
+
+        let instance = create_pipe_instance(&path, &options, security.as_ref(), true, false)?;
+
+        Ok(Self {
+            path,
+            options,
+            security,
+            nonblocking: std::sync::atomic::AtomicBool::new(false),
+            instance: Mutex::new(instance),
+        })
+    }
+
+    /// Waits for a client to connect, and returns the connection as a local socket stream.
+    ///
+    /// When the listener is nonblocking, this fails with `WouldBlock` if no client is waiting.
+    pub fn accept(&self) -> std::io::Result<LocalSocketStream> {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle, IntoRawHandle};
+        use std::sync::atomic::Ordering;
+        use winapi::shared::winerror::{ERROR_NO_DATA, ERROR_PIPE_CONNECTED, ERROR_PIPE_LISTENING};
+        use winapi::um::namedpipeapi::{ConnectNamedPipe, DisconnectNamedPipe};
+
+        let mut instance = self.instance.lock().expect("pipe instance lock poisoned");
+        let handle = instance.as_raw_handle().cast();
+
+        let nonblocking = self.nonblocking.load(Ordering::Relaxed);
+
+        // In nonblocking mode, success only means the instance started listening, and a client that connected since
+        // is reported with `ERROR_PIPE_CONNECTED`.
+        if unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) } != 0 {
+            if nonblocking {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+        } else {
+            let error = std::io::Error::last_os_error();
+
+            match error.raw_os_error().map(|code| code as u32) {
+                Some(ERROR_PIPE_CONNECTED) => {}
+                Some(ERROR_PIPE_LISTENING) => return Err(std::io::ErrorKind::WouldBlock.into()),
+                // The client already closed its end, so the instance is disconnected to wait for the next one.
+                Some(ERROR_NO_DATA) => {
+                    unsafe { DisconnectNamedPipe(handle) };
+                    return Err(error);
+                }
+                _ => return Err(error),
+            }
+        }
+
+        let next = create_pipe_instance(
+            &self.path,
+            &self.options,
+            self.security.as_ref(),
+            false,
+            nonblocking,
+        )?;
+        let connected = std::mem::replace(&mut *instance, next);
+
+        // SAFETY: `into_raw_handle` gives up ownership of a valid, connected duplex pipe handle,
+        // which is exactly what a Windows local socket stream wraps.
+        Ok(unsafe { LocalSocketStream::from_raw_handle(connected.into_raw_handle()) })
+    }
+
+    /// Sets whether `accept` waits for a client, for the waiting instance and the ones created after it.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+        use std::sync::atomic::Ordering;
+        use winapi::um::namedpipeapi::SetNamedPipeHandleState;
+        use winapi::um::winbase::{PIPE_NOWAIT, PIPE_READMODE_BYTE, PIPE_WAIT};
+
+        let instance = self.instance.lock().expect("pipe instance lock poisoned");
+        let mut mode = PIPE_READMODE_BYTE | if nonblocking { PIPE_NOWAIT } else { PIPE_WAIT };
+
+        let ok = unsafe {
+            SetNamedPipeHandleState(
+                instance.as_raw_handle().cast(),
+                &mut mode,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Creates an instance of a named pipe, failing if it's the first one and the pipe already exists.
+///
+/// Message-mode pipes keep writing messages, but are read as bytes, since reading part of a message in message mode
+/// fails with `ERROR_MORE_DATA` rather than leaving the rest for the next read. The buffer sizes are passed to the
+/// system as hints for the server's end of the instance.
+#[cfg(windows)]
+fn create_pipe_instance(
+    path: &[u16],
+    options: &PipeOptions,
+    security: Option<&SecurityDescriptor>,
+    first: bool,
+    nonblocking: bool,
+) -> std::io::Result<std::os::windows::io::OwnedHandle> {
+    use std::os::windows::io::FromRawHandle;
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+    use winapi::um::namedpipeapi::CreateNamedPipeW;
+    use winapi::um::winbase::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX, PIPE_NOWAIT, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_TYPE_MESSAGE, PIPE_UNLIMITED_INSTANCES,
+    };
+
+    let mut open_mode = PIPE_ACCESS_DUPLEX;
+    if first {
+        open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+    }
+
+    let mut pipe_mode = PIPE_READMODE_BYTE | PIPE_REJECT_REMOTE_CLIENTS;
+    pipe_mode |= if options.message_mode {
+        PIPE_TYPE_MESSAGE
+    } else {
+        PIPE_TYPE_BYTE
+    };
+    if nonblocking {
+        pipe_mode |= PIPE_NOWAIT;
+    }
+
+    // The same defaults `interprocess` uses for its own pipes.
+    let buffer_size =
+        |size: Option<usize>| size.map_or(512, |size| size.min(u32::MAX as usize) as u32);
+
+    let mut attributes = security.map(|security| SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: security.0,
+        bInheritHandle: 0,
+    });
+
+    let handle = unsafe {
+        CreateNamedPipeW(
+            path.as_ptr(),
+            open_mode,
+            pipe_mode,
+            PIPE_UNLIMITED_INSTANCES,
+            buffer_size(options.send_buffer_size),
+            buffer_size(options.recv_buffer_size),
+            50,
+            attributes
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |attributes| attributes),
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: the handle was just created, so nothing else owns it.
+    Ok(unsafe { std::os::windows::io::OwnedHandle::from_raw_handle(handle.cast()) })
+}
+
+/// A self-relative security descriptor allocated by the system.
+#[cfg(windows)]
+struct SecurityDescriptor(winapi::um::winnt::PSECURITY_DESCRIPTOR);
+
+// SAFETY: the descriptor is never modified once it's created, and is only freed when dropped.
+#[cfg(windows)]
+unsafe impl Send for SecurityDescriptor {}
+#[cfg(windows)]
+unsafe impl Sync for SecurityDescriptor {}
+
+#[cfg(windows)]
+impl SecurityDescriptor {
+    /// Creates a descriptor whose protected DACL grants full control to the user the current process runs as,
+    /// and nothing to anyone else.
+    fn current_user_only() -> std::io::Result<Self> {
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        use std::ptr;
+        use winapi::shared::sddl::{
+            ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+            SDDL_REVISION_1,
+        };
+        use winapi::um::processthreadsapi::GetCurrentProcess;
+        use winapi::um::winbase::LocalFree;
+
+        let user = unsafe { process_token_user(GetCurrentProcess()) }
+            .ok_or_else(std::io::Error::last_os_error)?;
+
+        let mut sid = ptr::null_mut();
+        if unsafe { ConvertSidToStringSidW(user.sid(), &mut sid) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let sid_string = unsafe {
+            let len = (0..).take_while(|&i| *sid.add(i) != 0).count();
+            std::ffi::OsString::from_wide(std::slice::from_raw_parts(sid, len))
+        };
+        unsafe { LocalFree(sid.cast()) };
+
+        let mut sddl = std::ffi::OsString::from("D:P(A;;GA;;;");
+        sddl.push(sid_string);
+        sddl.push(")");
+        let sddl: Vec<u16> = sddl.encode_wide().chain(Some(0)).collect();
+
+        let mut descriptor = ptr::null_mut();
+        let ok = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1.into(),
+                &mut descriptor,
+                ptr::null_mut(),
+            )
+        };
+
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self(descriptor))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for SecurityDescriptor {
+    fn drop(&mut self) {
+        unsafe { winapi::um::winbase::LocalFree(self.0) };
+    }
+}
+
+/// Gets the receive and send buffer sizes of a named pipe, from the point of view of the given end of it.
+#[cfg(windows)]
+pub fn pipe_buffer_sizes(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<(usize, usize)> {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use winapi::um::namedpipeapi::GetNamedPipeInfo;
+    use winapi::um::winbase::PIPE_SERVER_END;
+
+    let (mut flags, mut out_size, mut in_size) = (0, 0, 0);
+    let ok = unsafe {
+        GetNamedPipeInfo(
+            stream.as_raw_handle().cast(),
+            &mut flags,
+            &mut out_size,
+            &mut in_size,
+            ptr::null_mut(),
+        )
+    };
+
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // The sizes are those of the server's end, so the client receives what the server sends and the other way round.
+    if flags & PIPE_SERVER_END != 0 {
+        Ok((in_size as usize, out_size as usize))
+    } else {
+        Ok((out_size as usize, in_size as usize))
+    }
+}
+
+/// Sets the read timeout of a local socket stream, which `interprocess` doesn't expose directly.
+#[cfg(unix)]
+pub fn set_read_timeout(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<()> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    // SAFETY: the descriptor stays open for as long as `stream` is borrowed,
+    // and `ManuallyDrop` keeps the temporary `UnixStream` from closing it.
+    let socket = ManuallyDrop::new(unsafe { UnixStream::from_raw_fd(stream.as_raw_fd()) });
+
+    socket.set_read_timeout(timeout)
+}
+
+/// Sets a buffer size option, such as `SO_RCVBUF`, on a local socket stream.
+#[cfg(unix)]
+pub fn set_buffer_size(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    option: libc::c_int,
+    size: usize,
+) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+
+    let result = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            (&size as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Gets a buffer size option, such as `SO_RCVBUF`, of a local socket stream.
+#[cfg(unix)]
+pub fn buffer_size(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    option: libc::c_int,
+) -> std::io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut size: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            (&mut size as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(size as usize)
+}
+
+/// Gets the process id of the peer of a local socket stream.
+///
+/// `interprocess` can only tell on Linux, Android, Haiku and Windows, so the BSDs and Apple platforms read it from
+/// their own socket options instead.
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+pub fn peer_pid(stream: &interprocess::local_socket::LocalSocketStream) -> std::io::Result<u32> {
+    stream.peer_pid()
+}
+
+/// Gets the process id of the peer of a local socket stream.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn peer_pid(stream: &interprocess::local_socket::LocalSocketStream) -> std::io::Result<u32> {
+    let pid: libc::pid_t = peer_socket_option(stream, libc::SOL_LOCAL, libc::LOCAL_PEERPID)?;
+
+    Ok(pid as u32)
+}
+
+/// Gets the process id of the peer of a local socket stream.
+///
+/// The process id is only filled in since FreeBSD 13, so older versions fail with `Unsupported`.
+#[cfg(target_os = "freebsd")]
+pub fn peer_pid(stream: &interprocess::local_socket::LocalSocketStream) -> std::io::Result<u32> {
+    let cred: libc::xucred = peer_socket_option(stream, libc::SOL_LOCAL, libc::LOCAL_PEERCRED)?;
+    let pid = unsafe { cred.cr_pid__c_anonymous_union.cr_pid };
+
+    if cred.cr_version != libc::XUCRED_VERSION || pid <= 0 {
+        return Err(std::io::ErrorKind::Unsupported.into());
+    }
+
+    Ok(pid as u32)
+}
+
+/// Gets the process id of the peer of a local socket stream.
+#[cfg(target_os = "openbsd")]
+pub fn peer_pid(stream: &interprocess::local_socket::LocalSocketStream) -> std::io::Result<u32> {
+    let cred: libc::sockpeercred = peer_socket_option(stream, libc::SOL_SOCKET, libc::SO_PEERCRED)?;
+
+    Ok(cred.pid as u32)
+}
+
+/// Gets the process id of the peer of a local socket stream.
+#[cfg(target_os = "netbsd")]
+pub fn peer_pid(stream: &interprocess::local_socket::LocalSocketStream) -> std::io::Result<u32> {
+    // Local socket options are read at level 0, which NetBSD has no constant for.
+    let id: libc::unpcbid = peer_socket_option(stream, 0, libc::LOCAL_PEEREID)?;
+
+    Ok(id.unp_pid as u32)
+}
+
+/// Reads a socket option describing the peer of a local socket stream, for a plain C struct or integer `T`.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+fn peer_socket_option<T>(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    level: libc::c_int,
+    option: libc::c_int,
+) -> std::io::Result<T> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: `T` is only ever a C integer or struct, for which all zeroes is a valid value.
+    let mut value: T = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<T>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            level,
+            option,
+            (&mut value as *mut T).cast(),
+            &mut len,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(value)
+}
+
+/// Gets the effective user and group ids of the peer of a local socket stream.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn peer_credentials(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<(u32, u32)> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok((cred.uid, cred.gid))
+}
+
+/// Gets the effective user and group ids of the peer of a local socket stream.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+pub fn peer_credentials(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<(u32, u32)> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok((uid, gid))
+}
+
+/// Gets the effective user and group ids of the peer of a local socket stream, which this platform can't tell.
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+))]
+pub fn peer_credentials(
+    _stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<(u32, u32)> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Creates a new handle to the same local socket stream, so it can be read on one thread while written on another.
+#[cfg(unix)]
+pub fn try_clone(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<interprocess::local_socket::LocalSocketStream> {
+    use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd};
+
+    // SAFETY: the descriptor stays open for as long as `stream` is borrowed.
+    let fd = unsafe { BorrowedFd::borrow_raw(stream.as_raw_fd()) }.try_clone_to_owned()?;
+
+    // SAFETY: the duplicate refers to the same connected stream socket, and is owned by nothing else.
+    Ok(unsafe { interprocess::local_socket::LocalSocketStream::from_raw_fd(fd.into_raw_fd()) })
+}
+
+/// Shuts down the write half of a local socket stream, which `interprocess` doesn't expose directly.
+#[cfg(unix)]
+pub fn shutdown_write(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<()> {
+    shutdown(stream, std::net::Shutdown::Write)
+}
+
+/// Shuts down either or both halves of a local socket stream, waking up any thread blocked on them.
+#[cfg(unix)]
+pub fn shutdown(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    how: std::net::Shutdown,
+) -> std::io::Result<()> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    // SAFETY: the descriptor stays open for as long as `stream` is borrowed,
+    // and `ManuallyDrop` keeps the temporary `UnixStream` from closing it.
+    let socket = ManuallyDrop::new(unsafe { UnixStream::from_raw_fd(stream.as_raw_fd()) });
+
+    socket.shutdown(how)
+}
+
+/// Checks whether reading from a local socket stream would return immediately, without consuming anything.
+///
+/// This is the case when data is waiting to be read, and also once the peer has closed the connection.
+#[cfg(unix)]
+pub fn has_pending_data(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut byte = 0u8;
+
+    loop {
+        // `MSG_DONTWAIT` makes only this call nonblocking, leaving the stream's own mode alone.
+        let result = unsafe {
+            libc::recv(
+                stream.as_raw_fd(),
+                (&mut byte as *mut u8).cast(),
+                1,
+                libc::MSG_PEEK | libc::MSG_DONTWAIT,
+            )
+        };
+
+        if result >= 0 {
+            return Ok(true);
+        }
+
+        let error = std::io::Error::last_os_error();
+
+        match error.kind() {
+            std::io::ErrorKind::WouldBlock => return Ok(false),
+            std::io::ErrorKind::Interrupted => continue,
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Checks whether reading from a local socket stream would return immediately, without consuming anything.
+///
+/// This is the case when data is waiting in the pipe, and also once the peer has closed it.
+#[cfg(windows)]
+pub fn has_pending_data(
+    stream: &interprocess::local_socket::LocalSocketStream,
+) -> std::io::Result<bool> {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use winapi::shared::winerror::ERROR_BROKEN_PIPE;
+    use winapi::um::namedpipeapi::PeekNamedPipe;
+
+    let mut available = 0;
+    let ok = unsafe {
+        PeekNamedPipe(
+            stream.as_raw_handle().cast(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            &mut available,
+            ptr::null_mut(),
+        )
+    };
+
+    if ok == 0 {
+        let error = std::io::Error::last_os_error();
+
+        return match error.raw_os_error() {
+            Some(code) if code == ERROR_BROKEN_PIPE as i32 => Ok(true),
+            _ => Err(error),
+        };
+    }
+
+    Ok(available > 0)
+}
+
+/// The device and inode numbers of a file, which tell it apart from another file created at the same path.
+#[cfg(unix)]
+pub type FileId = (u64, u64);
+
+/// How often a server set to restart checks that its socket file is still there while waiting for connections.
+#[cfg(unix)]
+const SOCKET_FILE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Gets the identity of the file at the path.
+#[cfg(unix)]
+pub fn file_id(path: &str) -> std::io::Result<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+/// Waits until the listener has a connection to accept, checking every so often that the socket file at the path is
+/// still the one with the given identity.
+///
+/// Fails with `NotFound` once the file is deleted or replaced, since clients can no longer reach the listener.
+/// A nonblocking listener is only checked once, without waiting.
+#[cfg(unix)]
+pub fn wait_for_connection(
+    listener: &std::os::unix::net::UnixListener,
+    path: &str,
+    id: FileId,
+) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = listener.as_raw_fd();
+
+    // SAFETY: the descriptor belongs to the listener, which outlives this call.
+    let nonblocking = unsafe { libc::fcntl(fd, libc::F_GETFL) } & libc::O_NONBLOCK != 0;
+
+    loop {
+        if file_id(path).ok() != Some(id) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Socket file '{path}' was deleted or replaced"),
+            ));
+        }
+
+        if nonblocking {
+            return Ok(());
+        }
+
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: `poll_fd` is a single valid `pollfd`, and the descriptor belongs to the listener.
+        let ready = unsafe {
+            libc::poll(
+                &mut poll_fd,
+                1,
+                SOCKET_FILE_CHECK_INTERVAL.as_millis() as libc::c_int,
+            )
+        };
+
+        match ready {
+            0 => continue,
+            -1 => {
+                let error = std::io::Error::last_os_error();
+
+                if error.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Sets the write timeout of a local socket stream, which `interprocess` doesn't expose directly.
+#[cfg(unix)]
+pub fn set_write_timeout(
+    stream: &interprocess::local_socket::LocalSocketStream,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<()> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    // SAFETY: the descriptor stays open for as long as `stream` is borrowed,
+    // and `ManuallyDrop` keeps the temporary `UnixStream` from closing it.
+    let socket = ManuallyDrop::new(unsafe { UnixStream::from_raw_fd(stream.as_raw_fd()) });
+
+    socket.set_write_timeout(timeout)
+}
+
+/// A stream whose reads and writes fail with `TimedOut` if they don't finish before a deadline.
+///
+/// The stream's timeouts are shortened before every call, so a peer can't extend the deadline
+/// by trickling in one byte at a time.
+#[cfg(unix)]
+pub struct DeadlineStream<'a> {
+    stream: &'a mut interprocess::local_socket::LocalSocketStream,
+    deadline: std::time::Instant,
+}
+
+#[cfg(unix)]
+impl<'a> DeadlineStream<'a> {
+    pub fn new(
+        stream: &'a mut interprocess::local_socket::LocalSocketStream,
+        deadline: std::time::Instant,
+    ) -> Self {
+        Self { stream, deadline }
+    }
+}
+
+/// Connects to a local socket, giving up with `TimedOut` once the deadline passes.
+///
+/// The socket connects in nonblocking mode, and is polled until it's writable if the connection is in progress.
+/// Linux reports a full accept backlog as `WouldBlock` rather than in progress, so that is retried until the deadline.
+#[cfg(unix)]
+pub fn connect_until(
+    name: &str,
+    deadline: std::time::Instant,
+) -> std::io::Result<interprocess::local_socket::LocalSocketStream> {
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+
+    let (addr, addr_len) = unix_socket_addr(name)?;
+
+    // SAFETY: `socket` returns a new descriptor or -1, and the descriptor is owned from here on.
+    let fd = match unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) } {
+        -1 => return Err(Error::last_os_error()),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) },
+    };
+
+    set_fd_flags(fd.as_raw_fd(), libc::O_NONBLOCK, true)?;
+
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    loop {
+        let result = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                (&addr as *const libc::sockaddr_un).cast(),
+                addr_len,
+            )
+        };
+
+        if result == 0 {
+            break;
+        }
+
+        let error = Error::last_os_error();
+
+        match error.raw_os_error() {
+            Some(libc::EINPROGRESS) => {
+                wait_until_writable(fd.as_raw_fd(), deadline)?;
+                break;
+            }
+            Some(libc::EINTR) => continue,
+            _ if error.kind() == ErrorKind::WouldBlock => {
+                let remaining = time_until(deadline)?;
+                std::thread::sleep(remaining.min(std::time::Duration::from_millis(10)));
+            }
+            _ => return Err(error),
+        }
+    }
+
+    set_fd_flags(fd.as_raw_fd(), libc::O_NONBLOCK, false)?;
+
+    // SAFETY: the descriptor is a connected Unix stream socket, which is what a local socket stream wraps on Unix.
+    Ok(unsafe { interprocess::local_socket::LocalSocketStream::from_raw_fd(fd.into_raw_fd()) })
+}
+
+/// Builds the address of a local socket name, which is in the abstract namespace if it starts with `@` on Linux.
+#[cfg(unix)]
+fn unix_socket_addr(name: &str) -> std::io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    // Abstract names start with a nul byte instead, and aren't nul terminated.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let (bytes, terminator) = match name.strip_prefix('@') {
+        Some(name) => ([&[0][..], name.as_bytes()].concat(), 0),
+        None => (name.as_bytes().to_vec(), 1),
+    };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let (bytes, terminator) = (name.as_bytes().to_vec(), 1);
+
+    if bytes.len() + terminator > addr.sun_path.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "socket name is too long",
+        ));
+    }
+
+    for (dst, src) in addr.sun_path.iter_mut().zip(&bytes) {
+        *dst = *src as libc::c_char;
+    }
+
+    let path_offset = std::mem::size_of_val(&addr) - std::mem::size_of_val(&addr.sun_path);
+    let len = path_offset + bytes.len() + terminator;
+
+    Ok((addr, len as libc::socklen_t))
+}
+
+/// Sets or clears a file status flag, such as `O_NONBLOCK`, on a descriptor.
+#[cfg(unix)]
+fn set_fd_flags(fd: libc::c_int, flag: libc::c_int, set: bool) -> std::io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+
+    if flags == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let flags = if set { flags | flag } else { flags & !flag };
+
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Waits for a nonblocking connect to finish, and returns its result.
+#[cfg(unix)]
+fn wait_until_writable(fd: libc::c_int, deadline: std::time::Instant) -> std::io::Result<()> {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+
+    loop {
+        let timeout = time_until(deadline)?
+            .as_millis()
+            .clamp(1, libc::c_int::MAX as u128);
+
+        match unsafe { libc::poll(&mut poll_fd, 1, timeout as libc::c_int) } {
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            -1 => return Err(std::io::Error::last_os_error()),
+            0 => {}
+            _ => break,
+        }
+    }
+
+    let mut error: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            (&mut error as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+
+    match (result, error) {
+        (-1, _) => Err(std::io::Error::last_os_error()),
+        (_, 0) => Ok(()),
+        (_, error) => Err(std::io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// Connects to a named pipe, waiting with `WaitNamedPipeW` while every instance of it is busy, and giving up with
+/// `TimedOut` once the deadline passes.
+#[cfg(windows)]
+pub fn connect_until(
+    name: &str,
+    deadline: std::time::Instant,
+) -> std::io::Result<interprocess::local_socket::LocalSocketStream> {
+    use std::io::Error;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use std::ptr;
+    use winapi::shared::winerror::{ERROR_PIPE_BUSY, ERROR_SEM_TIMEOUT};
+    use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::namedpipeapi::WaitNamedPipeW;
+    use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE};
+
+    let path = std::ffi::OsString::from(format!(r"\\.\pipe\{name}"))
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+
+    loop {
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                0,
+                ptr::null_mut(),
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+
+        if handle != INVALID_HANDLE_VALUE {
+            // SAFETY: the handle is a newly opened, connected duplex pipe handle, which is what a local socket
+            // stream wraps on Windows.
+            return Ok(unsafe {
+                interprocess::local_socket::LocalSocketStream::from_raw_handle(handle.cast())
+            });
+        }
+
+        let error = Error::last_os_error();
+
+        if error.raw_os_error() != Some(ERROR_PIPE_BUSY as i32) {
+            return Err(error);
+        }
+
+        let timeout = time_until(deadline)?
+            .as_millis()
+            .clamp(1, u32::MAX as u128 - 1);
+
+        if unsafe { WaitNamedPipeW(path.as_ptr(), timeout as u32) } == 0 {
+            let error = Error::last_os_error();
+
+            // The pipe may have gone away, which the next attempt to open it reports.
+            if error.raw_os_error() == Some(ERROR_SEM_TIMEOUT as i32) {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+        }
+    }
+}
+
+/// Gets the time left until the deadline, failing with `TimedOut` if it has passed.
+pub fn time_until(deadline: std::time::Instant) -> std::io::Result<std::time::Duration> {
+    deadline
+        .checked_duration_since(std::time::Instant::now())
+        .filter(|remaining| !remaining.is_zero())
+        .ok_or_else(|| std::io::ErrorKind::TimedOut.into())
+}
+
+/// Converts the `WouldBlock` error a socket timeout produces into `TimedOut`.
+#[cfg(unix)]
+fn timed_out<T>(result: std::io::Result<T>) -> std::io::Result<T> {
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+            Err(std::io::ErrorKind::TimedOut.into())
+        }
+        result => result,
+    }
+}
+
+#[cfg(unix)]
+impl std::io::Read for DeadlineStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        set_read_timeout(self.stream, Some(time_until(self.deadline)?))?;
+
+        timed_out(self.stream.read(buf))
+    }
+}
+
+#[cfg(unix)]
+impl std::io::Write for DeadlineStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        set_write_timeout(self.stream, Some(time_until(self.deadline)?))?;
+
+        timed_out(self.stream.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}